@@ -0,0 +1,114 @@
+use anon_core::{interner::Symbol, span::Span};
+
+use crate::{
+    func_decl::FuncDecl,
+    sig::{Constraint, Sig, Type},
+};
+
+/// Class decl, the signatures of its methods and their default equations
+///
+/// ```ignore
+/// class Eq a => Ord a where
+///   compare :: a -> a -> Ordering
+///   max :: a -> a -> a
+///   max x y = if x >= y then x else y
+/// ```
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub struct ClassDecl<M> {
+    pub name: Symbol,
+    /// the type variables after the name, e.g. `a` in `class Show a`
+    pub params: Vec<Symbol>,
+    /// the constraints before `=>`, every instance must also be an instance of them
+    pub superclasses: Vec<Constraint>,
+    /// the method signatures in source order
+    pub methods: Vec<ClassMethod>,
+    /// the default equations, used by the instances not defining the method
+    pub defaults: Vec<FuncDecl<M>>,
+    /// the `--|` doc comments right before the declaration
+    pub doc: Option<Symbol>,
+    pub span: Span,
+}
+
+impl<M> ClassDecl<M> {
+    pub fn new(
+        name: Symbol,
+        params: Vec<Symbol>,
+        superclasses: Vec<Constraint>,
+        doc: Option<Symbol>,
+        span: Span,
+    ) -> Self {
+        Self {
+            name,
+            params,
+            superclasses,
+            methods: Vec::new(),
+            defaults: Vec::new(),
+            doc,
+            span,
+        }
+    }
+
+    /// the signature of the method called `name`
+    pub fn method(&self, name: Symbol) -> Option<&ClassMethod> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+
+    /// the default equations of the method called `name`
+    pub fn default(&self, name: Symbol) -> Option<&FuncDecl<M>> {
+        self.defaults
+            .iter()
+            .find(|default| default.func_name == name)
+    }
+}
+
+/// `show :: a -> String` in a class
+#[derive(Debug, Clone)]
+pub struct ClassMethod {
+    pub name: Symbol,
+    pub sig: Sig,
+    /// the `--|` doc comments right before the signature
+    pub doc: Option<Symbol>,
+    /// from the name to the end of the signature
+    pub span: Span,
+}
+
+/// Instance decl, the methods of a class for some types
+///
+/// ```ignore
+/// instance Show a => Show (Vec a) where
+///   show xs = ...
+/// ```
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub struct InstanceDecl<M> {
+    /// the constraints before `=>`, needed by the methods
+    pub constraints: Vec<Constraint>,
+    pub class: Symbol,
+    /// the types the class is instantiated with, e.g. `Vec a`
+    pub args: Vec<Type>,
+    /// the methods in source order, the class gives the defaults of the others
+    pub methods: Vec<FuncDecl<M>>,
+    /// the `--|` doc comments right before the declaration
+    pub doc: Option<Symbol>,
+    pub span: Span,
+}
+
+impl<M> InstanceDecl<M> {
+    pub fn new(
+        constraints: Vec<Constraint>,
+        class: Symbol,
+        args: Vec<Type>,
+        doc: Option<Symbol>,
+        span: Span,
+    ) -> Self {
+        Self {
+            constraints,
+            class,
+            args,
+            methods: Vec::new(),
+            doc,
+            span,
+        }
+    }
+}
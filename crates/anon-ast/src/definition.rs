@@ -1,10 +1,29 @@
-use crate::func_decl::FuncDecl;
+use anon_core::span::Span;
+
+use crate::{
+    class::{ClassDecl, InstanceDecl},
+    fixity::FixityDecl,
+    func_decl::FuncDecl,
+    module::{Export, Import, ModuleDecl},
+    refine::RefineDecl,
+    type_decl::TypeDecl,
+};
 
 /// Top level definition
 #[non_exhaustive]
 #[derive(Debug)]
 pub enum Definition<M> {
     FuncDecl(FuncDecl<M>),
+    Fixity(FixityDecl),
+    TypeDecl(TypeDecl<M>),
+    Refine(RefineDecl),
+    Module(ModuleDecl),
+    Import(Import),
+    Export(Export),
+    Class(ClassDecl<M>),
+    Instance(InstanceDecl<M>),
+    /// a definition with a syntax error, covers the tokens skipped to recover from it
+    Error(Span),
 }
 
 #[cfg(test)]
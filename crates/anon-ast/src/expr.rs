@@ -1,6 +1,8 @@
 use anon_core::interner::Symbol;
 
-use crate::{literal::Literal, match_arm::MatchArm};
+use crate::{
+    func_decl::FuncDecl, literal::Literal, match_arm::MatchArm, pattern::Pattern,
+};
 
 pub type Sym = Symbol;
 
@@ -14,21 +16,74 @@ pub struct Expr<M> {
 #[derive(Debug, Clone)]
 pub enum ExprKind<M> {
     Object(Sym),
+    /// `F.x`, a name imported from the module `F`, `module` is the alias or path as written
+    Qualified {
+        module: Sym,
+        name: Sym,
+    },
     //  => Apply()
     Application {
-        func: Box<Self>,
-        args: Vec<Self>,
+        func: Box<Expr<M>>,
+        args: Vec<Expr<M>>,
     },
     Literal(Literal),
+    /// `lhs op rhs`, `op` is the operator as written, e.g. `+` or `<+>`
+    Binary {
+        op: Sym,
+        lhs: Box<Expr<M>>,
+        rhs: Box<Expr<M>>,
+    },
+    /// prefix `-`
+    Negate(Box<Expr<M>>),
+    /// `receiver.method`, a method of the `can` block of the receiver's type
+    MethodCall {
+        receiver: Box<Expr<M>>,
+        method: Sym,
+    },
+    /// `[a, b]`
+    List(Vec<Expr<M>>),
+    /// `x[i]`, the `[` follows the value without a space
+    Index {
+        value: Box<Expr<M>>,
+        index: Box<Expr<M>>,
+    },
+    /// `x[a..b]`, either bound can be left out, e.g. `x[1..]`
+    Slice {
+        value: Box<Expr<M>>,
+        start: Option<Box<Expr<M>>>,
+        end: Option<Box<Expr<M>>>,
+    },
     If {
-        condition: Box<Self>,
-        consequence: Box<Self>,
-        alternative: Box<Self>,
+        condition: Box<Expr<M>>,
+        consequence: Box<Expr<M>>,
+        alternative: Box<Expr<M>>,
     },
     Match {
-        // 待匹配的值，例如: `match val with ...` 中的 `val`
-        value: Box<ExprKind<M>>,
+        // 待匹配的值，例如: `match val` 中的 `val`，保留它的 meta
+        value: Box<Expr<M>>,
         // 匹配分支列表
         arms: Vec<MatchArm<M>>,
     },
+    /// `let <bindings> in body`, the bindings are visible in each other and in the body
+    Let {
+        bindings: Vec<FuncDecl<M>>,
+        body: Box<Expr<M>>,
+    },
+    /// `\x y -> body`
+    Lambda {
+        params: Vec<Pattern>,
+        body: Box<Expr<M>>,
+    },
+    /// `(x /)`, the operator applied to its left operand
+    LeftSection {
+        op: Sym,
+        lhs: Box<Expr<M>>,
+    },
+    /// `(+ 1)`, the operator applied to its right operand, `(- 1)` is a negation
+    RightSection {
+        op: Sym,
+        rhs: Box<Expr<M>>,
+    },
+    /// an invalid token, the lexer has already reported it
+    Error,
 }
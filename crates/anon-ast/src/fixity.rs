@@ -0,0 +1,46 @@
+use anon_core::{interner::Symbol, span::Span};
+
+/// how operators of the same precedence group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    /// infixl, `a - b - c` is `(a - b) - c`
+    Left,
+    /// infixr, `a $ b $ c` is `a $ (b $ c)`
+    Right,
+    /// infix, `a == b == c` is an error
+    None,
+}
+
+/// the associativity and precedence of an infix operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixity {
+    pub associativity: Associativity,
+    /// from 0 (binds loosest) to 9 (binds tightest)
+    pub precedence: u8,
+}
+
+impl Fixity {
+    pub const MAX_PRECEDENCE: u8 = 9;
+
+    pub fn new(associativity: Associativity, precedence: u8) -> Self {
+        Self {
+            associativity,
+            precedence,
+        }
+    }
+}
+
+impl Default for Fixity {
+    /// operators without a fixity declaration are `infixl 9`
+    fn default() -> Self {
+        Self::new(Associativity::Left, Self::MAX_PRECEDENCE)
+    }
+}
+
+/// fixity declaration, e.g. `infixl 6 <+>, <->`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixityDecl {
+    pub fixity: Fixity,
+    pub operators: Vec<Symbol>,
+    pub span: Span,
+}
@@ -1,13 +1,49 @@
-use anon_core::interner::Symbol;
+use anon_core::{interner::Symbol, span::Span};
 
-use crate::{expr::Expr, sig::Sig};
+use crate::{expr::Expr, pattern::Pattern, sig::Sig};
 
-/// Func decl, without type annotation
+/// Func decl, the optional signature and every equation of one function
+///
+/// ```ignore
+/// get :: (Self, Usize) -> Maybe a
+/// get self 0 = unwrap self
+/// get self index = ...
+/// ```
 #[non_exhaustive]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FuncDecl<M> {
-    pub func_sig: Sig,
+    /// the `name :: type` right before the equations
+    pub func_sig: Option<Sig>,
     pub func_name: Symbol,
-    pub params: Vec<Symbol>,
-    pub func_body: Expr<M>,
+    /// the equations in source order
+    pub clauses: Vec<Clause<M>>,
+    /// the `--|` doc comments right before the declaration
+    pub doc: Option<Symbol>,
+}
+
+impl<M> FuncDecl<M> {
+    pub fn new(
+        func_name: Symbol,
+        func_sig: Option<Sig>,
+        clauses: Vec<Clause<M>>,
+        doc: Option<Symbol>,
+    ) -> Self {
+        Self {
+            func_sig,
+            func_name,
+            clauses,
+            doc,
+        }
+    }
+}
+
+/// one equation of a function, e.g. `get self 0 = unwrap self`
+#[derive(Debug, Clone)]
+pub struct Clause<M> {
+    pub params: Vec<Pattern>,
+    pub body: Expr<M>,
+    /// the functions of the `where` block after the body, visible in the body
+    pub where_bindings: Vec<FuncDecl<M>>,
+    /// from the function name to the end of the body
+    pub span: Span,
 }
@@ -1,8 +1,13 @@
+pub mod class;
 pub mod definition;
 pub mod expr;
+pub mod fixity;
 pub mod func_decl;
 pub mod literal;
 pub mod match_arm;
+pub mod module;
 pub mod pattern;
+pub mod refine;
 pub mod root;
-pub mod sig;
\ No newline at end of file
+pub mod sig;
+pub mod type_decl;
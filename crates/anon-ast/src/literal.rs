@@ -4,6 +4,104 @@ use anon_core::interner::Symbol;
 pub enum Literal {
     String(Symbol),
     Char(char),
-    Integer(i64),
+    /// the magnitude of an integer without suffix, `9223372036854775808` only fits `Int`
+    /// as the operand of `-`
+    Integer(u64),
     Float(f64),
+    /// integer with a type suffix, e.g. `10u8`, already checked to fit the suffix,
+    /// `128i8` only fits as the operand of `-`
+    SuffixedInteger(u64, IntSuffix),
+    /// float with a type suffix, e.g. `1.5f32`
+    SuffixedFloat(f64, FloatSuffix),
+}
+
+/// type suffix of an integer literal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntSuffix {
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        Some(match suffix {
+            "i8" => Self::I8,
+            "i16" => Self::I16,
+            "i32" => Self::I32,
+            "i64" => Self::I64,
+            "u8" => Self::U8,
+            "u16" => Self::U16,
+            "u32" => Self::U32,
+            "u64" => Self::U64,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+        }
+    }
+
+    /// the largest value a literal of this type can hold,
+    /// literals are never negative since `-` is an operator
+    pub fn max_value(self) -> u64 {
+        match self {
+            Self::I8 => i8::MAX as u64,
+            Self::I16 => i16::MAX as u64,
+            Self::I32 => i32::MAX as u64,
+            Self::I64 => i64::MAX as u64,
+            Self::U8 => u8::MAX as u64,
+            Self::U16 => u16::MAX as u64,
+            Self::U32 => u32::MAX as u64,
+            Self::U64 => u64::MAX,
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        matches!(self, Self::I8 | Self::I16 | Self::I32 | Self::I64)
+    }
+
+    /// the largest magnitude of a literal of this type, `-128i8` is `-` applied to `128i8`
+    /// so signed types allow one more than [IntSuffix::max_value]
+    pub fn max_magnitude(self) -> u64 {
+        self.max_value() + u64::from(self.is_signed())
+    }
+}
+
+/// type suffix of a float literal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FloatSuffix {
+    F32,
+    F64,
+}
+
+impl FloatSuffix {
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix {
+            "f32" => Some(Self::F32),
+            "f64" => Some(Self::F64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        }
+    }
 }
@@ -1,9 +1,15 @@
+use anon_core::span::Span;
+
 use crate::{expr::Expr, pattern::Pattern};
 
 // 模式匹配的单个分支
-// 例如: `pattern -> expression`
+// 例如: `pattern -> expression` 或者带守卫的 `pattern if guard -> expression`
 #[derive(Debug, Clone)]
 pub struct MatchArm<M> {
     pub pattern: Pattern,
+    // 模式匹配成功后还要满足的条件
+    pub guard: Option<Expr<M>>,
     pub body: Expr<M>,
+    // 从模式到分支的结尾
+    pub span: Span,
 }
@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+
+use anon_core::{
+    interner::{Interner, Symbol},
+    span::Span,
+};
+
+/// the extension of anon source files
+pub const SOURCE_EXTENSION: &str = "an";
+
+/// a dotted module name, e.g. `Foo.Bar`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModulePath {
+    pub segments: Vec<Symbol>,
+    pub span: Span,
+}
+
+impl ModulePath {
+    /// the file of the module relative to the source root, `Foo.Bar` is `Foo/Bar.an`
+    pub fn file_name(&self, interner: &Interner) -> PathBuf {
+        let mut path: PathBuf = self
+            .segments
+            .iter()
+            .map(|&segment| interner.resolve(segment).unwrap_or_default())
+            .collect();
+        path.set_extension(SOURCE_EXTENSION);
+        path
+    }
+
+    /// the module name as written, e.g. `Foo.Bar`
+    pub fn name(&self, interner: &Interner) -> String {
+        self.segments
+            .iter()
+            .map(|&segment| interner.resolve(segment).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+/// Module header, the first line of a file, e.g. `module Foo.Bar`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleDecl {
+    pub path: ModulePath,
+    pub span: Span,
+}
+
+/// Import decl
+///
+/// ```ignore
+/// import Data.Vec
+/// import Data.Vec (map, Vec)
+/// import Data.Vec as V hiding (map)
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub module: ModulePath,
+    /// `as V`, the qualifier of the imported names instead of the module name
+    pub alias: Option<Symbol>,
+    pub names: ImportNames,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportNames {
+    /// every exported name
+    All,
+    /// `(map, Vec)`
+    Only(Vec<ListedName>),
+    /// `hiding (map)`, every exported name but these
+    Hiding(Vec<ListedName>),
+}
+
+/// Export decl, `export (map, Vec, (<+>))`, a module without one exports everything
+#[derive(Debug, Clone, PartialEq)]
+pub struct Export {
+    pub names: Vec<ListedName>,
+    pub span: Span,
+}
+
+/// a name in an import or export list, an operator is written in parentheses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ListedName {
+    pub name: Symbol,
+    pub span: Span,
+}
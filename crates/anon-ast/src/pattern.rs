@@ -1,9 +1,23 @@
-use crate::literal::Literal;
+use anon_core::{interner::Symbol, span::Span};
+
+use crate::{literal::Literal, sig::Type};
+
+// 模式 (Pattern)，带着它在源码中的位置
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+impl Pattern {
+    pub fn new(kind: PatternKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
 
-// 模式 (Pattern)
 #[non_exhaustive]
 #[derive(Debug, PartialEq, Clone)]
-pub enum Pattern {
+pub enum PatternKind {
     // 匹配任何值，例如: `_`
     Wildcard,
 
@@ -11,13 +25,19 @@ pub enum Pattern {
     Literal(Literal),
 
     // 将匹配到的值绑定到一个变量，例如: `x`
-    Variable(String),
+    Variable(Symbol),
 
     // 构造器模式 (用于解构元组、列表或自定义代数数据类型)
     // - name: 构造器的名称，例如: 列表的 `Cons` 或元组的 `Tuple`
-    // - args: 递归的子模式列表，例如: `(x, y)` 中的 `x` 和 `y`
-    Constructor { name: String, args: Vec<Pattern> },
+    // - args: 递归的子模式列表，例如: `Cons x xs` 中的 `x` 和 `xs`
+    Constructor { name: Symbol, args: Vec<Pattern> },
+
+    // 细化类型模式：值满足该细化类型的谓词，例如: `sumOfVec EmptyIntVec = 0` 中的 `EmptyIntVec`
+    Refinement(Symbol),
+
+    // 模式别名：将匹配结果绑定到变量，同时进行解构，例如: `(Cons x _) @ list`
+    Alias { name: Symbol, pattern: Box<Pattern> },
 
-    // 模式别名：将匹配结果绑定到变量，同时进行解构，例如: `(x, y) @ point`
-    Alias { name: String, pattern: Box<Pattern> },
+    // 类型标注：值必须属于该类型，常用于细化类型，例如: `(x :: OneElementIntVec)`
+    Annotated { pattern: Box<Pattern>, ty: Type },
 }
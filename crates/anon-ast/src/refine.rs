@@ -0,0 +1,96 @@
+use anon_core::{interner::Symbol, span::Span};
+
+use crate::{literal::Literal, sig::Type};
+
+/// Refine decl, a base type restricted to the values satisfying a predicate
+///
+/// ```ignore
+/// refine EmptyIntVec = Vec Int where Vec.len = 0
+/// refine Digit = Int where
+///   self >= 0
+///   self <= 9
+/// ```
+///
+/// a refinement can be used as a pattern, `sumOfVec EmptyIntVec = 0`
+/// matches the values of the base type satisfying the predicate
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub struct RefineDecl {
+    pub name: Symbol,
+    /// the type variables after the name, e.g. `a` in `refine NonEmpty a = Vec a where ...`
+    pub params: Vec<Symbol>,
+    pub base: Type,
+    /// the lines of the `where` block joined with `&&`
+    pub predicate: Predicate,
+    /// the `--|` doc comments right before the declaration
+    pub doc: Option<Symbol>,
+    pub span: Span,
+}
+
+impl RefineDecl {
+    pub fn new(
+        name: Symbol,
+        params: Vec<Symbol>,
+        base: Type,
+        predicate: Predicate,
+        doc: Option<Symbol>,
+        span: Span,
+    ) -> Self {
+        Self {
+            name,
+            params,
+            base,
+            predicate,
+            doc,
+            span,
+        }
+    }
+}
+
+/// the predicate language of refinements, only pure terms of the refined value
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Literal(Literal),
+    /// the refined value, written `self` or as the head of the base type, e.g. `Vec`
+    Value,
+    /// a call to a pure measure method, e.g. `Vec.len`
+    Measure {
+        receiver: Box<Predicate>,
+        measure: Symbol,
+    },
+    /// `+ - * / %`
+    Arith {
+        op: ArithOp,
+        lhs: Box<Predicate>,
+        rhs: Box<Predicate>,
+    },
+    /// `= != < <= > >=`, `==` is the same as `=`
+    Compare {
+        op: CompareOp,
+        lhs: Box<Predicate>,
+        rhs: Box<Predicate>,
+    },
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
@@ -1,26 +1,39 @@
-use std::{
-    collections::{HashMap, HashSet},
-    str::FromStr,
-};
+use anon_core::{interner::Symbol, span::Span};
 
-use anon_core::interner::Symbol;
-
-/// sig for func
-/// e.g map :: (a->b) -> [a] -> [b]
-/// print :: Show a => a -> IO ()
-#[derive(Debug)]
+/// sig for func, the part after `::`
+/// e.g map :: (a -> b) -> Vec a -> Vec b
+/// print :: Show a => a -> () with Out Console
+#[derive(Debug, Clone, PartialEq)]
 pub struct Sig {
-    name: Symbol,
-    constraints: HashMap<Symbol, HashSet<Symbol>>,
-    arrow: Vec<Symbol>,
+    /// `Show a =>`, empty without a context
+    pub constraints: Vec<Constraint>,
+    pub ty: Type,
+    /// the effects after `with`, e.g. `Out Console` and `Panic!`
+    pub effects: Vec<Type>,
+    pub span: Span,
 }
 
-#[derive(Debug)]
-pub enum ParseSigError {}
+/// a class constraint, e.g. `Show a` or `Functor f`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    pub class: Symbol,
+    pub args: Vec<Type>,
+}
 
-impl FromStr for Sig {
-    type Err = ParseSigError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        unimplemented!()
-    }
+/// a type expression
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// type variable, starts with a lowercase letter, e.g. `a`
+    Var(Symbol),
+    /// named type, e.g. `Int` or `Vec`
+    Con(Symbol),
+    /// `Vec Int`, `Either e a`
+    App { func: Box<Type>, args: Vec<Type> },
+    /// `a -> b`, `a -> b -> c` is `a -> (b -> c)`
+    Arrow { param: Box<Type>, result: Box<Type> },
+    /// `(a, b)`, with at least two elements
+    Tuple(Vec<Type>),
+    /// `()`
+    Unit,
 }
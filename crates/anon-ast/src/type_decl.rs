@@ -0,0 +1,95 @@
+use anon_core::{interner::Symbol, span::Span};
+
+use crate::{func_decl::FuncDecl, sig::Type};
+
+/// Type decl, an algebraic data type or an alias, and the methods of its `can` block
+///
+/// ```ignore
+/// type Maybe a = data a {
+///   Just a | None
+/// }
+/// can {
+///   unwrap :: Self -> a with Maybe Panic!
+///   ...
+/// }
+/// ```
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub struct TypeDecl<M> {
+    pub name: Symbol,
+    /// the type variables, both the ones after the name and after `data`
+    pub params: Vec<Symbol>,
+    pub body: TypeBody,
+    /// the methods of the `can` block in source order, `Self` is this type in them
+    pub methods: Vec<FuncDecl<M>>,
+    /// the calls in the methods whose receiver is known to be `Self`
+    pub self_calls: Vec<SelfCall>,
+    /// the `--|` doc comments right before the declaration
+    pub doc: Option<Symbol>,
+    pub span: Span,
+}
+
+impl<M> TypeDecl<M> {
+    pub fn new(
+        name: Symbol,
+        params: Vec<Symbol>,
+        body: TypeBody,
+        doc: Option<Symbol>,
+        span: Span,
+    ) -> Self {
+        Self {
+            name,
+            params,
+            body,
+            methods: Vec::new(),
+            self_calls: Vec::new(),
+            doc,
+            span,
+        }
+    }
+
+    /// the method called `name`, `x.name` calls it on a value `x` of this type
+    pub fn method(&self, name: Symbol) -> Option<&FuncDecl<M>> {
+        self.methods.iter().find(|method| method.func_name == name)
+    }
+}
+
+/// `x.name` in a method where `x` is `Self`, `name` is a method of the same `can` block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfCall {
+    pub method: Symbol,
+    /// from the receiver to the method name
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeBody {
+    /// `data { Just a | None }`
+    Data(Vec<Constructor>),
+    /// `type Ints = Vec Int`
+    Alias(Type),
+}
+
+/// a data constructor, e.g. `Cons a Self` or `Point { x :: Int, y :: Int }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constructor {
+    pub name: Symbol,
+    pub fields: Fields,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fields {
+    /// `Cons a Self`, empty for constructors without fields
+    Positional(Vec<Type>),
+    /// `Point { x :: Int, y :: Int }`
+    Named(Vec<Field>),
+}
+
+/// `x :: Int`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: Symbol,
+    pub ty: Type,
+    pub span: Span,
+}
@@ -3,10 +3,12 @@ use std::collections::HashMap;
 /// cli struct to receive env args
 /// format: command [option] [args]
 /// usage: anon [command] [options]
+#[allow(dead_code)]
 pub struct Cli {
     pub map: HashMap<String, Command>,
 }
 
+#[allow(dead_code)]
 impl Cli {
     pub fn new() -> Self {
         let mut map = HashMap::with_capacity(10);
@@ -26,12 +28,16 @@ impl Cli {
     }
 }
 
+pub type CallBack = Box<dyn Fn(&Vec<String>)>;
+
+#[allow(dead_code)]
 pub struct Command {
     pub name: String,
     pub option: Vec<String>,
-    pub call_back: Box<dyn Fn(&Vec<String>)>,
+    pub call_back: CallBack,
 }
 
+#[allow(dead_code)]
 impl Command {
     pub fn process(&self, args: &Vec<String>) {
         (self.call_back)(args)
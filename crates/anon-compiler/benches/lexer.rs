@@ -0,0 +1,57 @@
+//! Compares the hand-written lexer with the pest based one.
+//!
+//! Run with `cargo bench -p anon-compiler --bench lexer`.
+
+use std::{cell::RefCell, hint::black_box, rc::Rc};
+
+use anon_compiler::{Lexer, PestLexer};
+use anon_core::interner::Interner;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+const SAMPLES: &[&str] = &[
+    include_str!("../../../samples/data.an"),
+    include_str!("../../../samples/hello-world.an"),
+    include_str!("../../../samples/refinement-types.an"),
+    include_str!("../../../samples/trivia.an"),
+];
+
+/// a large generated source, the samples repeated until it has about `size` bytes
+fn generated_source(size: usize) -> String {
+    let mut src = String::with_capacity(size);
+    while src.len() < size {
+        for sample in SAMPLES {
+            src.push_str(sample);
+            src.push('\n');
+        }
+    }
+    src
+}
+
+fn bench_lexers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lex");
+    for size in [16 * 1024, 256 * 1024] {
+        let src = generated_source(size);
+        group.throughput(Throughput::Bytes(src.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("hand-written", size),
+            &src,
+            |b, src| {
+                b.iter(|| {
+                    let interner = Rc::new(RefCell::new(Interner::new()));
+                    Lexer::new(black_box(src), 4, interner).count()
+                })
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("pest", size), &src, |b, src| {
+            b.iter(|| {
+                let interner = Rc::new(RefCell::new(Interner::new()));
+                PestLexer::new(black_box(src), 4, interner).count()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lexers);
+criterion_main!(benches);
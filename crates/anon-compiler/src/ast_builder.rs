@@ -1,39 +1,143 @@
-use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
+};
 
-use anon_ast::func_decl::FuncDecl;
-use anon_core::interner::Interner;
+use anon_ast::{
+    class::{ClassDecl, ClassMethod, InstanceDecl},
+    expr::{Expr, ExprKind},
+    fixity::{Associativity, Fixity, FixityDecl},
+    func_decl::{Clause, FuncDecl},
+    literal::Literal,
+    match_arm::MatchArm,
+    module::{Export, Import, ImportNames, ListedName, ModuleDecl, ModulePath},
+    pattern::{Pattern, PatternKind},
+    refine::{ArithOp, CompareOp, Predicate, RefineDecl},
+    sig::{Constraint, Sig, Type},
+    type_decl::{Constructor, Field, Fields, TypeBody, TypeDecl},
+};
+use anon_core::{
+    diagnostic::{Severity, SimpleDiagnostic},
+    interner::{Interner, Symbol},
+    span::{Span, Spanned},
+};
 
-use crate::{Lexer, token::Token, untyped_ast::UntypedAST};
+use crate::{
+    Layout, Lexer,
+    delimiter::Delimiter,
+    fixity::{FixityTable, builtin_fixity, collect_fixity_decls, parse_fixity_decl},
+    keyword::Keyword,
+    lexer::LexerConfig,
+    method::resolve_self_calls,
+    number::{NumberErrorKind, negation_only},
+    operator::Operator,
+    token::Token,
+    untyped_ast::UntypedAST,
+};
 
 // flow： 优先从buffer的front读取，否则就从
 #[derive(Debug, Clone)]
 pub struct Parser<'a> {
-    // 封装 IndentManager，它提供了我们的 Tokens 流
-    lexer: Lexer<'a>,
+    // 经过 layout pass 的 Tokens 流，块由 VirtualOpen/VirtualSemi/VirtualClose 标出
+    tokens: Layout<'a, std::vec::IntoIter<Spanned<Token>>>,
     // 用于缓存 peek 过的 Tokens，因为递归下降需要前瞻
     // LanguageToken 已经是经过处理的 Tokens，所以现在我们用它来 peek
-    buffered_tokens: VecDeque<Token>,
+    buffered_tokens: VecDeque<Spanned<Token>>,
     interner: Rc<RefCell<Interner>>,
+    // 二元运算符的优先级和结合性
+    fixities: FixityTable,
+    // 文件里声明的细化类型，它们作为模式时不是构造器
+    refinements: HashSet<Symbol>,
+    // 读多了的下一个 item，用来判断函数的方程是否结束，也可能是一个语法错误
+    lookahead: Option<Result<Item, SimpleDiagnostic>>,
+    // 已经解析过的函数和它们第一个方程的位置
+    defined: HashMap<Symbol, Span>,
+    // 正在解析的 `can`、`class` 或 `instance` 块和里面已经解析过的方法
+    methods: Option<(Block, HashMap<Symbol, Span>)>,
+    // `Self` 只能出现在类型声明和它的 `can` 块里
+    self_type: bool,
+    diagnostics: Vec<SimpleDiagnostic>,
+    // 已经返回过定义了，模块头只能在文件的最前面
+    started: bool,
+    // 已经消耗的 VirtualOpen 和 `{` 减去 VirtualClose 和 `}`，为 0 时在顶层
+    depth: i32,
+    // 正在解析的顶层定义的第一个 Token，出错时整个定义都被跳过
+    item_start: Option<Span>,
+    // 最后消耗的非虚拟 Token 的位置
+    previous: Option<Span>,
+    // 直接跟在 `-` 后面的整数字面量，可以是 `128i8` 这样取负之后才在范围内的值
+    negated: Option<Span>,
 }
 
 impl<'a> Parser<'a> {
+    /// the fixity declarations of the whole file apply to its expressions and its
+    /// refinements to its patterns, so the file is lexed before parsing starts and the
+    /// lexical errors come first
     pub fn new(lexer: Lexer<'a>, interner: Rc<RefCell<Interner>>) -> Self {
+        let (src, tab_width) = (lexer.source(), lexer.config().tab_width);
+        let mut lexer = lexer.for_layout();
+        let tokens: Vec<_> = lexer.by_ref().collect();
+        let mut diagnostics = lexer.take_diagnostics();
+        // 声明本身的错误在解析到那一行时报告
+        let (decls, _) = collect_fixity_decls(&tokens);
+        let (fixities, errors) = FixityTable::from_decls(&decls, &interner.borrow());
+        diagnostics.extend(errors);
+        let refinements = collect_refinements(&tokens);
         Parser {
-            lexer,
+            tokens: Layout::new(tokens.into_iter(), src, tab_width),
             buffered_tokens: VecDeque::new(),
             interner,
+            fixities,
+            refinements,
+            lookahead: None,
+            defined: HashMap::new(),
+            methods: None,
+            self_type: false,
+            diagnostics,
+            started: false,
+            depth: 0,
+            item_start: None,
+            previous: None,
+            negated: None,
         }
     }
 
+    /// makes the operators exported by the module of `import` usable with their fixities,
+    /// they only apply to the definitions parsed after this call
+    pub fn import_fixities(&mut self, exporter: &FixityTable, import: &Import) {
+        let errors = self
+            .fixities
+            .import(exporter, import, &self.interner.borrow());
+        self.diagnostics.extend(errors);
+    }
+
+    /// the fixities of the file, they override [builtin_fixity]
+    pub fn fixities(&self) -> &FixityTable {
+        &self.fixities
+    }
+
     // 获取下一个 Tokens，优先从缓存中获取
-    fn next_token(&mut self) -> Option<Token> {
-        self.buffered_tokens.pop_front().or(self.lexer.next())
+    fn next_token(&mut self) -> Option<Spanned<Token>> {
+        let token = self
+            .buffered_tokens
+            .pop_front()
+            .or_else(|| self.tokens.next())?;
+        self.depth += nesting(token.value);
+        if !token.context.is_empty() {
+            self.previous = Some(token.context);
+        }
+        Some(token)
     }
 
     // 窥视下一个 Tokens，但不消耗
     fn peek(&mut self) -> Option<&Token> {
+        self.peek_spanned().map(|tok| &tok.value)
+    }
+
+    fn peek_spanned(&mut self) -> Option<&Spanned<Token>> {
         if self.buffered_tokens.is_empty()
-            && let Some(tok) = self.lexer.next()
+            && let Some(tok) = self.tokens.next()
         {
             self.buffered_tokens.push_back(tok);
         }
@@ -41,56 +145,3953 @@ impl<'a> Parser<'a> {
     }
 
     /// 把元素放回缓存的头
-    fn put_back(&mut self, tok: Token) {
+    fn put_back(&mut self, tok: Spanned<Token>) {
+        self.depth -= nesting(tok.value);
         self.buffered_tokens.push_front(tok);
     }
 
     // 消耗当前 Tokens，并检查它是否符合期望
-    fn consume(&mut self, expected: Token) -> Result<Token, String> {
-        let token = self.next_token().ok_or_else(|| {
-            format!("Expected {:?}, but reached end of file", expected)
-        })?;
-
-        // 实际应用中，你可能只需要匹配 Token 的**类型**
-        if token == expected {
-            Ok(token)
+    fn consume(&mut self, expected: Token) -> Result<Spanned<Token>, SimpleDiagnostic> {
+        match self.next_token() {
+            Some(token) if token.value == expected => Ok(token),
+            token => {
+                if let Some(token) = token {
+                    self.put_back(token);
+                }
+                Err(self.expected(&expected.to_string()))
+            }
+        }
+    }
+
+    /// "expected {what}, found ..." at the next token, which is put back
+    fn expected(&mut self, what: &str) -> SimpleDiagnostic {
+        let (found, span) = match self.peek_spanned() {
+            Some(token) => (token.value.to_string(), token.context),
+            // EOF 已经被消耗了，报告在最后一个 token 之后
+            None => {
+                let end = self.previous.map_or(0, |span| span.end());
+                (Token::EOF.to_string(), Span::empty(end))
+            }
+        };
+        SimpleDiagnostic::new(
+            Severity::Error,
+            format!("expected {what}, found {found}"),
+            span,
+            None,
+        )
+    }
+
+    fn intern(&self, text: &str) -> Symbol {
+        self.interner.borrow_mut().intern_or_get(text)
+    }
+
+    /// parses an expression, binary operators are grouped by their fixity
+    pub fn parse_expr(&mut self) -> Result<Expr<Span>, SimpleDiagnostic> {
+        self.parse_binary(0, None, None)
+    }
+
+    /// operators binding looser than `min_bp` are left to the caller,
+    /// `parent` is the operator whose right operand is being parsed
+    ///
+    /// `section` is the closing bracket if the expression is directly inside brackets,
+    /// where an operator followed by it is left to the caller too, e.g. `(x /)` or `x[1..]`
+    fn parse_binary(
+        &mut self,
+        min_bp: u8,
+        parent: Option<(Symbol, Fixity)>,
+        section: Option<Delimiter>,
+    ) -> Result<Expr<Span>, SimpleDiagnostic> {
+        let mut lhs = self.parse_prefix()?;
+        // 上一个同层的运算符，相同优先级下结合性不同或者不可结合时报错
+        let mut previous = parent;
+        while let Some(&token) = self.peek_spanned()
+            && let Some((op, fixity)) = self.binary_operator(token.value)
+        {
+            let (left_bp, right_bp) = binding_power(fixity);
+            if left_bp < min_bp {
+                break;
+            }
+            self.check_mix(previous, (op, fixity), token.context)?;
+
+            self.next_token();
+            // `(x op)` 是一个 section，运算符留给 [Parser::parse_atom]
+            if let Some(close) = section
+                && self.peek() == Some(&Token::Delimiter(close))
+            {
+                self.put_back(token);
+                if let Some((outer, outer_fixity)) = previous
+                    && min_bp > 0
+                {
+                    let message = format!(
+                        "`{}` [{}] can not be the operator of a section after `{}` [{}], \
+                         add parentheses",
+                        self.resolve(op),
+                        describe(fixity),
+                        self.resolve(outer),
+                        describe(outer_fixity),
+                    );
+                    return Err(SimpleDiagnostic::new(
+                        Severity::Error,
+                        message,
+                        token.context,
+                        None,
+                    ));
+                }
+                break;
+            }
+            let rhs = self.parse_binary(right_bp, Some((op, fixity)), section)?;
+            lhs = Expr {
+                meta: lhs.meta.to(rhs.meta),
+                kind: ExprKind::Binary {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                },
+            };
+            previous = Some((op, fixity));
+        }
+        Ok(lhs)
+    }
+
+    /// operators of the same precedence can only be chained when they associate
+    /// the same way, e.g. `a == b == c` needs parentheses
+    fn check_mix(
+        &self,
+        previous: Option<(Symbol, Fixity)>,
+        (op, fixity): (Symbol, Fixity),
+        span: Span,
+    ) -> Result<(), SimpleDiagnostic> {
+        let Some((previous_op, previous_fixity)) = previous else {
+            return Ok(());
+        };
+        if previous_fixity.precedence != fixity.precedence
+            || (previous_fixity.associativity == fixity.associativity
+                && fixity.associativity != Associativity::None)
+        {
+            return Ok(());
+        }
+        let interner = self.interner.borrow();
+        let name = |op| interner.resolve(op).unwrap_or("<unknown>");
+        Err(SimpleDiagnostic::new(
+            Severity::Error,
+            format!(
+                "cannot mix `{}` [{}] and `{}` [{}] in the same infix expression, \
+                 add parentheses",
+                name(previous_op),
+                describe(previous_fixity),
+                name(op),
+                describe(fixity),
+            ),
+            span,
+            None,
+        ))
+    }
+
+    /// the operator spelled by `token` and its fixity, if `token` is a binary operator
+    fn binary_operator(&self, token: Token) -> Option<(Symbol, Fixity)> {
+        let (op, builtin) = match token {
+            Token::SymbolicIdentifier(op) => (op, None),
+            Token::Operator(op) => {
+                (self.intern(op.as_str()), Some(builtin_fixity(op)?))
+            }
+            _ => return None,
+        };
+        let fixity = self.fixities.get(op).or(builtin).unwrap_or_default();
+        Some((op, fixity))
+    }
+
+    /// prefix `-` binds like binary `-`, so `-a * b` is `-(a * b)`,
+    /// `let`, `match` and lambdas extend as far to the right as possible
+    fn parse_prefix(&mut self) -> Result<Expr<Span>, SimpleDiagnostic> {
+        match self.peek_spanned() {
+            Some(&Spanned {
+                context,
+                value: Token::Keyword(Keyword::Match),
+            }) => {
+                self.next_token();
+                self.parse_match(context)
+            }
+            Some(&Spanned {
+                context,
+                value: Token::Keyword(Keyword::Let),
+            }) => {
+                self.next_token();
+                let bindings = self.parse_bindings(Block::Let)?;
+                self.consume(Token::Keyword(Keyword::In))?;
+                let body = self.parse_expr()?;
+                Ok(Expr {
+                    meta: context.to(body.meta),
+                    kind: ExprKind::Let {
+                        bindings,
+                        body: Box::new(body),
+                    },
+                })
+            }
+            Some(&Spanned {
+                context,
+                value: Token::Delimiter(Delimiter::Backslash),
+            }) => {
+                self.next_token();
+                let mut params = vec![];
+                while self.peek() != Some(&Token::Operator(Operator::Arrow)) {
+                    if !self.peek().is_some_and(|&token| starts_pattern(token)) {
+                        return Err(self.expected("a parameter or `->`"));
+                    }
+                    params.push(self.parse_pattern_atom()?);
+                }
+                if params.is_empty() {
+                    return Err(self.expected("a parameter"));
+                }
+                self.next_token();
+                let body = self.parse_expr()?;
+                Ok(Expr {
+                    meta: context.to(body.meta),
+                    kind: ExprKind::Lambda {
+                        params,
+                        body: Box::new(body),
+                    },
+                })
+            }
+            Some(&Spanned {
+                context,
+                value: Token::Operator(Operator::Negate),
+            }) => {
+                self.next_token();
+                let minus = self.intern(Operator::Negate.as_str());
+                let fixity =
+                    builtin_fixity(Operator::Negate).expect("`-` is a binary operator");
+                let literal = match self.peek_spanned() {
+                    Some(&Spanned {
+                        context,
+                        value: Token::Literal(literal),
+                    }) => negation_only(literal).map(|_| (context, literal)),
+                    _ => None,
+                };
+                let negated =
+                    std::mem::replace(&mut self.negated, literal.map(|(span, _)| span));
+                let operand = self.parse_binary(
+                    binding_power(fixity).1,
+                    Some((minus, fixity)),
+                    None,
+                );
+                self.negated = negated;
+                let operand = operand?;
+                // `-128i8 * 2` 中取负的不是 `128i8`
+                if let Some((span, literal)) = literal
+                    && operand.meta != span
+                {
+                    self.check_literal(literal, span);
+                }
+                Ok(Expr {
+                    meta: context.to(operand.meta),
+                    kind: ExprKind::Negate(Box::new(operand)),
+                })
+            }
+            _ => self.parse_application(),
+        }
+    }
+
+    /// `match value` after the `match` keyword at `start`,
+    /// followed by a block of `pattern (if guard)? -> expr` arms
+    fn parse_match(&mut self, start: Span) -> Result<Expr<Span>, SimpleDiagnostic> {
+        let value = self.parse_expr()?;
+        self.consume(Token::VirtualOpen)?;
+        let mut arms = Vec::new();
+        loop {
+            let pattern = self.parse_pattern()?;
+            let guard = if self.peek() == Some(&Token::Keyword(Keyword::If)) {
+                self.next_token();
+                Some(self.parse_expr()?)
+            } else {
+                None
+            };
+            self.consume(Token::Operator(Operator::Arrow))?;
+            let body = self.parse_expr()?;
+            arms.push(MatchArm {
+                span: pattern.span.to(body.meta),
+                pattern,
+                guard,
+                body,
+            });
+            if self.peek() != Some(&Token::VirtualSemi) {
+                break;
+            }
+            self.next_token();
+        }
+        self.consume(Token::VirtualClose)?;
+        let span = start.to(arms.last().expect("at least one arm").span);
+        Ok(Expr {
+            meta: span,
+            kind: ExprKind::Match {
+                value: Box::new(value),
+                arms,
+            },
+        })
+    }
+
+    /// `f x y`, juxtaposition binds tighter than every operator
+    fn parse_application(&mut self) -> Result<Expr<Span>, SimpleDiagnostic> {
+        let func = self.parse_postfix()?;
+        let mut meta = func.meta;
+        let mut args = Vec::new();
+        while self.peek().is_some_and(|&token| starts_atom(token)) {
+            let arg = self.parse_postfix()?;
+            meta = meta.to(arg.meta);
+            args.push(arg);
+        }
+        if args.is_empty() {
+            return Ok(func);
+        }
+        Ok(Expr {
+            meta,
+            kind: ExprKind::Application {
+                func: Box::new(func),
+                args,
+            },
+        })
+    }
+
+    /// an atom followed by `.method`s and indexing, which bind tighter than application
+    ///
+    /// `x[0]` is an index but `f [0]` applies `f` to a list
+    fn parse_postfix(&mut self) -> Result<Expr<Span>, SimpleDiagnostic> {
+        let mut receiver = self.parse_atom()?;
+        loop {
+            match self.peek_spanned() {
+                Some(Spanned {
+                    value: Token::Delimiter(Delimiter::Dot),
+                    ..
+                }) => {
+                    let method = self.parse_method_name()?;
+                    receiver = Expr {
+                        meta: receiver.meta.to(method.context),
+                        kind: ExprKind::MethodCall {
+                            receiver: Box::new(receiver),
+                            method: method.value,
+                        },
+                    };
+                }
+                Some(&Spanned {
+                    context,
+                    value: Token::Delimiter(Delimiter::LBracket),
+                }) if context.start() == receiver.meta.end() => {
+                    self.next_token();
+                    receiver = self.parse_index(receiver)?;
+                }
+                _ => return Ok(receiver),
+            }
+        }
+    }
+
+    /// the rest of `value[i]` or `value[a..b]` after the `[`
+    fn parse_index(
+        &mut self,
+        value: Expr<Span>,
+    ) -> Result<Expr<Span>, SimpleDiagnostic> {
+        let range = Token::Operator(Operator::Range);
+        let close = Token::Delimiter(Delimiter::RBracket);
+        let start = if self.peek() == Some(&range) {
+            None
         } else {
-            Err(format!("Expected {:?}, got {:?}", expected, token))
+            Some(self.parse_binary(0, None, Some(Delimiter::RBracket))?)
+        };
+        let span = value.meta;
+        let value = Box::new(value);
+        let kind = match start {
+            Some(Expr {
+                kind: ExprKind::Binary { op, lhs, rhs },
+                ..
+            }) if op == self.intern(Operator::Range.as_str()) => ExprKind::Slice {
+                value,
+                start: Some(lhs),
+                end: Some(rhs),
+            },
+            Some(index) if self.peek() != Some(&range) => ExprKind::Index {
+                value,
+                index: Box::new(index),
+            },
+            // `x[a..]` 或者 `x[..b]`，`..` 作为 section 留下了
+            start => {
+                let op = self.consume(range)?;
+                let end = if start.is_some() || self.peek() == Some(&close) {
+                    None
+                } else {
+                    let (op, fixity) = self
+                        .binary_operator(op.value)
+                        .expect("`..` is a binary operator");
+                    Some(self.parse_binary(
+                        binding_power(fixity).1,
+                        Some((op, fixity)),
+                        None,
+                    )?)
+                };
+                ExprKind::Slice {
+                    value,
+                    start: start.map(Box::new),
+                    end: end.map(Box::new),
+                }
+            }
+        };
+        let close = self.consume(close)?;
+        Ok(Expr {
+            meta: span.to(close.context),
+            kind,
+        })
+    }
+
+    /// `.name` after a receiver
+    fn parse_method_name(&mut self) -> Result<Spanned<Symbol>, SimpleDiagnostic> {
+        self.consume(Token::Delimiter(Delimiter::Dot))?;
+        match self.next_token() {
+            Some(Spanned {
+                context,
+                value: Token::Identifier(method),
+            }) => Ok(Spanned::new(context, method)),
+            token => {
+                if let Some(token) = token {
+                    self.put_back(token);
+                }
+                Err(self.expected("a method name"))
+            }
+        }
+    }
+
+    /// reports an integer literal like `128i8` that only fits its type when negated
+    fn check_literal(&mut self, literal: Literal, span: Span) {
+        if let Some(ty) = negation_only(literal) {
+            self.error(NumberErrorKind::OutOfRange(ty).to_string(), span, None);
+        }
+    }
+
+    /// a name, a literal, a list or a parenthesised expression
+    fn parse_atom(&mut self) -> Result<Expr<Span>, SimpleDiagnostic> {
+        let Some(token) = self.next_token() else {
+            return Err(self.expected("an expression"));
+        };
+        let kind = match token.value {
+            Token::Identifier(name) if is_constructor(&self.resolve(name)) => {
+                return Ok(self.parse_qualified(Spanned::new(token.context, name)));
+            }
+            Token::Identifier(name) => ExprKind::Object(name),
+            Token::Literal(literal) => {
+                if self.negated != Some(token.context) {
+                    self.check_literal(literal, token.context);
+                }
+                ExprKind::Literal(literal)
+            }
+            Token::StringStart => return self.parse_interpolated_string(token.context),
+            Token::Delimiter(Delimiter::LParen) => {
+                return self.parse_parens(token.context);
+            }
+            Token::Delimiter(Delimiter::LBracket) => {
+                return self.parse_list(token.context);
+            }
+            // lexer 已经报告了错误，这里不再报一次，也不丢掉整个定义
+            Token::Error => ExprKind::Error,
+            _ => {
+                self.put_back(token);
+                return Err(self.expected("an expression"));
+            }
+        };
+        Ok(Expr {
+            meta: token.context,
+            kind,
+        })
+    }
+
+    /// `Foo.Bar.x` after the `Foo` at `first`, the last name is qualified by the ones
+    /// before it, there is no space around the dots unlike in `Foo . x`
+    fn parse_qualified(&mut self, first: Spanned<Symbol>) -> Expr<Span> {
+        let mut segments = vec![first.value];
+        let mut span = first.context;
+        // 小写的名字之后的 `.` 是方法调用
+        while let Some(&dot) = self.peek_spanned()
+            && dot.value == Token::Delimiter(Delimiter::Dot)
+            && dot.context.start() == span.end()
+        {
+            self.next_token();
+            match self.peek_spanned() {
+                Some(&Spanned {
+                    context,
+                    value: Token::Identifier(name),
+                }) if context.start() == dot.context.end() => {
+                    self.next_token();
+                    segments.push(name);
+                    span = span.to(context);
+                    if !is_constructor(&self.resolve(name)) {
+                        break;
+                    }
+                }
+                _ => {
+                    self.put_back(dot);
+                    break;
+                }
+            }
+        }
+
+        let name = segments.pop().expect("at least the first name");
+        if segments.is_empty() {
+            return Expr {
+                meta: span,
+                kind: ExprKind::Object(name),
+            };
+        }
+        let module = segments
+            .iter()
+            .map(|&segment| self.resolve(segment))
+            .collect::<Vec<_>>()
+            .join(".");
+        Expr {
+            meta: span,
+            kind: ExprKind::Qualified {
+                module: self.intern(&module),
+                name,
+            },
+        }
+    }
+
+    /// the rest of a parenthesised expression or section after the `(` at `open`,
+    /// e.g. `(+)`, `(+ 1)` or `(x /)`
+    fn parse_parens(&mut self, open: Span) -> Result<Expr<Span>, SimpleDiagnostic> {
+        let close = |parser: &mut Self, kind| {
+            let close = parser.consume(Token::Delimiter(Delimiter::RParen))?;
+            Ok(Expr {
+                meta: open.to(close.context),
+                kind,
+            })
+        };
+
+        if let Some(&token) = self.peek_spanned()
+            && let Some((op, fixity)) = self.binary_operator(token.value)
+        {
+            self.next_token();
+            if self.peek() == Some(&Token::Delimiter(Delimiter::RParen)) {
+                return close(self, ExprKind::Object(op));
+            }
+            // `(- 1)` 是取负数
+            if token.value == Token::Operator(Operator::Negate) {
+                self.put_back(token);
+            } else {
+                let rhs = self.parse_binary(
+                    binding_power(fixity).1,
+                    Some((op, fixity)),
+                    None,
+                )?;
+                let rhs = Box::new(rhs);
+                return close(self, ExprKind::RightSection { op, rhs });
+            }
+        }
+
+        let inner = self.parse_binary(0, None, Some(Delimiter::RParen))?;
+        if let Some(&token) = self.peek_spanned()
+            && let Some((op, _)) = self.binary_operator(token.value)
+        {
+            self.next_token();
+            let lhs = Box::new(inner);
+            return close(self, ExprKind::LeftSection { op, lhs });
+        }
+        close(self, inner.kind)
+    }
+
+    /// the rest of `[a, b]` after the `[` at `open`
+    fn parse_list(&mut self, open: Span) -> Result<Expr<Span>, SimpleDiagnostic> {
+        let mut elements = Vec::new();
+        while self.peek() != Some(&Token::Delimiter(Delimiter::RBracket)) {
+            elements.push(self.parse_expr()?);
+            if self.peek() != Some(&Token::Delimiter(Delimiter::Comma)) {
+                break;
+            }
+            self.next_token();
+        }
+        let close = self.consume(Token::Delimiter(Delimiter::RBracket))?;
+        Ok(Expr {
+            meta: open.to(close.context),
+            kind: ExprKind::List(elements),
+        })
+    }
+
+    /// the rest of a string after its [Token::StringStart],
+    /// `"a {x} b"` becomes `"a " ++ show x ++ " b"`
+    fn parse_interpolated_string(
+        &mut self,
+        open: Span,
+    ) -> Result<Expr<Span>, SimpleDiagnostic> {
+        let show = self.intern("show");
+        let mut parts = Vec::new();
+        let close = loop {
+            let Some(token) = self.next_token() else {
+                return Err(self.expected("the end of the string"));
+            };
+            match token.value {
+                Token::StringFragment(text) => parts.push(Expr {
+                    meta: token.context,
+                    kind: ExprKind::Literal(Literal::String(text)),
+                }),
+                Token::InterpolationStart => {
+                    let value = self.parse_expr()?;
+                    let end = self.consume(Token::InterpolationEnd)?;
+                    // 插入的 `show` 没有写在源码里，用 `{` 的位置
+                    let func = Expr {
+                        meta: token.context,
+                        kind: ExprKind::Object(show),
+                    };
+                    parts.push(Expr {
+                        meta: token.context.to(end.context),
+                        kind: ExprKind::Application {
+                            func: Box::new(func),
+                            args: vec![value],
+                        },
+                    });
+                }
+                Token::StringEnd => break token.context,
+                _ => {
+                    self.put_back(token);
+                    return Err(self.expected("the end of the string"));
+                }
+            }
+        };
+
+        let concat = self.intern("++");
+        let expr = parts
+            .into_iter()
+            .rev()
+            .reduce(|rhs, lhs| Expr {
+                meta: lhs.meta.to(rhs.meta),
+                kind: ExprKind::Binary {
+                    op: concat,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                },
+            })
+            .expect("the lexer only splits strings with interpolations");
+        Ok(Expr {
+            meta: open.to(close),
+            kind: expr.kind,
+        })
+    }
+
+    /// the next top level item, `None` at the end of the file
+    ///
+    /// the equations of a function are returned one by one, [Parser::next_function]
+    /// groups them
+    fn parse_item(&mut self) -> Result<Option<Item>, SimpleDiagnostic> {
+        let mut docs = Vec::new();
+        loop {
+            match self.peek() {
+                // 文件或者 `can` 块结束了
+                None
+                | Some(
+                    Token::EOF
+                    | Token::VirtualClose
+                    | Token::Delimiter(Delimiter::RBrace),
+                ) => return Ok(None),
+                Some(Token::VirtualSemi) => {}
+                Some(&Token::DocComment(doc)) => docs.push(doc),
+                Some(_) => break,
+            }
+            self.next_token();
+        }
+        let doc = self.join_docs(&docs);
+
+        let token = self.next_token().expect("peeked above");
+        let start = token.context;
+        if self.depth == 0 {
+            self.item_start = Some(start);
+        }
+        let construct = self.construct(token.value);
+        self.parse_item_from(token, doc)
+            .map(Some)
+            .map_err(|err| match construct {
+                Some(construct) => {
+                    let span = self.error_span(start, &err);
+                    within(err, &construct, span)
+                }
+                None => err,
+            })
+    }
+
+    /// what an item starting with `token` is, e.g. "type declaration"
+    fn construct(&self, token: Token) -> Option<String> {
+        let construct = match token {
+            Token::Keyword(Keyword::Infix | Keyword::InfixL | Keyword::InfixR) => {
+                "fixity declaration"
+            }
+            Token::Keyword(Keyword::Type) => "type declaration",
+            Token::Keyword(Keyword::Refine) => "refinement",
+            Token::Keyword(Keyword::Class) => "class declaration",
+            Token::Keyword(Keyword::Instance) => "instance declaration",
+            Token::Keyword(Keyword::Module) => "module header",
+            Token::Keyword(Keyword::Import) => "import",
+            Token::Keyword(Keyword::Export) => "export list",
+            Token::Identifier(name) => {
+                return Some(format!("definition of `{}`", self.resolve(name)));
+            }
+            _ => return None,
+        };
+        Some(construct.to_string())
+    }
+
+    /// the item starting with `token`, up to the separator after it
+    fn parse_item_from(
+        &mut self,
+        token: Spanned<Token>,
+        doc: Option<Symbol>,
+    ) -> Result<Item, SimpleDiagnostic> {
+        let item = match token.value {
+            Token::Keyword(Keyword::Infix | Keyword::InfixL | Keyword::InfixR) => {
+                let mut tokens = vec![token];
+                while !matches!(
+                    self.peek(),
+                    None | Some(Token::EOF | Token::VirtualSemi | Token::VirtualClose)
+                ) {
+                    tokens.extend(self.next_token());
+                }
+                let (decl, _) = parse_fixity_decl(&tokens)?;
+                Item::Fixity(decl)
+            }
+            Token::Keyword(Keyword::Type) => {
+                Item::Type(self.parse_type_decl(token.context, doc)?)
+            }
+            Token::Keyword(Keyword::Refine) => {
+                Item::Refine(self.parse_refine(token.context, doc)?)
+            }
+            Token::Keyword(Keyword::Class) => {
+                Item::Class(self.parse_class(token.context, doc)?)
+            }
+            Token::Keyword(Keyword::Instance) => {
+                Item::Instance(self.parse_instance(token.context, doc)?)
+            }
+            Token::Keyword(Keyword::Module) => {
+                let path = self.parse_module_path()?;
+                Item::Module(ModuleDecl {
+                    span: token.context.to(path.span),
+                    path,
+                })
+            }
+            Token::Keyword(Keyword::Import) => {
+                Item::Import(self.parse_import(token.context)?)
+            }
+            Token::Keyword(Keyword::Export) => {
+                let (names, close) = self.parse_name_list()?;
+                Item::Export(Export {
+                    names,
+                    span: token.context.to(close),
+                })
+            }
+            Token::Keyword(Keyword::Can) => {
+                // 方法由 [Parser::parse_methods] 一个一个地解析
+                self.consume(Token::Delimiter(Delimiter::LBrace))?;
+                let layout = self.peek() == Some(&Token::VirtualOpen);
+                if layout {
+                    self.next_token();
+                }
+                return Ok(Item::Can {
+                    span: token.context,
+                    layout,
+                });
+            }
+            Token::Identifier(name)
+                if self.peek() == Some(&Token::Delimiter(Delimiter::Annotate)) =>
+            {
+                let annotate = self.next_token().expect("peeked above");
+                let ty = self.parse_sig()?;
+                Item::Signature {
+                    name,
+                    span: token.context.to(annotate.context),
+                    ty,
+                    doc,
+                }
+            }
+            Token::Identifier(name) => {
+                let clause = self.parse_clause(token.context)?;
+                Item::Equation { name, clause, doc }
+            }
+            _ => {
+                self.put_back(token);
+                return Err(self.expected("a definition"));
+            }
+        };
+        self.end_item()?;
+        Ok(item)
+    }
+
+    /// the separator after an item, a `can` block may follow a type on the same line
+    fn end_item(&mut self) -> Result<(), SimpleDiagnostic> {
+        match self.peek() {
+            Some(
+                Token::EOF
+                | Token::VirtualClose
+                | Token::Delimiter(Delimiter::RBrace)
+                | Token::Keyword(Keyword::Can),
+            ) => Ok(()),
+            _ => self.consume(Token::VirtualSemi).map(drop),
+        }
+    }
+
+    /// the methods of a `can` block up to its closing `}`, added to `methods`
+    fn parse_methods(
+        &mut self,
+        can: Span,
+        layout: bool,
+        methods: &mut Vec<FuncDecl<Span>>,
+    ) -> Result<(), SimpleDiagnostic> {
+        let outer = self.methods.replace((Block::Can(can), HashMap::new()));
+        let self_type = std::mem::replace(&mut self.self_type, true);
+        let parsed = self.parse_functions(Block::Can(can), methods);
+        self.methods = outer;
+        self.self_type = self_type;
+        parsed?;
+
+        if layout {
+            self.consume(Token::VirtualClose)?;
+        }
+        self.consume(Token::Delimiter(Delimiter::RBrace))?;
+        self.end_item()
+    }
+
+    /// the bindings of a `let` or `where` block, they have their own scope
+    fn parse_bindings(
+        &mut self,
+        block: Block,
+    ) -> Result<Vec<FuncDecl<Span>>, SimpleDiagnostic> {
+        self.consume(Token::VirtualOpen)?;
+        let defined = std::mem::take(&mut self.defined);
+        let methods = self.methods.take();
+        let mut bindings = Vec::new();
+        let parsed = self.parse_functions(block, &mut bindings);
+        self.defined = defined;
+        self.methods = methods;
+        parsed?;
+        self.consume(Token::VirtualClose)?;
+        Ok(bindings)
+    }
+
+    /// the functions of a block up to its end, other items are reported
+    fn parse_functions(
+        &mut self,
+        block: Block,
+        functions: &mut Vec<FuncDecl<Span>>,
+    ) -> Result<(), SimpleDiagnostic> {
+        while let Some(item) = self.next_item()? {
+            match item {
+                Item::Signature { .. } | Item::Equation { .. } => {
+                    functions.extend(self.next_function(item));
+                }
+                item => self.misplaced(block, item)?,
+            }
         }
+        Ok(())
     }
 
-    fn parse_func_deck(&mut self) -> Result<FuncDecl<()>, String> {
-        if let Some(Token::Identifier(func_name)) = self.peek() {
-            // get the params
-            let mut params = vec![];
-            while let Some(tok) = self.next_token() {
-                if let Token::Identifier(param) = tok {
-                    params.push(param);
+    /// reports an item that can not be defined in `block`, the methods of a
+    /// misplaced `can` block are parsed and dropped
+    fn misplaced(&mut self, block: Block, item: Item) -> Result<(), SimpleDiagnostic> {
+        match item {
+            Item::Can { span, layout } => {
+                if let Block::Can(outer) = block {
+                    let message = "`can` blocks can not be nested".to_string();
+                    self.error(message, span, Some(note("the outer block", outer)));
                 } else {
-                    self.buffered_tokens.push_front(tok);
+                    self.error(block.only_functions(), span, None);
+                }
+                self.parse_methods(span, layout, &mut Vec::new())?;
+            }
+            item => self.error(block.only_functions(), item.span(), None),
+        }
+        Ok(())
+    }
+
+    /// `class context? Name params (where methods)?` after the `class` keyword at `start`
+    fn parse_class(
+        &mut self,
+        start: Span,
+        doc: Option<Symbol>,
+    ) -> Result<ClassDecl<Span>, SimpleDiagnostic> {
+        let (superclasses, head) = self.parse_context_and_head()?;
+        let Some((name, params)) = class_head(head.value) else {
+            return Err(SimpleDiagnostic::new(
+                Severity::Error,
+                "expected a class and its type variables like `Show a`".to_string(),
+                head.context,
+                None,
+            ));
+        };
+        let mut decl =
+            ClassDecl::new(name, params, superclasses, doc, start.to(head.context));
+        if self.peek() != Some(&Token::Keyword(Keyword::Where)) {
+            return Ok(decl);
+        }
+        self.next_token();
+        self.consume(Token::VirtualOpen)?;
+        let outer = self.methods.replace((Block::Class, HashMap::new()));
+        let parsed = self.parse_class_items(&mut decl);
+        self.methods = outer;
+        parsed?;
+        self.consume(Token::VirtualClose)?;
+
+        for default in &decl.defaults {
+            if decl.method(default.func_name).is_none() {
+                let message = format!(
+                    "`{}` is not a method of the class `{}`",
+                    self.resolve(default.func_name),
+                    self.resolve(decl.name),
+                );
+                let note = note("declare its signature in the class", head.context);
+                self.error(message, default.clauses[0].span, Some(note));
+            }
+        }
+        Ok(decl)
+    }
+
+    /// the signatures and default equations of a class up to the end of its block
+    fn parse_class_items(
+        &mut self,
+        decl: &mut ClassDecl<Span>,
+    ) -> Result<(), SimpleDiagnostic> {
+        while let Some(item) = self.next_item()? {
+            match item {
+                Item::Signature {
+                    name,
+                    span,
+                    ty,
+                    doc,
+                } => {
+                    let span = span.to(ty.span);
+                    if let Some(previous) = decl.method(name).map(|method| method.span)
+                    {
+                        let message = format!(
+                            "duplicate signature of `{}` in this `class` block",
+                            self.resolve(name)
+                        );
+                        self.error(
+                            message,
+                            span,
+                            Some(note("first declared here", previous)),
+                        );
+                    }
+                    decl.span = decl.span.to(span);
+                    decl.methods.push(ClassMethod {
+                        name,
+                        sig: ty,
+                        doc,
+                        span,
+                    });
+                }
+                Item::Equation { .. } => {
+                    if let Some(default) = self.next_function(item) {
+                        let last =
+                            default.clauses.last().expect("at least one equation");
+                        decl.span = decl.span.to(last.span);
+                        decl.defaults.push(default);
+                    }
+                }
+                item => self.misplaced(Block::Class, item)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// `instance context? Class types (where methods)?` after the `instance` keyword
+    /// at `start`
+    fn parse_instance(
+        &mut self,
+        start: Span,
+        doc: Option<Symbol>,
+    ) -> Result<InstanceDecl<Span>, SimpleDiagnostic> {
+        let (constraints, head) = self.parse_context_and_head()?;
+        let Some(Constraint { class, args }) = constraint_of(head.value) else {
+            return Err(SimpleDiagnostic::new(
+                Severity::Error,
+                "expected a class and its types like `Show Int`".to_string(),
+                head.context,
+                None,
+            ));
+        };
+        let mut decl =
+            InstanceDecl::new(constraints, class, args, doc, start.to(head.context));
+        if self.peek() != Some(&Token::Keyword(Keyword::Where)) {
+            return Ok(decl);
+        }
+        self.next_token();
+        self.consume(Token::VirtualOpen)?;
+        let outer = self.methods.replace((Block::Instance, HashMap::new()));
+        let parsed = self.parse_functions(Block::Instance, &mut decl.methods);
+        self.methods = outer;
+        parsed?;
+        self.consume(Token::VirtualClose)?;
+
+        if let Some(last) = decl.methods.last().and_then(|method| method.clauses.last())
+        {
+            decl.span = decl.span.to(last.span);
+        }
+        Ok(decl)
+    }
+
+    /// `context? head` of a class or an instance, e.g. `Show a => Show (Vec a)`
+    fn parse_context_and_head(
+        &mut self,
+    ) -> Result<(Vec<Constraint>, Spanned<Type>), SimpleDiagnostic> {
+        let ty = self.parse_type_application()?;
+        if self.peek() != Some(&Token::Operator(Operator::FatArrow)) {
+            return Ok((Vec::new(), ty));
+        }
+        self.next_token();
+        let constraints = constraints_of(ty)?;
+        Ok((constraints, self.parse_type_application()?))
+    }
+
+    /// `type Name params = data params { constructors }` or `type Name params = type`
+    /// after the `type` keyword at `start`
+    fn parse_type_decl(
+        &mut self,
+        start: Span,
+        doc: Option<Symbol>,
+    ) -> Result<TypeDecl<Span>, SimpleDiagnostic> {
+        let self_type = std::mem::replace(&mut self.self_type, true);
+        let decl = self.parse_type_decl_body(start, doc);
+        self.self_type = self_type;
+        decl
+    }
+
+    fn parse_type_decl_body(
+        &mut self,
+        start: Span,
+        doc: Option<Symbol>,
+    ) -> Result<TypeDecl<Span>, SimpleDiagnostic> {
+        let name = self.parse_name(true)?.value;
+        let mut params = self.parse_type_params(Vec::new())?;
+        self.consume(Token::Operator(Operator::Eq))?;
+
+        if self.peek() != Some(&Token::Keyword(Keyword::Data)) {
+            let ty = self.parse_type()?;
+            let span = start.to(ty.context);
+            return Ok(TypeDecl::new(
+                name,
+                params,
+                TypeBody::Alias(ty.value),
+                doc,
+                span,
+            ));
+        }
+        self.next_token();
+        params = self.parse_type_params(params)?;
+
+        self.consume(Token::Delimiter(Delimiter::LBrace))?;
+        let mut constructors = Vec::new();
+        if self.peek() != Some(&Token::Delimiter(Delimiter::RBrace)) {
+            loop {
+                constructors.push(self.parse_constructor()?);
+                if self.peek() != Some(&Token::Delimiter(Delimiter::Bar)) {
                     break;
                 }
+                self.next_token();
             }
-            // get the func body
+        }
+        let close = self.consume(Token::Delimiter(Delimiter::RBrace))?;
+        let body = TypeBody::Data(constructors);
+        Ok(TypeDecl::new(
+            name,
+            params,
+            body,
+            doc,
+            start.to(close.context),
+        ))
+    }
 
-            unimplemented!()
+    /// an identifier, `upper` tells whether it must start with an uppercase letter
+    /// like the name of a type or a constructor, or a lowercase one like a type variable
+    fn parse_name(&mut self, upper: bool) -> Result<Spanned<Symbol>, SimpleDiagnostic> {
+        let what = if upper {
+            "a type or constructor name"
         } else {
-            Err("func_decl should begin with an identifier")?
+            "a type variable"
+        };
+        match self.next_token() {
+            Some(Spanned {
+                context,
+                value: Token::Identifier(name),
+            }) if is_constructor(&self.resolve(name)) == upper => {
+                Ok(Spanned::new(context, name))
+            }
+            token => {
+                if let Some(token) = token {
+                    self.put_back(token);
+                }
+                Err(self.expected(what))
+            }
         }
     }
-}
 
-impl<'a> Iterator for Parser<'a> {
-    type Item = UntypedAST;
+    /// the type variables up to the next `=` or `{`, added to `params` unless already there
+    fn parse_type_params(
+        &mut self,
+        mut params: Vec<Symbol>,
+    ) -> Result<Vec<Symbol>, SimpleDiagnostic> {
+        while let Some(Token::Identifier(_)) = self.peek() {
+            let param = self.parse_name(false)?.value;
+            if !params.contains(&param) {
+                params.push(param);
+            }
+        }
+        Ok(params)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(tok) = self.peek() {
-            // TODO!
-            todo!();
-        } else {
-            return None;
+    /// `Cons a Self` or `Point { x :: Int, y :: Int }`
+    fn parse_constructor(&mut self) -> Result<Constructor, SimpleDiagnostic> {
+        let name = self.parse_name(true)?;
+        let mut span = name.context;
+
+        if self.peek() != Some(&Token::Delimiter(Delimiter::LBrace)) {
+            let mut fields = Vec::new();
+            while let Some(&token) = self.peek()
+                && self.starts_type(token)
+            {
+                let field = self.parse_type_atom()?;
+                span = span.to(field.context);
+                fields.push(field.value);
+            }
+            return Ok(Constructor {
+                name: name.value,
+                fields: Fields::Positional(fields),
+                span,
+            });
+        }
+
+        self.next_token();
+        let mut fields = Vec::new();
+        loop {
+            let field = self.parse_name(false)?;
+            self.consume(Token::Delimiter(Delimiter::Annotate))?;
+            let ty = self.parse_type()?;
+            fields.push(Field {
+                name: field.value,
+                ty: ty.value,
+                span: field.context.to(ty.context),
+            });
+            if self.peek() != Some(&Token::Delimiter(Delimiter::Comma)) {
+                break;
+            }
+            self.next_token();
+        }
+        let close = self.consume(Token::Delimiter(Delimiter::RBrace))?;
+        Ok(Constructor {
+            name: name.value,
+            fields: Fields::Named(fields),
+            span: span.to(close.context),
+        })
+    }
+
+    /// `refine Name params = type where predicate` after the `refine` keyword at `start`,
+    /// every line of the `where` block is a predicate and they must all hold
+    fn parse_refine(
+        &mut self,
+        start: Span,
+        doc: Option<Symbol>,
+    ) -> Result<RefineDecl, SimpleDiagnostic> {
+        let name = self.parse_name(true)?.value;
+        let params = self.parse_type_params(Vec::new())?;
+        self.consume(Token::Operator(Operator::Eq))?;
+        let base = self.parse_type()?.value;
+        self.consume(Token::Keyword(Keyword::Where))?;
+        self.consume(Token::VirtualOpen)?;
+
+        // `Vec.len` 里的 `Vec` 指被细化的值
+        let value = match &base {
+            Type::Con(head) => Some(*head),
+            Type::App { func, .. } => match **func {
+                Type::Con(head) => Some(head),
+                _ => None,
+            },
+            _ => None,
+        };
+        let mut predicate = self.parse_predicate(0, None, value)?;
+        loop {
+            match self.peek_spanned() {
+                Some(&Spanned {
+                    value: Token::VirtualSemi,
+                    ..
+                }) => {
+                    self.next_token();
+                    let next = self.parse_predicate(0, None, value)?;
+                    predicate = Spanned::new(
+                        predicate.context.to(next.context),
+                        Predicate::And(Box::new(predicate.value), Box::new(next.value)),
+                    );
+                }
+                Some(&Spanned {
+                    context,
+                    value: Token::Operator(op),
+                }) => {
+                    let message =
+                        format!("`{}` can not be used in a refinement", op.as_str());
+                    return Err(SimpleDiagnostic::new(
+                        Severity::Error,
+                        message,
+                        context,
+                        None,
+                    ));
+                }
+                _ => break,
+            }
+        }
+        self.consume(Token::VirtualClose)?;
+        Ok(RefineDecl::new(
+            name,
+            params,
+            base,
+            predicate.value,
+            doc,
+            start.to(predicate.context),
+        ))
+    }
+
+    /// the predicate of a refinement, operators have their usual fixities,
+    /// `value` is the head of the base type, which names the refined value like `self`
+    fn parse_predicate(
+        &mut self,
+        min_bp: u8,
+        parent: Option<(Symbol, Fixity)>,
+        value: Option<Symbol>,
+    ) -> Result<Spanned<Predicate>, SimpleDiagnostic> {
+        let mut lhs = self.parse_predicate_term(value)?;
+        let mut previous = parent;
+        while let Some(&token) = self.peek_spanned()
+            && let Token::Operator(op) = token.value
+            && let Some(fixity) = predicate_fixity(op)
+        {
+            let (left_bp, right_bp) = binding_power(fixity);
+            if left_bp < min_bp {
+                break;
+            }
+            let op_symbol = self.intern(op.as_str());
+            self.check_mix(previous, (op_symbol, fixity), token.context)?;
+
+            self.next_token();
+            let rhs =
+                self.parse_predicate(right_bp, Some((op_symbol, fixity)), value)?;
+            let predicate = combine_predicates(op, lhs.value, rhs.value);
+            lhs = Spanned::new(lhs.context.to(rhs.context), predicate);
+            previous = Some((op_symbol, fixity));
+        }
+        Ok(lhs)
+    }
+
+    /// `!term`, a literal, the refined value, a parenthesised predicate,
+    /// followed by calls to measures like `.len`
+    fn parse_predicate_term(
+        &mut self,
+        value: Option<Symbol>,
+    ) -> Result<Spanned<Predicate>, SimpleDiagnostic> {
+        let Some(token) = self.next_token() else {
+            return Err(self.expected("a predicate"));
+        };
+        let mut term = match token.value {
+            Token::Operator(Operator::Not) => {
+                let operand = self.parse_predicate_term(value)?;
+                return Ok(Spanned::new(
+                    token.context.to(operand.context),
+                    Predicate::Not(Box::new(operand.value)),
+                ));
+            }
+            Token::Literal(literal) => {
+                self.check_literal(literal, token.context);
+                Spanned::new(token.context, Predicate::Literal(literal))
+            }
+            Token::Identifier(name)
+                if self.resolve(name) == "self" || Some(name) == value =>
+            {
+                Spanned::new(token.context, Predicate::Value)
+            }
+            Token::Identifier(name) => {
+                let mut message = format!(
+                    "`{}` is not the refined value, refer to it as `self`",
+                    self.resolve(name)
+                );
+                if let Some(value) = value {
+                    message.push_str(&format!(" or `{}`", self.resolve(value)));
+                }
+                return Err(SimpleDiagnostic::new(
+                    Severity::Error,
+                    message,
+                    token.context,
+                    None,
+                ));
+            }
+            Token::Delimiter(Delimiter::LParen) => {
+                let inner = self.parse_predicate(0, None, value)?;
+                let close = self.consume(Token::Delimiter(Delimiter::RParen))?;
+                Spanned::new(token.context.to(close.context), inner.value)
+            }
+            _ => {
+                self.put_back(token);
+                return Err(self.expected("a predicate"));
+            }
+        };
+        while self.peek() == Some(&Token::Delimiter(Delimiter::Dot)) {
+            let measure = self.parse_method_name()?;
+            term = Spanned::new(
+                term.context.to(measure.context),
+                Predicate::Measure {
+                    receiver: Box::new(term.value),
+                    measure: measure.value,
+                },
+            );
+        }
+        Ok(term)
+    }
+
+    /// `Foo.Bar`
+    fn parse_module_path(&mut self) -> Result<ModulePath, SimpleDiagnostic> {
+        let first = self.parse_name(true)?;
+        let mut segments = vec![first.value];
+        let mut span = first.context;
+        while self.peek() == Some(&Token::Delimiter(Delimiter::Dot)) {
+            self.next_token();
+            let segment = self.parse_name(true)?;
+            segments.push(segment.value);
+            span = span.to(segment.context);
+        }
+        Ok(ModulePath { segments, span })
+    }
+
+    /// `import Foo.Bar (as F)? ((names) | hiding (names))?` after the `import` at `start`
+    fn parse_import(&mut self, start: Span) -> Result<Import, SimpleDiagnostic> {
+        let module = self.parse_module_path()?;
+        let mut span = start.to(module.span);
+
+        let mut alias = None;
+        if let Some(&token) = self.peek()
+            && self.is_word(token, "as")
+        {
+            self.next_token();
+            let name = self.parse_name(true)?;
+            alias = Some(name.value);
+            span = span.to(name.context);
+        }
+
+        let names = match self.peek().copied() {
+            Some(token) if self.is_word(token, "hiding") => {
+                self.next_token();
+                let (names, close) = self.parse_name_list()?;
+                span = span.to(close);
+                ImportNames::Hiding(names)
+            }
+            Some(Token::Delimiter(Delimiter::LParen)) => {
+                let (names, close) = self.parse_name_list()?;
+                span = span.to(close);
+                ImportNames::Only(names)
+            }
+            _ => ImportNames::All,
+        };
+        Ok(Import {
+            module,
+            alias,
+            names,
+            span,
+        })
+    }
+
+    /// `(x, Vec, (<+>))`, returns the names and the span of the `)`
+    fn parse_name_list(&mut self) -> Result<(Vec<ListedName>, Span), SimpleDiagnostic> {
+        self.consume(Token::Delimiter(Delimiter::LParen))?;
+        let mut names = Vec::new();
+        while self.peek() != Some(&Token::Delimiter(Delimiter::RParen)) {
+            let Some(token) = self.next_token() else {
+                return Err(self.expected("a name"));
+            };
+            let name = match token.value {
+                Token::Identifier(name) => ListedName {
+                    name,
+                    span: token.context,
+                },
+                Token::Delimiter(Delimiter::LParen) => {
+                    let op = match self.next_token() {
+                        Some(Spanned {
+                            value: Token::SymbolicIdentifier(op),
+                            ..
+                        }) => op,
+                        Some(Spanned {
+                            value: Token::Operator(op),
+                            ..
+                        }) => self.intern(op.as_str()),
+                        token => {
+                            if let Some(token) = token {
+                                self.put_back(token);
+                            }
+                            return Err(self.expected("an operator"));
+                        }
+                    };
+                    let close = self.consume(Token::Delimiter(Delimiter::RParen))?;
+                    ListedName {
+                        name: op,
+                        span: token.context.to(close.context),
+                    }
+                }
+                _ => {
+                    self.put_back(token);
+                    return Err(self.expected("a name"));
+                }
+            };
+            names.push(name);
+            if self.peek() != Some(&Token::Delimiter(Delimiter::Comma)) {
+                break;
+            }
+            self.next_token();
+        }
+        let close = self.consume(Token::Delimiter(Delimiter::RParen))?;
+        Ok((names, close.context))
+    }
+
+    /// `context? type (with effects)?`, e.g. `Show a => a -> () with Out Console`
+    pub fn parse_sig(&mut self) -> Result<Sig, SimpleDiagnostic> {
+        let mut ty = self.parse_type()?;
+        let start = ty.context;
+        let mut constraints = Vec::new();
+        if self.peek() == Some(&Token::Operator(Operator::FatArrow)) {
+            self.next_token();
+            constraints = constraints_of(ty)?;
+            ty = self.parse_type()?;
+        }
+
+        let mut span = start.to(ty.context);
+        let mut effects = Vec::new();
+        if let Some(&token) = self.peek()
+            && self.is_with(token)
+        {
+            self.next_token();
+            loop {
+                let effect = self.parse_type_application()?;
+                span = span.to(effect.context);
+                effects.push(effect.value);
+                if self.peek() != Some(&Token::Delimiter(Delimiter::Comma)) {
+                    break;
+                }
+                self.next_token();
+            }
         }
+        Ok(Sig {
+            constraints,
+            ty: ty.value,
+            effects,
+            span,
+        })
+    }
+
+    /// `with` only separates the effects in a signature, elsewhere it is a name
+    fn is_with(&self, token: Token) -> bool {
+        self.is_word(token, "with")
+    }
+
+    /// whether `token` is the identifier `word`, for words that are only keywords in
+    /// some places like `with`, `as` and `hiding`
+    fn is_word(&self, token: Token, word: &str) -> bool {
+        token == Token::Identifier(self.intern(word))
+    }
+
+    /// `btype (-> type)?`, arrows are right associative
+    fn parse_type(&mut self) -> Result<Spanned<Type>, SimpleDiagnostic> {
+        let param = self.parse_type_application()?;
+        if self.peek() != Some(&Token::Operator(Operator::Arrow)) {
+            return Ok(param);
+        }
+        self.next_token();
+        let result = self.parse_type()?;
+        Ok(Spanned::new(
+            param.context.to(result.context),
+            Type::Arrow {
+                param: Box::new(param.value),
+                result: Box::new(result.value),
+            },
+        ))
+    }
+
+    /// `Vec Int`, juxtaposition of type atoms
+    fn parse_type_application(&mut self) -> Result<Spanned<Type>, SimpleDiagnostic> {
+        let func = self.parse_type_atom()?;
+        let mut span = func.context;
+        let mut args = Vec::new();
+        while let Some(&token) = self.peek()
+            && self.starts_type(token)
+        {
+            let arg = self.parse_type_atom()?;
+            span = span.to(arg.context);
+            args.push(arg.value);
+        }
+        if args.is_empty() {
+            return Ok(func);
+        }
+        Ok(Spanned::new(
+            span,
+            Type::App {
+                func: Box::new(func.value),
+                args,
+            },
+        ))
+    }
+
+    fn starts_type(&self, token: Token) -> bool {
+        match token {
+            Token::Identifier(_) => !self.is_with(token),
+            Token::Delimiter(Delimiter::LParen) => true,
+            _ => false,
+        }
+    }
+
+    /// a type variable, a named type, `()`, a tuple or a parenthesised type
+    fn parse_type_atom(&mut self) -> Result<Spanned<Type>, SimpleDiagnostic> {
+        let token = match self.next_token() {
+            Some(token) if self.starts_type(token.value) => token,
+            token => {
+                if let Some(token) = token {
+                    self.put_back(token);
+                }
+                return Err(self.expected("a type"));
+            }
+        };
+        let Token::Identifier(name) = token.value else {
+            // `(`
+            if let Some(&Token::Delimiter(Delimiter::RParen)) = self.peek() {
+                let close = self.next_token().expect("peeked above");
+                return Ok(Spanned::new(token.context.to(close.context), Type::Unit));
+            }
+            let mut types = vec![self.parse_type()?.value];
+            while self.peek() == Some(&Token::Delimiter(Delimiter::Comma)) {
+                self.next_token();
+                types.push(self.parse_type()?.value);
+            }
+            let close = self.consume(Token::Delimiter(Delimiter::RParen))?;
+            let ty = if types.len() == 1 {
+                types.pop().expect("one type")
+            } else {
+                Type::Tuple(types)
+            };
+            return Ok(Spanned::new(token.context.to(close.context), ty));
+        };
+
+        let text = self.resolve(name);
+        if text == "Self" && !self.self_type {
+            let message =
+                "`Self` can only be used in a type declaration and its `can` block"
+                    .to_string();
+            self.error(message, token.context, None);
+        }
+        let ty = if is_constructor(&text) {
+            Type::Con(name)
+        } else {
+            Type::Var(name)
+        };
+        Ok(Spanned::new(token.context, ty))
+    }
+
+    /// the doc comments of one item as a single text
+    fn join_docs(&self, docs: &[Symbol]) -> Option<Symbol> {
+        if docs.is_empty() {
+            return None;
+        }
+        let mut interner = self.interner.borrow_mut();
+        let text = docs
+            .iter()
+            .map(|&doc| interner.resolve(doc).unwrap_or_default().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        Some(interner.intern_or_get(text))
+    }
+
+    /// `pat* = expr (where bindings)?` after the name of the function, which is at `start`
+    fn parse_clause(&mut self, start: Span) -> Result<Clause<Span>, SimpleDiagnostic> {
+        let mut params = Vec::new();
+        while self.peek() != Some(&Token::Operator(Operator::Eq)) {
+            if !self.peek().is_some_and(|&token| starts_pattern(token)) {
+                return Err(self.expected("a parameter or `=`"));
+            }
+            params.push(self.parse_pattern_atom()?);
+        }
+        self.next_token();
+        let body = self.parse_expr()?;
+        let mut where_bindings = Vec::new();
+        if self.peek() == Some(&Token::Keyword(Keyword::Where)) {
+            self.next_token();
+            where_bindings = self.parse_bindings(Block::Where)?;
+        }
+        Ok(Clause {
+            params,
+            span: start.to(body.meta),
+            body,
+            where_bindings,
+        })
+    }
+
+    /// a pattern of a `match` arm or inside parentheses, a constructor takes
+    /// arguments and the whole pattern can be named, e.g. `Cons x _ @ list`
+    fn parse_pattern(&mut self) -> Result<Pattern, SimpleDiagnostic> {
+        let mut pattern = self.parse_pattern_atom()?;
+        if let PatternKind::Constructor { args, .. } = &mut pattern.kind
+            && args.is_empty()
+        {
+            while self.peek().is_some_and(|&token| starts_pattern(token)) {
+                let arg = self.parse_pattern_atom()?;
+                pattern.span = pattern.span.to(arg.span);
+                args.push(arg);
+            }
+        }
+        while self.peek() == Some(&Token::Delimiter(Delimiter::At)) {
+            self.next_token();
+            let name = match self.next_token() {
+                Some(Spanned {
+                    context,
+                    value: Token::Identifier(name),
+                }) if !is_constructor(&self.resolve(name)) => {
+                    Spanned::new(context, name)
+                }
+                token => {
+                    if let Some(token) = token {
+                        self.put_back(token);
+                    }
+                    return Err(self.expected("a variable after `@`"));
+                }
+            };
+            let span = pattern.span.to(name.context);
+            pattern = Pattern::new(
+                PatternKind::Alias {
+                    name: name.value,
+                    pattern: Box::new(pattern),
+                },
+                span,
+            );
+        }
+        Ok(pattern)
+    }
+
+    /// `_`, a literal, a variable, a refinement, a constructor without arguments or a
+    /// parenthesised pattern like `(Cons x xs)` or `(x :: OneElementIntVec)`
+    fn parse_pattern_atom(&mut self) -> Result<Pattern, SimpleDiagnostic> {
+        let Some(token) = self.next_token() else {
+            return Err(self.expected("a pattern"));
+        };
+        let kind = match token.value {
+            Token::Delimiter(Delimiter::UnderScore) => PatternKind::Wildcard,
+            Token::Literal(literal) => {
+                self.check_literal(literal, token.context);
+                PatternKind::Literal(literal)
+            }
+            Token::Identifier(name) if self.refinements.contains(&name) => {
+                PatternKind::Refinement(name)
+            }
+            Token::Identifier(name) if is_constructor(&self.resolve(name)) => {
+                PatternKind::Constructor { name, args: vec![] }
+            }
+            Token::Identifier(name) => PatternKind::Variable(name),
+            Token::Delimiter(Delimiter::LParen) => {
+                let pattern = self.parse_pattern()?;
+                let annotation =
+                    if self.peek() == Some(&Token::Delimiter(Delimiter::Annotate)) {
+                        self.next_token();
+                        Some(self.parse_type()?.value)
+                    } else {
+                        None
+                    };
+                let close = self.consume(Token::Delimiter(Delimiter::RParen))?;
+                let span = token.context.to(close.context);
+                return Ok(match annotation {
+                    Some(ty) => Pattern::new(
+                        PatternKind::Annotated {
+                            pattern: Box::new(pattern),
+                            ty,
+                        },
+                        span,
+                    ),
+                    None => Pattern { span, ..pattern },
+                });
+            }
+            _ => {
+                self.put_back(token);
+                return Err(self.expected("a pattern"));
+            }
+        };
+        Ok(Pattern::new(kind, token.context))
+    }
+
+    fn resolve(&self, sym: Symbol) -> String {
+        self.interner
+            .borrow()
+            .resolve(sym)
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// the next item, `None` at the end of the file or block
+    fn next_item(&mut self) -> Result<Option<Item>, SimpleDiagnostic> {
+        if let Some(item) = self.lookahead.take() {
+            return item.map(Some);
+        }
+        self.parse_item()
+    }
+
+    /// groups `first` with the equations of the same function that directly follow it
+    ///
+    /// the equations of a function must be adjacent and have the same number of
+    /// parameters, a signature must be followed by the equations
+    fn next_function(&mut self, first: Item) -> Option<FuncDecl<Span>> {
+        let (name, sig, doc, first_clause) = match first {
+            Item::Signature {
+                name,
+                span,
+                ty,
+                doc,
+            } => match self.next_item() {
+                Ok(Some(Item::Equation {
+                    name: equation,
+                    clause,
+                    doc: equation_doc,
+                })) if equation == name => {
+                    (name, Some(ty), doc.or(equation_doc), clause)
+                }
+                next => {
+                    self.lookahead = next.transpose();
+                    let message = format!(
+                        "the signature of `{}` is not followed by its equations",
+                        self.resolve(name)
+                    );
+                    self.error(message, span, None);
+                    return None;
+                }
+            },
+            Item::Equation { name, clause, doc } => (name, None, doc, clause),
+            _ => unreachable!("only functions have equations"),
+        };
+
+        let mut clauses = vec![first_clause];
+        loop {
+            match self.next_item() {
+                Ok(Some(Item::Equation {
+                    name: equation,
+                    clause,
+                    ..
+                })) if equation == name => {
+                    let arity = clauses[0].params.len();
+                    if clause.params.len() != arity {
+                        let message = format!(
+                            "this equation of `{}` has {} parameters, but the first one has {arity}",
+                            self.resolve(name),
+                            clause.params.len(),
+                        );
+                        let note = note("the first equation", clauses[0].span);
+                        self.error(message, clause.span, Some(note));
+                    }
+                    clauses.push(clause);
+                }
+                next => {
+                    self.lookahead = next.transpose();
+                    break;
+                }
+            }
+        }
+
+        let span = clauses[0].span;
+        let previous = match &mut self.methods {
+            Some((_, methods)) => methods.insert(name, span),
+            None => self.defined.insert(name, span),
+        };
+        if let Some(previous) = previous {
+            let name = self.resolve(name);
+            let (message, note) = if let Some((block, _)) = &self.methods {
+                let message = format!(
+                    "duplicate method `{name}` in this `{}` block",
+                    block.keyword()
+                );
+                (message, note("first defined here", previous))
+            } else {
+                let message = format!("the equations of `{name}` are not adjacent");
+                (message, note("the previous equations", previous))
+            };
+            self.error(message, span, Some(note));
+        }
+        Some(FuncDecl::new(name, sig, clauses, doc))
+    }
+
+    /// from `start` to `err` and the tokens consumed before it
+    fn error_span(&self, start: Span, err: &SimpleDiagnostic) -> Span {
+        // 虚拟 Token 的 span 为空，在下一行的开头
+        [self.previous, Some(err.primary_span)]
+            .into_iter()
+            .flatten()
+            .filter(|end| !end.is_empty() && end.start() >= start.start())
+            .fold(start, Span::to)
+    }
+
+    /// reports a syntax error and skips to the next line of the file, returns the
+    /// span from `start` over the error and the skipped tokens
+    fn recover(&mut self, err: SimpleDiagnostic, start: Span) -> Span {
+        let mut span = self.error_span(start, &err);
+        self.diagnostics.push(err);
+        while let Some(token) = self.next_token() {
+            match token.value {
+                Token::EOF => {
+                    self.put_back(token);
+                    break;
+                }
+                // 块里的换行不是顶层定义的结束
+                Token::VirtualSemi if self.depth <= 0 => break,
+                Token::VirtualOpen | Token::VirtualSemi | Token::VirtualClose => {}
+                _ => span = span.to(token.context),
+            }
+        }
+        // 多余的 VirtualClose 或 `}` 已经被跳过了
+        self.depth = 0;
+        span
+    }
+
+    fn error(&mut self, message: String, span: Span, child: Option<SimpleDiagnostic>) {
+        self.diagnostics.push(SimpleDiagnostic::new(
+            Severity::Error,
+            message,
+            span,
+            child.map(Box::new),
+        ));
+    }
+
+    /// the diagnostics of everything parsed so far
+    pub fn diagnostics(&self) -> &[SimpleDiagnostic] {
+        &self.diagnostics
+    }
+}
+
+/// a top level item, before equations are grouped into functions
+#[derive(Debug, Clone)]
+enum Item {
+    /// `name :: type`, `span` covers `name ::`
+    Signature {
+        name: Symbol,
+        span: Span,
+        ty: Sig,
+        doc: Option<Symbol>,
+    },
+    Equation {
+        name: Symbol,
+        clause: Clause<Span>,
+        doc: Option<Symbol>,
+    },
+    Fixity(FixityDecl),
+    Type(TypeDecl<Span>),
+    Refine(RefineDecl),
+    Module(ModuleDecl),
+    Import(Import),
+    Export(Export),
+    Class(ClassDecl<Span>),
+    Instance(InstanceDecl<Span>),
+    /// `can {`, the methods follow as items, `layout` tells whether they form a block
+    Can {
+        span: Span,
+        layout: bool,
+    },
+}
+
+impl Item {
+    fn span(&self) -> Span {
+        match self {
+            Item::Signature { span, .. } | Item::Can { span, .. } => *span,
+            Item::Equation { clause, .. } => clause.span,
+            Item::Fixity(decl) => decl.span,
+            Item::Type(decl) => decl.span,
+            Item::Refine(decl) => decl.span,
+            Item::Module(decl) => decl.span,
+            Item::Import(import) => import.span,
+            Item::Export(export) => export.span,
+            Item::Class(decl) => decl.span,
+            Item::Instance(decl) => decl.span,
+        }
+    }
+}
+
+/// a block of functions nested in a type declaration or an expression
+#[derive(Debug, Clone, Copy)]
+enum Block {
+    /// the methods of a type, with the span of `can`
+    Can(Span),
+    Let,
+    Where,
+    Class,
+    Instance,
+}
+
+impl Block {
+    fn only_functions(self) -> String {
+        match self {
+            Block::Can(_) => "only methods can be defined in a `can` block",
+            Block::Let => "only functions can be defined in a `let` block",
+            Block::Where => "only functions can be defined in a `where` block",
+            Block::Class => {
+                "only method signatures and default methods can be defined in a `class` block"
+            }
+            Block::Instance => "only methods can be defined in an `instance` block",
+        }
+        .to_string()
+    }
+
+    /// the keyword opening the block
+    fn keyword(self) -> &'static str {
+        match self {
+            Block::Can(_) => "can",
+            Block::Let => "let",
+            Block::Where => "where",
+            Block::Class => "class",
+            Block::Instance => "instance",
+        }
+    }
+}
+
+/// `Show a` or `(Show a, Eq b)` parsed as a type before `=>`
+fn constraints_of(ty: Spanned<Type>) -> Result<Vec<Constraint>, SimpleDiagnostic> {
+    let types = match ty.value {
+        Type::Tuple(types) => types,
+        Type::Unit => Vec::new(),
+        ty => vec![ty],
+    };
+    types
+        .into_iter()
+        .map(constraint_of)
+        .collect::<Option<_>>()
+        .ok_or_else(|| {
+            SimpleDiagnostic::new(
+                Severity::Error,
+                "expected constraints like `Show a` before `=>`".to_string(),
+                ty.context,
+                None,
+            )
+        })
+}
+
+/// how much `token` nests the tokens after it in blocks
+fn nesting(token: Token) -> i32 {
+    match token {
+        Token::VirtualOpen | Token::Delimiter(Delimiter::LBrace) => 1,
+        Token::VirtualClose | Token::Delimiter(Delimiter::RBrace) => -1,
+        _ => 0,
+    }
+}
+
+/// adds a note pointing at the construct `err` is in, which covers `span`,
+/// unless it already has one from an inner construct
+fn within(mut err: SimpleDiagnostic, construct: &str, span: Span) -> SimpleDiagnostic {
+    if err.children.is_none() {
+        let message = format!("while parsing this {construct}");
+        err.children = Some(Box::new(note(&message, span)));
+    }
+    err
+}
+
+/// `Show a` parsed as a type, a class applied to types
+fn constraint_of(ty: Type) -> Option<Constraint> {
+    match ty {
+        Type::App { func, args } => match *func {
+            Type::Con(class) => Some(Constraint { class, args }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// the class and its type variables of the head of a class, e.g. `Show a`
+fn class_head(ty: Type) -> Option<(Symbol, Vec<Symbol>)> {
+    let Constraint { class, args } = constraint_of(ty)?;
+    let params = args
+        .into_iter()
+        .map(|arg| match arg {
+            Type::Var(param) => Some(param),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    Some((class, params))
+}
+
+/// parses a signature on its own, e.g. the type of a `:type` query in the REPL
+pub fn parse_sig(
+    src: &str,
+    interner: Rc<RefCell<Interner>>,
+) -> Result<Sig, SimpleDiagnostic> {
+    let lexer = Lexer::with_config(src, LexerConfig::default(), interner.clone());
+    let mut parser = Parser::new(lexer, interner);
+    let sig = parser.parse_sig()?;
+    parser.consume(Token::EOF)?;
+    Ok(sig)
+}
+
+fn note(message: &str, span: Span) -> SimpleDiagnostic {
+    SimpleDiagnostic::new(Severity::Note, message.to_string(), span, None)
+}
+
+/// constructors start with an uppercase letter, e.g. `Just` or `Nil`
+fn is_constructor(name: &str) -> bool {
+    name.starts_with(char::is_uppercase)
+}
+
+/// the names of the refinements declared in `tokens`, they can be used as patterns
+/// before their declarations
+fn collect_refinements(tokens: &[Spanned<Token>]) -> HashSet<Symbol> {
+    tokens
+        .windows(2)
+        .filter_map(|pair| match (pair[0].value, pair[1].value) {
+            (Token::Keyword(Keyword::Refine), Token::Identifier(name)) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// whether `token` can start a parameter of an equation
+fn starts_pattern(token: Token) -> bool {
+    matches!(
+        token,
+        Token::Identifier(_)
+            | Token::Literal(_)
+            | Token::Delimiter(Delimiter::UnderScore | Delimiter::LParen)
+    )
+}
+
+/// the binding powers on the left and right of an operator, a higher power binds tighter
+fn binding_power(fixity: Fixity) -> (u8, u8) {
+    let power = fixity.precedence * 2 + 1;
+    match fixity.associativity {
+        Associativity::Right => (power + 1, power),
+        Associativity::Left | Associativity::None => (power, power + 1),
+    }
+}
+
+/// how a fixity is declared, e.g. `infixl 6`
+fn describe(fixity: Fixity) -> String {
+    let keyword = match fixity.associativity {
+        Associativity::Left => "infixl",
+        Associativity::Right => "infixr",
+        Associativity::None => "infix",
+    };
+    format!("{keyword} {}", fixity.precedence)
+}
+
+/// the fixity of an operator of refinement predicates, `=` compares like `==`
+fn predicate_fixity(op: Operator) -> Option<Fixity> {
+    match op {
+        Operator::Eq => builtin_fixity(Operator::EqEq),
+        Operator::Add
+        | Operator::Negate
+        | Operator::Mul
+        | Operator::Div
+        | Operator::Mod
+        | Operator::EqEq
+        | Operator::NotEq
+        | Operator::Lt
+        | Operator::Le
+        | Operator::Gt
+        | Operator::Ge
+        | Operator::And
+        | Operator::Or => builtin_fixity(op),
+        _ => None,
+    }
+}
+
+/// `lhs op rhs` for an operator accepted by [predicate_fixity]
+fn combine_predicates(op: Operator, lhs: Predicate, rhs: Predicate) -> Predicate {
+    let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+    let arith = match op {
+        Operator::Add => ArithOp::Add,
+        Operator::Negate => ArithOp::Sub,
+        Operator::Mul => ArithOp::Mul,
+        Operator::Div => ArithOp::Div,
+        Operator::Mod => ArithOp::Mod,
+        _ => {
+            let compare = match op {
+                Operator::Eq | Operator::EqEq => CompareOp::Eq,
+                Operator::NotEq => CompareOp::NotEq,
+                Operator::Lt => CompareOp::Lt,
+                Operator::Le => CompareOp::Le,
+                Operator::Gt => CompareOp::Gt,
+                Operator::Ge => CompareOp::Ge,
+                Operator::And => return Predicate::And(lhs, rhs),
+                Operator::Or => return Predicate::Or(lhs, rhs),
+                _ => unreachable!("`{}` is not an operator of predicates", op.as_str()),
+            };
+            return Predicate::Compare {
+                op: compare,
+                lhs,
+                rhs,
+            };
+        }
+    };
+    Predicate::Arith {
+        op: arith,
+        lhs,
+        rhs,
+    }
+}
+
+/// whether `token` can start an argument of an application
+fn starts_atom(token: Token) -> bool {
+    matches!(
+        token,
+        Token::Identifier(_)
+            | Token::Literal(_)
+            | Token::StringStart
+            | Token::Delimiter(Delimiter::LParen | Delimiter::LBracket)
+            | Token::Error
+    )
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = UntypedAST;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let definition = self.next_definition();
+        self.started |= definition.is_some();
+        definition
+    }
+}
+
+impl Parser<'_> {
+    fn next_definition(&mut self) -> Option<UntypedAST> {
+        loop {
+            let err = match self.next_item() {
+                Ok(Some(item)) => match self.definition(item) {
+                    Some(definition) => return Some(definition),
+                    None => continue,
+                },
+                Ok(None) if matches!(self.peek(), None | Some(Token::EOF)) => {
+                    return None;
+                }
+                // 顶层多余的 `}` 或者 VirtualClose
+                Ok(None) => {
+                    self.item_start = None;
+                    self.expected("a definition")
+                }
+                Err(err) => err,
+            };
+            let start = self.item_start.take().unwrap_or(err.primary_span);
+            let span = self.recover(err, start);
+            return Some(UntypedAST::Error(span));
+        }
+    }
+
+    /// the definition of a top level item, `None` for the items reported as misplaced
+    fn definition(&mut self, item: Item) -> Option<UntypedAST> {
+        match item {
+            Item::Fixity(decl) => Some(UntypedAST::Fixity(decl)),
+            Item::Refine(decl) => Some(UntypedAST::Refine(decl)),
+            Item::Module(decl) => {
+                if self.started {
+                    let message =
+                        "the module header must be the first line of the file"
+                            .to_string();
+                    self.error(message, decl.span, None);
+                }
+                Some(UntypedAST::Module(decl))
+            }
+            Item::Import(import) => Some(UntypedAST::Import(import)),
+            Item::Export(export) => Some(UntypedAST::Export(export)),
+            Item::Class(decl) => Some(UntypedAST::Class(decl)),
+            Item::Instance(decl) => Some(UntypedAST::Instance(decl)),
+            Item::Type(mut decl) => {
+                match self.next_item() {
+                    Ok(Some(Item::Can { span, layout })) => {
+                        // 出错之前的方法留在类型声明里
+                        if let Err(err) =
+                            self.parse_methods(span, layout, &mut decl.methods)
+                        {
+                            self.recover(err, span);
+                        }
+                    }
+                    next => self.lookahead = next.transpose(),
+                }
+                let errors = resolve_self_calls(&mut decl, &self.interner.borrow());
+                self.diagnostics.extend(errors);
+                Some(UntypedAST::TypeDecl(decl))
+            }
+            Item::Can { span, layout } => {
+                let message =
+                    "a `can` block must follow a type declaration".to_string();
+                self.error(message, span, None);
+                let err = self.parse_methods(span, layout, &mut Vec::new()).err()?;
+                Some(UntypedAST::Error(self.recover(err, span)))
+            }
+            item => self.next_function(item).map(UntypedAST::FuncDecl),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// the expression as an s-expression, or the message and source text of the error
+    fn parse_with(
+        src: &str,
+        decls: &[(&str, Fixity)],
+    ) -> Result<String, (String, String)> {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let decls: Vec<_> = decls
+            .iter()
+            .map(|&(op, fixity)| FixityDecl {
+                fixity,
+                operators: vec![interner.borrow_mut().intern_or_get(op)],
+                span: Span::empty(0u32),
+            })
+            .collect();
+        let (fixities, errors) = FixityTable::from_decls(&decls, &interner.borrow());
+        assert!(errors.is_empty());
+
+        let lexer = Lexer::new(src, 4, interner.clone());
+        let mut parser = Parser::new(lexer, interner.clone());
+        parser.fixities = fixities;
+        let result = parser.parse_expr();
+        let interner = interner.borrow();
+        match result {
+            Ok(expr) => {
+                let span = expr.meta.start() as usize..expr.meta.end() as usize;
+                assert_eq!(src.trim(), &src[span], "the span covers the expression");
+                Ok(render(&expr.kind, &interner))
+            }
+            Err(err) => {
+                let span = err.primary_span;
+                let text = &src[span.start() as usize..span.end() as usize];
+                Err((err.message, text.to_string()))
+            }
+        }
+    }
+
+    fn parse(src: &str) -> Result<String, (String, String)> {
+        parse_with(src, &[])
+    }
+
+    fn render(kind: &ExprKind<Span>, interner: &Interner) -> String {
+        let name = |sym| interner.resolve(sym).unwrap().to_string();
+        match kind {
+            ExprKind::Object(sym) => name(*sym),
+            ExprKind::Qualified { module, name: sym } => {
+                format!("{}.{}", name(*module), name(*sym))
+            }
+            ExprKind::Literal(Literal::String(sym)) => format!("{:?}", name(*sym)),
+            ExprKind::Literal(Literal::Integer(value)) => value.to_string(),
+            ExprKind::Literal(literal) => format!("{literal:?}"),
+            ExprKind::Application { func, args } => {
+                let args: Vec<_> =
+                    args.iter().map(|arg| render(&arg.kind, interner)).collect();
+                format!("({} {})", render(&func.kind, interner), args.join(" "))
+            }
+            ExprKind::Binary { op, lhs, rhs } => format!(
+                "({} {} {})",
+                name(*op),
+                render(&lhs.kind, interner),
+                render(&rhs.kind, interner)
+            ),
+            ExprKind::Negate(operand) => {
+                format!("(neg {})", render(&operand.kind, interner))
+            }
+            ExprKind::MethodCall { receiver, method } => {
+                format!("(. {} {})", render(&receiver.kind, interner), name(*method))
+            }
+            ExprKind::List(elements) => {
+                let elements: Vec<_> = elements
+                    .iter()
+                    .map(|element| render(&element.kind, interner))
+                    .collect();
+                format!("[{}]", elements.join(", "))
+            }
+            ExprKind::Index { value, index } => format!(
+                "(index {} {})",
+                render(&value.kind, interner),
+                render(&index.kind, interner)
+            ),
+            ExprKind::Slice { value, start, end } => {
+                let bound = |bound: &Option<Box<Expr<Span>>>| {
+                    bound
+                        .as_ref()
+                        .map_or("_".to_string(), |bound| render(&bound.kind, interner))
+                };
+                format!(
+                    "(slice {} {} {})",
+                    render(&value.kind, interner),
+                    bound(start),
+                    bound(end)
+                )
+            }
+            ExprKind::Match { value, arms } => {
+                let mut words =
+                    vec!["(match".to_string(), render(&value.kind, interner)];
+                for arm in arms {
+                    let guard = arm.guard.as_ref().map_or(String::new(), |guard| {
+                        format!(" if {}", render(&guard.kind, interner))
+                    });
+                    words.push(format!(
+                        "[{}{guard} -> {}]",
+                        render_pattern(&arm.pattern, interner),
+                        render(&arm.body.kind, interner)
+                    ));
+                }
+                words.join(" ") + ")"
+            }
+            ExprKind::Let { bindings, body } => format!(
+                "(let {} {})",
+                render_bindings(bindings, interner),
+                render(&body.kind, interner)
+            ),
+            ExprKind::Lambda { params, body } => {
+                let params: Vec<_> = params
+                    .iter()
+                    .map(|param| render_pattern(param, interner))
+                    .collect();
+                format!(
+                    "(\\{} -> {})",
+                    params.join(" "),
+                    render(&body.kind, interner)
+                )
+            }
+            ExprKind::LeftSection { op, lhs } => {
+                format!("({} {})", render(&lhs.kind, interner), name(*op))
+            }
+            ExprKind::RightSection { op, rhs } => {
+                format!("({} {})", name(*op), render(&rhs.kind, interner))
+            }
+            ExprKind::Error => "<error>".to_string(),
+            other => unreachable!("not parsed yet: {other:?}"),
+        }
+    }
+
+    /// `name params = body`, followed by the `where` bindings in brackets
+    fn render_clause(
+        name: Symbol,
+        clause: &Clause<Span>,
+        interner: &Interner,
+    ) -> String {
+        let mut words = vec![interner.resolve(name).unwrap().to_string()];
+        words.extend(
+            clause
+                .params
+                .iter()
+                .map(|param| render_pattern(param, interner)),
+        );
+        words.push("=".to_string());
+        words.push(render(&clause.body.kind, interner));
+        if !clause.where_bindings.is_empty() {
+            words.push(format!(
+                "where {}",
+                render_bindings(&clause.where_bindings, interner)
+            ));
+        }
+        words.join(" ")
+    }
+
+    /// `[f :: Int ; f = 1 | g x = x]`, signatures are rendered from the AST
+    fn render_bindings(bindings: &[FuncDecl<Span>], interner: &Interner) -> String {
+        let bindings: Vec<_> = bindings
+            .iter()
+            .map(|decl| {
+                let mut lines = Vec::new();
+                if let Some(sig) = &decl.func_sig {
+                    lines.push(format!(
+                        "{} :: {}",
+                        interner.resolve(decl.func_name).unwrap(),
+                        render_type(&sig.ty, interner)
+                    ));
+                }
+                lines.extend(
+                    decl.clauses
+                        .iter()
+                        .map(|clause| render_clause(decl.func_name, clause, interner)),
+                );
+                lines.join(" ; ")
+            })
+            .collect();
+        format!("[{}]", bindings.join(" | "))
+    }
+
+    fn render_pattern(pattern: &Pattern, interner: &Interner) -> String {
+        let name = |sym| interner.resolve(sym).unwrap().to_string();
+        match &pattern.kind {
+            PatternKind::Wildcard => "_".to_string(),
+            PatternKind::Literal(Literal::Integer(value)) => value.to_string(),
+            PatternKind::Literal(literal) => format!("{literal:?}"),
+            PatternKind::Variable(sym) => name(*sym),
+            PatternKind::Constructor { name: sym, args } if args.is_empty() => {
+                name(*sym)
+            }
+            PatternKind::Refinement(sym) => format!("(refine {})", name(*sym)),
+            PatternKind::Constructor { name: sym, args } => {
+                let args: Vec<_> = args
+                    .iter()
+                    .map(|arg| render_pattern(arg, interner))
+                    .collect();
+                format!("({} {})", name(*sym), args.join(" "))
+            }
+            PatternKind::Alias { name: sym, pattern } => {
+                format!("({} @ {})", render_pattern(pattern, interner), name(*sym))
+            }
+            PatternKind::Annotated { pattern, ty } => format!(
+                "({} :: {})",
+                render_pattern(pattern, interner),
+                render_type(ty, interner)
+            ),
+            other => unreachable!("not parsed yet: {other:?}"),
+        }
+    }
+
+    fn render_predicate(predicate: &Predicate, interner: &Interner) -> String {
+        let binary = |op: &str, lhs, rhs| {
+            format!(
+                "({op} {} {})",
+                render_predicate(lhs, interner),
+                render_predicate(rhs, interner)
+            )
+        };
+        match predicate {
+            Predicate::Literal(Literal::Integer(value)) => value.to_string(),
+            Predicate::Literal(literal) => format!("{literal:?}"),
+            Predicate::Value => "self".to_string(),
+            Predicate::Measure { receiver, measure } => format!(
+                "(. {} {})",
+                render_predicate(receiver, interner),
+                interner.resolve(*measure).unwrap()
+            ),
+            Predicate::Arith { op, lhs, rhs } => binary(&format!("{op:?}"), lhs, rhs),
+            Predicate::Compare { op, lhs, rhs } => binary(&format!("{op:?}"), lhs, rhs),
+            Predicate::And(lhs, rhs) => binary("&&", lhs, rhs),
+            Predicate::Or(lhs, rhs) => binary("||", lhs, rhs),
+            Predicate::Not(operand) => {
+                format!("(! {})", render_predicate(operand, interner))
+            }
+            other => unreachable!("not parsed yet: {other:?}"),
+        }
+    }
+
+    fn render_type(ty: &Type, interner: &Interner) -> String {
+        let name = |sym| interner.resolve(sym).unwrap().to_string();
+        let render_all = |types: &[Type], separator| {
+            let types: Vec<_> =
+                types.iter().map(|ty| render_type(ty, interner)).collect();
+            types.join(separator)
+        };
+        match ty {
+            Type::Var(sym) | Type::Con(sym) => name(*sym),
+            Type::App { func, args } => {
+                format!(
+                    "({} {})",
+                    render_type(func, interner),
+                    render_all(args, " ")
+                )
+            }
+            Type::Arrow { param, result } => format!(
+                "({} -> {})",
+                render_type(param, interner),
+                render_type(result, interner)
+            ),
+            Type::Tuple(types) => format!("({})", render_all(types, ", ")),
+            Type::Unit => "()".to_string(),
+            other => unreachable!("not parsed yet: {other:?}"),
+        }
+    }
+
+    fn sig(src: &str) -> Result<String, (String, String)> {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let result = parse_sig(src, interner.clone());
+        let interner = interner.borrow();
+        match result {
+            Ok(sig) => {
+                let span = sig.span.start() as usize..sig.span.end() as usize;
+                assert_eq!(src.trim(), &src[span], "the span covers the signature");
+                let mut text = String::new();
+                if !sig.constraints.is_empty() {
+                    let constraints: Vec<_> = sig
+                        .constraints
+                        .iter()
+                        .map(|constraint| {
+                            let class = Type::Con(constraint.class);
+                            let ty = Type::App {
+                                func: Box::new(class),
+                                args: constraint.args.clone(),
+                            };
+                            render_type(&ty, &interner)
+                        })
+                        .collect();
+                    text += &format!("{} => ", constraints.join(", "));
+                }
+                text += &render_type(&sig.ty, &interner);
+                if !sig.effects.is_empty() {
+                    let effects: Vec<_> = sig
+                        .effects
+                        .iter()
+                        .map(|effect| render_type(effect, &interner))
+                        .collect();
+                    text += &format!(" with {}", effects.join(", "));
+                }
+                Ok(text)
+            }
+            Err(err) => {
+                let span = err.primary_span;
+                let text = &src[span.start() as usize..span.end() as usize];
+                Err((err.message, text.to_string()))
+            }
+        }
+    }
+
+    /// every definition on one line, and the messages and source text of the diagnostics
+    fn parse_file(src: &str) -> (Vec<String>, Vec<(String, String)>) {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = Lexer::new(src, 4, interner.clone());
+        let mut parser = Parser::new(lexer, interner.clone());
+        let definitions: Vec<_> = parser.by_ref().collect();
+
+        let text =
+            |span: Span| src[span.start() as usize..span.end() as usize].to_string();
+        let interner = interner.borrow();
+        let name = |sym| interner.resolve(sym).unwrap().to_string();
+        let render_func = |decl: &FuncDecl<Span>| {
+            let mut lines = Vec::new();
+            if let Some(doc) = decl.doc {
+                lines.push(format!("--| {}", name(doc)));
+            }
+            if let Some(sig) = &decl.func_sig {
+                lines.push(format!("{} :: {}", name(decl.func_name), text(sig.span)));
+            }
+            for clause in &decl.clauses {
+                lines.push(render_clause(decl.func_name, clause, &interner));
+            }
+            lines.join(" | ")
+        };
+        let render_context = |constraints: &[Constraint]| {
+            if constraints.is_empty() {
+                return String::new();
+            }
+            let constraints: Vec<_> = constraints
+                .iter()
+                .map(|constraint| {
+                    let ty = Type::App {
+                        func: Box::new(Type::Con(constraint.class)),
+                        args: constraint.args.clone(),
+                    };
+                    render_type(&ty, &interner)
+                })
+                .collect();
+            format!("{} => ", constraints.join(", "))
+        };
+        let definitions = definitions
+            .into_iter()
+            .map(|definition| match definition {
+                UntypedAST::FuncDecl(decl) => render_func(&decl),
+                UntypedAST::Fixity(decl) => text(decl.span),
+                UntypedAST::TypeDecl(decl) => {
+                    let mut words = vec!["type".to_string(), name(decl.name)];
+                    words.extend(decl.params.iter().map(|&param| name(param)));
+                    words.push("=".to_string());
+                    match &decl.body {
+                        TypeBody::Alias(ty) => words.push(render_type(ty, &interner)),
+                        TypeBody::Data(constructors) => {
+                            let constructors: Vec<_> = constructors
+                                .iter()
+                                .map(|constructor| {
+                                    let fields: Vec<_> = match &constructor.fields {
+                                        Fields::Positional(fields) => fields
+                                            .iter()
+                                            .map(|ty| render_type(ty, &interner))
+                                            .collect(),
+                                        Fields::Named(fields) => fields
+                                            .iter()
+                                            .map(|field| {
+                                                format!(
+                                                    "{}: {}",
+                                                    name(field.name),
+                                                    render_type(&field.ty, &interner)
+                                                )
+                                            })
+                                            .collect(),
+                                    };
+                                    let mut words = vec![name(constructor.name)];
+                                    words.extend(fields);
+                                    format!(
+                                        "{} <{}>",
+                                        words.join(" "),
+                                        text(constructor.span)
+                                    )
+                                })
+                                .collect();
+                            words.push(format!(
+                                "data {{ {} }}",
+                                constructors.join(" | ")
+                            ));
+                        }
+                    }
+                    assert!(text(decl.span).starts_with("type"));
+                    if !decl.methods.is_empty() {
+                        let methods: Vec<_> =
+                            decl.methods.iter().map(render_func).collect();
+                        words.push(format!("can {{ {} }}", methods.join(" ; ")));
+                    }
+                    words.join(" ")
+                }
+                UntypedAST::Refine(decl) => {
+                    let mut words = vec!["refine".to_string(), name(decl.name)];
+                    words.extend(decl.params.iter().map(|&param| name(param)));
+                    words.push("=".to_string());
+                    words.push(render_type(&decl.base, &interner));
+                    words.push("where".to_string());
+                    words.push(render_predicate(&decl.predicate, &interner));
+                    format!("{} <{}>", words.join(" "), text(decl.span))
+                }
+                UntypedAST::Class(decl) => {
+                    let mut words = vec![name(decl.name)];
+                    words.extend(decl.params.iter().map(|&param| name(param)));
+                    let mut methods: Vec<_> = decl
+                        .methods
+                        .iter()
+                        .map(|method| {
+                            format!(
+                                "{} :: {}",
+                                name(method.name),
+                                text(method.sig.span)
+                            )
+                        })
+                        .collect();
+                    methods.extend(decl.defaults.iter().map(render_func));
+                    format!(
+                        "class {}{} {{ {} }} <{}>",
+                        render_context(&decl.superclasses),
+                        words.join(" "),
+                        methods.join(" ; "),
+                        text(decl.span)
+                    )
+                }
+                UntypedAST::Instance(decl) => {
+                    let ty = Type::App {
+                        func: Box::new(Type::Con(decl.class)),
+                        args: decl.args.clone(),
+                    };
+                    let methods: Vec<_> =
+                        decl.methods.iter().map(render_func).collect();
+                    format!(
+                        "instance {}{} {{ {} }} <{}>",
+                        render_context(&decl.constraints),
+                        render_type(&ty, &interner),
+                        methods.join(" ; "),
+                        text(decl.span)
+                    )
+                }
+                UntypedAST::Module(decl) => {
+                    format!(
+                        "module {} <{}>",
+                        decl.path.name(&interner),
+                        text(decl.span)
+                    )
+                }
+                UntypedAST::Import(import) => {
+                    let list = |names: &[ListedName]| {
+                        let names: Vec<_> =
+                            names.iter().map(|listed| name(listed.name)).collect();
+                        format!("[{}]", names.join(", "))
+                    };
+                    let mut words =
+                        vec!["import".to_string(), import.module.name(&interner)];
+                    if let Some(alias) = import.alias {
+                        words.push(format!("as {}", name(alias)));
+                    }
+                    match &import.names {
+                        ImportNames::All => {}
+                        ImportNames::Only(names) => words.push(list(names)),
+                        ImportNames::Hiding(names) => {
+                            words.push(format!("hiding {}", list(names)))
+                        }
+                    }
+                    format!("{} <{}>", words.join(" "), text(import.span))
+                }
+                UntypedAST::Export(export) => {
+                    let names: Vec<_> = export
+                        .names
+                        .iter()
+                        .map(|listed| {
+                            format!("{} <{}>", name(listed.name), text(listed.span))
+                        })
+                        .collect();
+                    format!("export [{}]", names.join(", "))
+                }
+                UntypedAST::Error(span) => format!("error <{}>", text(span)),
+                other => unreachable!("not parsed yet: {other:?}"),
+            })
+            .collect();
+        let diagnostics = parser
+            .diagnostics()
+            .iter()
+            .map(|err| (err.message.clone(), text(err.primary_span)))
+            .collect();
+        (definitions, diagnostics)
+    }
+
+    fn ok(sexp: &str) -> Result<String, (String, String)> {
+        Ok(sexp.to_string())
+    }
+
+    #[test]
+    fn test_application() {
+        assert_eq!(ok("x"), parse("x"));
+        assert_eq!(ok("(f x y)"), parse("f x y"));
+        assert_eq!(ok("(f (g x) 1 \"s\")"), parse("f (g x) 1 \"s\""));
+        assert_eq!(ok("(+ (f x) (g y))"), parse("f x + g y"));
+        assert_eq!(ok("(f x)"), parse("((f) (x))"));
+    }
+
+    #[test]
+    fn test_precedence_and_associativity() {
+        assert_eq!(ok("(+ 1 (* 2 3))"), parse("1 + 2 * 3"));
+        assert_eq!(ok("(* (+ 1 2) 3)"), parse("(1 + 2) * 3"));
+        assert_eq!(ok("(- (- 1 2) 3)"), parse("1 - 2 - 3"));
+        assert_eq!(ok("($ f ($ g x))"), parse("f $ g $ x"));
+        assert_eq!(ok("(|| a (&& b (== c d)))"), parse("a || b && c == d"));
+        assert_eq!(ok("(>> f (>> g h))"), parse("f >> g >> h"));
+        assert_eq!(ok("(- f 1)"), parse("f -1"));
+    }
+
+    #[test]
+    fn test_negation() {
+        assert_eq!(ok("(neg (* a b))"), parse("-a * b"));
+        assert_eq!(ok("(+ (neg a) b)"), parse("- a + b"));
+        assert_eq!(ok("(neg (neg a))"), parse("- - a"));
+        assert_eq!(ok("(neg (f x))"), parse("-f x"));
+    }
+
+    #[test]
+    fn test_user_fixities() {
+        let infixr5 = Fixity::new(Associativity::Right, 5);
+        assert_eq!(
+            ok("(<+> a (<+> b c))"),
+            parse_with("a <+> b <+> c", &[("<+>", infixr5)])
+        );
+        assert_eq!(
+            ok("(<+> (+ a b) c)"),
+            parse_with("a + b <+> c", &[("<+>", infixr5)])
+        );
+        // 没有声明的运算符是 infixl 9
+        assert_eq!(ok("(* (<|> a b) c)"), parse("a <|> b * c"));
+        // 声明可以覆盖内置运算符
+        let infixl8 = Fixity::new(Associativity::Left, 8);
+        assert_eq!(
+            ok("(* (+ a b) c)"),
+            parse_with("a + b * c", &[("+", infixl8)])
+        );
+    }
+
+    #[test]
+    fn test_interpolated_string() {
+        assert_eq!(
+            ok("(++ \"a \" (++ (show (+ x 1)) \"!\"))"),
+            parse("\"a {x + 1}!\"")
+        );
+        assert_eq!(ok("(f (show x))"), parse("f \"{x}\""));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err =
+            |message: &str, text: &str| Err((message.to_string(), text.to_string()));
+        assert_eq!(
+            err(
+                "cannot mix `==` [infix 4] and `==` [infix 4] in the same infix expression, \
+                 add parentheses",
+                "=="
+            ),
+            parse("a == b == c")
+        );
+        let infixr6 = Fixity::new(Associativity::Right, 6);
+        assert_eq!(
+            err(
+                "cannot mix `+` [infixl 6] and `++` [infixr 6] in the same infix expression, \
+                 add parentheses",
+                "++"
+            ),
+            parse_with("a + b ++ c", &[("++", infixr6)])
+        );
+        assert_eq!(err("expected `)`, found end of file", ""), parse("(1 + 2"));
+        assert_eq!(
+            err("expected an expression, found `)`", ")"),
+            parse("1 + )")
+        );
+        assert_eq!(err("expected an expression, found `=`", "="), parse("= 1"));
+    }
+
+    #[test]
+    fn test_multi_clause_functions() {
+        let src = "\
+--| the sum
+--| of a vector
+sumOfVec :: Vec Int -> Int
+sumOfVec Nil = 0
+sumOfVec (Cons x xs) =
+    x + sumOfVec xs
+
+infixl 6 <+>
+get self 0 = unwrap self
+get _ index = get self (index - 1)
+main = print \"hi\"
+";
+        let (definitions, diagnostics) = parse_file(src);
+        assert_eq!(
+            vec![
+                "--| the sum\nof a vector | sumOfVec :: Vec Int -> Int | sumOfVec Nil = 0 | \
+                 sumOfVec (Cons x xs) = (+ x (sumOfVec xs))",
+                "infixl 6 <+>",
+                "get self 0 = (unwrap self) | get _ index = (get self (- index 1))",
+                "main = (print \"hi\")",
+            ],
+            definitions
+        );
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn test_readme_equations() {
+        // 和 README 里的例子相同
+        let (definitions, diagnostics) =
+            parse_file(include_str!("../../../samples/refinement-types.an"));
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert_eq!(
+            "sumOfVec :: Vec Int -> Int | sumOfVec (refine EmptyIntVec) = 0 | \
+             sumOfVec (x :: OneElementIntVec) = (index x 0) | \
+             sumOfVec x = (+ [0] (sumOfVec (slice x 1 _)))",
+            definitions[2]
+        );
+
+        let (definitions, diagnostics) =
+            parse_file(include_str!("../../../samples/data.an"));
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert!(
+            definitions[1].ends_with(
+                "get :: (Self, Usize) -> Maybe a | get self 0 = (unwrap self) | \
+                 get self index = (match self [(Cons x) -> ($ unwrap (- (get x index) 1))] \
+                 [Nil -> Nothing]) }"
+            ),
+            "{}",
+            definitions[1]
+        );
+    }
+
+    #[test]
+    fn test_clause_diagnostics() {
+        let (definitions, diagnostics) = parse_file("f 0 = 1\ng x = x\nf n = n\n");
+        assert_eq!(vec!["f 0 = 1", "g x = x", "f n = n"], definitions);
+        assert_eq!(
+            vec![(
+                "the equations of `f` are not adjacent".to_string(),
+                "f n = n".to_string()
+            )],
+            diagnostics
+        );
+
+        let (definitions, diagnostics) = parse_file("f 0 = 1\nf a b = a\n");
+        assert_eq!(vec!["f 0 = 1 | f a b = a"], definitions);
+        assert_eq!(
+            vec![(
+                "this equation of `f` has 2 parameters, but the first one has 1"
+                    .to_string(),
+                "f a b = a".to_string()
+            )],
+            diagnostics
+        );
+
+        let (definitions, diagnostics) = parse_file("f :: Int\ng = 1\n");
+        assert_eq!(vec!["g = 1"], definitions);
+        assert_eq!(
+            vec![(
+                "the signature of `f` is not followed by its equations".to_string(),
+                "f ::".to_string()
+            )],
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_expected_after_end_of_file() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = Lexer::new("f x  ", 4, interner.clone());
+        let mut parser = Parser::new(lexer, interner);
+        assert!(parser.parse_expr().is_ok());
+        assert!(parser.consume(Token::EOF).is_ok());
+
+        let err = parser.parse_expr().unwrap_err();
+        assert_eq!("expected an expression, found end of file", err.message);
+        assert_eq!(Span::empty(3u32), err.primary_span);
+    }
+
+    #[test]
+    fn test_syntax_error_recovery() {
+        let (definitions, diagnostics) = parse_file("f = 1\ng = )\nh = 2\n");
+        assert_eq!(vec!["f = 1", "error <g = )>", "h = 2"], definitions);
+        assert_eq!(
+            vec![(
+                "expected an expression, found `)`".to_string(),
+                ")".to_string()
+            )],
+            diagnostics
+        );
+
+        let (_, diagnostics) = parse_file("f x y\ng = 1\n");
+        assert_eq!(
+            vec![(
+                "expected a parameter or `=`, found end of line".to_string(),
+                "".to_string()
+            )],
+            diagnostics
+        );
+
+        let (definitions, diagnostics) = parse_file("}\nf = 1\n} }\n");
+        assert_eq!(vec!["error <}>", "f = 1", "error <} }>"], definitions);
+        assert_eq!(
+            vec![
+                (
+                    "expected a definition, found `}`".to_string(),
+                    "}".to_string()
+                );
+                2
+            ],
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_sig() {
+        assert_eq!(ok("Int"), sig("Int"));
+        assert_eq!(ok("()"), sig("( )"));
+        assert_eq!(
+            ok("((a -> b) -> ((Vec a) -> (Vec b)))"),
+            sig("(a -> b) -> Vec a -> Vec b")
+        );
+        assert_eq!(ok("(Either e (Vec a))"), sig("Either e (Vec a)"));
+        assert_eq!(ok("((a, (b -> c)) -> Self)"), sig("(a, b -> c) -> Self"));
+        assert_eq!(
+            ok("(Show a) => (a -> ()) with (Out Console)"),
+            sig("Show a => a -> () with Out Console")
+        );
+        assert_eq!(
+            ok("(Show a), (Functor f) => ((f a) -> a) with (Maybe Panic!), Io"),
+            sig("(Show a, Functor f) => f a -> a with Maybe Panic!, Io")
+        );
+    }
+
+    #[test]
+    fn test_sig_errors() {
+        let err =
+            |message: &str, text: &str| Err((message.to_string(), text.to_string()));
+        assert_eq!(err("expected a type, found end of file", ""), sig("a ->"));
+        assert_eq!(err("expected `)`, found end of file", ""), sig("(a, b"));
+        assert_eq!(
+            err("expected constraints like `Show a` before `=>`", "a"),
+            sig("a => b")
+        );
+        assert_eq!(err("expected end of file, found `)`", ")"), sig("Int)"));
+        assert_eq!(
+            err("expected a type, found `->`", "->"),
+            sig("Int with -> a")
+        );
+    }
+
+    #[test]
+    fn test_signature_of_function() {
+        let (definitions, diagnostics) =
+            parse_file("main :: () -> () with Out Console\nmain = print \"hi\"\n");
+        assert_eq!(
+            vec!["main :: () -> () with Out Console | main = (print \"hi\")"],
+            definitions
+        );
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn test_type_decls() {
+        let src = "\
+type Maybe a = data a {
+  Just a | None
+}
+type List = data a {
+  Cons a Self | Nil
+}
+type Shape = data { Circle { r :: Float } | Rect { w :: Float, h :: Float -> Int } }
+type Ints = Vec Int
+type Void = data {}
+";
+        let (definitions, diagnostics) = parse_file(src);
+        assert_eq!(
+            vec![
+                "type Maybe a = data { Just a <Just a> | None <None> }",
+                "type List a = data { Cons a Self <Cons a Self> | Nil <Nil> }",
+                "type Shape = data { Circle r: Float <Circle { r :: Float }> | \
+                 Rect w: Float h: (Float -> Int) <Rect { w :: Float, h :: Float -> Int }> }",
+                "type Ints = (Vec Int)",
+                "type Void = data {  }",
+            ],
+            definitions
+        );
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn test_type_decl_errors() {
+        let error = |src| parse_file(src).1;
+        assert_eq!(
+            vec![(
+                "expected a type or constructor name, found identifier".to_string(),
+                "just".to_string()
+            )],
+            error("type Maybe a = data { just a }")
+        );
+        assert_eq!(
+            vec![(
+                "expected a type variable, found identifier".to_string(),
+                "A".to_string()
+            )],
+            error("type Maybe A = data { Just A }")
+        );
+        assert_eq!(
+            vec![(
+                "expected `}`, found end of file".to_string(),
+                "".to_string()
+            )],
+            error("type Maybe a = data { Just a")
+        );
+    }
+
+    #[test]
+    fn test_method_calls() {
+        assert_eq!(ok("(. x unwrap)"), parse("x.unwrap"));
+        assert_eq!(ok("(. (. x unwrap) len)"), parse("x.unwrap.len"));
+        assert_eq!(ok("(f (. x len) y)"), parse("f x.len y"));
+        assert_eq!(ok("((. x get) 1)"), parse("x.get 1"));
+        assert_eq!(ok("(. (+ a b) len)"), parse("(a + b).len"));
+        assert_eq!(
+            Err((
+                "expected a method name, found literal".to_string(),
+                "1".to_string()
+            )),
+            parse("x.1")
+        );
+    }
+
+    #[test]
+    fn test_lists_and_indexing() {
+        assert_eq!(ok("[]"), parse("[]"));
+        assert_eq!(ok("[1, (+ a b), [x]]"), parse("[1, a + b, [x]]"));
+        assert_eq!(ok("(index x 0)"), parse("x[0]"));
+        assert_eq!(ok("(index (index xs (+ i 1)) 0)"), parse("xs[i + 1][0]"));
+        assert_eq!(ok("(. (index xs 0) len)"), parse("xs[0].len"));
+        // 有空格的 `[` 是一个列表参数
+        assert_eq!(ok("(f [0])"), parse("f [0]"));
+        assert_eq!(ok("(f (index x 0))"), parse("f x[0]"));
+        assert_eq!(ok("(+ (index x 0) 1)"), parse("x[0] + 1"));
+
+        assert_eq!(ok("(slice x 1 _)"), parse("x[1..]"));
+        assert_eq!(ok("(slice x _ (+ n 1))"), parse("x[..n + 1]"));
+        assert_eq!(ok("(slice x a b)"), parse("x[a..b]"));
+        assert_eq!(ok("(slice x (+ a 1) _)"), parse("x[a + 1..]"));
+        assert_eq!(ok("(slice x _ _)"), parse("x[..]"));
+
+        assert_eq!(
+            Err(("expected `]`, found `)`".to_string(), ")".to_string())),
+            parse("x[0)")
+        );
+        assert_eq!(
+            Err(("expected `]`, found `,`".to_string(), ",".to_string())),
+            parse("x[1, 2]")
+        );
+        assert_eq!(
+            Err((
+                "expected `]`, found end of file".to_string(),
+                "".to_string()
+            )),
+            parse("[1, 2")
+        );
+    }
+
+    #[test]
+    fn test_can_blocks() {
+        let src = "\
+type Maybe a = data a {
+  Just a | None
+}
+can {
+  unwrap :: Self -> a with Maybe Panic!
+  unwrap (Just x) = x
+  unwrap None = panic! \"Unwrapping None!\"
+
+  isJust x = x.isNone.not
+}
+type Unit = data { Unit } can { unit = Unit }
+main = print (Just 1).unwrap
+";
+        let (definitions, diagnostics) = parse_file(src);
+        assert_eq!(
+            vec![
+                "type Maybe a = data { Just a <Just a> | None <None> } can { \
+                 unwrap :: Self -> a with Maybe Panic! | unwrap (Just x) = x | \
+                 unwrap None = (panic! \"Unwrapping None!\") ; \
+                 isJust x = (. (. x isNone) not) }",
+                "type Unit = data { Unit <Unit> } can { unit = Unit }",
+                "main = (print (. (Just 1) unwrap))",
+            ],
+            definitions
+        );
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn test_can_block_diagnostics() {
+        let src = "type T = data { T }\ncan {\n  f = 1\n  g = 2\n  f = 3\n}\nh = 4\n";
+        let (definitions, diagnostics) = parse_file(src);
+        assert_eq!(
+            vec![
+                "type T = data { T <T> } can { f = 1 ; g = 2 ; f = 3 }",
+                "h = 4"
+            ],
+            definitions
+        );
+        assert_eq!(
+            vec![(
+                "duplicate method `f` in this `can` block".to_string(),
+                "f = 3".to_string()
+            )],
+            diagnostics
+        );
+
+        let (definitions, diagnostics) =
+            parse_file("f = 1\ncan {\n  g = 1\n}\nh = 2\n");
+        assert_eq!(vec!["f = 1", "h = 2"], definitions);
+        assert_eq!(
+            vec![(
+                "a `can` block must follow a type declaration".to_string(),
+                "can".to_string()
+            )],
+            diagnostics
+        );
+
+        let (_, diagnostics) = parse_file("f :: Self -> Int\nf x = 1\n");
+        assert_eq!(
+            vec![(
+                "`Self` can only be used in a type declaration and its `can` block"
+                    .to_string(),
+                "Self".to_string()
+            )],
+            diagnostics
+        );
+
+        let (_, diagnostics) =
+            parse_file("type T = data { T }\ncan {\n  infixl 6 <+>\n}\n");
+        assert_eq!(
+            vec![(
+                "only methods can be defined in a `can` block".to_string(),
+                "infixl 6 <+>".to_string()
+            )],
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_refinements() {
+        let (definitions, diagnostics) = parse_file(
+            "refine EmptyIntVec = Vec Int where Vec.len = 0\n\
+             refine OneElementIntVec = Vec Int where Vec.len == 1\n\
+             refine Digit = Int where\n\
+             \x20   self >= 0 && self <= 9\n\
+             \x20   !(self = 5 || self = 7)\n\
+             refine Small a = Vec a where (Vec.len + 1) * 2 < 10 % 4 - self.first.len\n\
+             sumOfVec :: Vec Int -> Int\n\
+             sumOfVec EmptyIntVec = 0\n\
+             sumOfVec (x :: OneElementIntVec) = x\n",
+        );
+        assert_eq!(Vec::<(String, String)>::new(), diagnostics);
+        assert_eq!(
+            vec![
+                "refine EmptyIntVec = (Vec Int) where (Eq (. self len) 0) \
+                 <refine EmptyIntVec = Vec Int where Vec.len = 0>",
+                "refine OneElementIntVec = (Vec Int) where (Eq (. self len) 1) \
+                 <refine OneElementIntVec = Vec Int where Vec.len == 1>",
+                "refine Digit = Int where \
+                 (&& (&& (Ge self 0) (Le self 9)) (! (|| (Eq self 5) (Eq self 7)))) \
+                 <refine Digit = Int where\n    self >= 0 && self <= 9\n    !(self = 5 || self = 7)>",
+                "refine Small a = (Vec a) where \
+                 (Lt (Mul (Add (. self len) 1) 2) (Sub (Mod 10 4) (. (. self first) len))) \
+                 <refine Small a = Vec a where (Vec.len + 1) * 2 < 10 % 4 - self.first.len>",
+                "sumOfVec :: Vec Int -> Int | sumOfVec (refine EmptyIntVec) = 0 \
+                 | sumOfVec (x :: OneElementIntVec) = x",
+            ],
+            definitions
+        );
+    }
+
+    #[test]
+    fn test_refinement_patterns() {
+        // 细化类型可以在声明之前作为模式，其他的大写名字还是构造器
+        let (definitions, diagnostics) = parse_file(
+            "f Empty = 0\n\
+             f (Cons x Empty) = x\n\
+             f Nil = 1\n\
+             g xs = match xs\n\
+             \x20 Empty -> 0\n\
+             \x20 (Empty @ ys) -> 1\n\
+             refine Empty = Vec Int where Vec.len = 0\n",
+        );
+        assert_eq!(Vec::<(String, String)>::new(), diagnostics);
+        assert_eq!(
+            vec![
+                "f (refine Empty) = 0 | f (Cons x (refine Empty)) = x | f Nil = 1",
+                "g xs = (match xs [(refine Empty) -> 0] [((refine Empty) @ ys) -> 1])",
+            ],
+            definitions[..2]
+        );
+
+        // 细化类型不接受参数
+        let (_, diagnostics) = parse_file(
+            "refine Empty = Vec Int where Vec.len = 0\n\
+             g xs = match xs\n\
+             \x20 Empty x -> 0\n",
+        );
+        assert_eq!(
+            vec![(
+                "expected `->`, found identifier".to_string(),
+                "x".to_string()
+            )],
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_refinement_errors() {
+        let error = |src: &str| {
+            let (_, diagnostics) = parse_file(src);
+            assert_eq!(1, diagnostics.len(), "{diagnostics:?}");
+            diagnostics.into_iter().next().unwrap()
+        };
+        assert_eq!(
+            (
+                "`List` is not the refined value, refer to it as `self` or `Vec`"
+                    .to_string(),
+                "List".to_string()
+            ),
+            error("refine Empty = Vec Int where List.len = 0\n")
+        );
+        assert_eq!(
+            (
+                "`x` is not the refined value, refer to it as `self`".to_string(),
+                "x".to_string()
+            ),
+            error("refine Pos = (Int, Int) where x > 0\n")
+        );
+        assert_eq!(
+            (
+                "cannot mix `<=` [infix 4] and `<` [infix 4] in the same infix expression, \
+                 add parentheses"
+                    .to_string(),
+                "<".to_string()
+            ),
+            error("refine Digit = Int where 0 <= self < 10\n")
+        );
+        assert_eq!(
+            (
+                "`$` can not be used in a refinement".to_string(),
+                "$".to_string()
+            ),
+            error("refine Digit = Int where self < 10 $ 1\n")
+        );
+        assert_eq!(
+            (
+                "expected `where`, found end of line".to_string(),
+                "".to_string()
+            ),
+            error("refine Digit = Int\nf = 1\n")
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        assert_eq!(
+            ok("(match (f x) [(Just y) -> y] [None -> 0])"),
+            parse("match f x\n  Just y -> y\n  None ->\n    0\n")
+        );
+
+        let (definitions, diagnostics) = parse_file(
+            "sum xs =\n\
+             \x20   match xs\n\
+             \x20       Cons x rest -> x + sum rest\n\
+             \n\
+             \x20       Nil -> 0\n\
+             classify t =\n\
+             \x20 match t.root\n\
+             \x20   Node (Leaf 0) _ @ node if weight node > 10 -> 1\n\
+             \x20   (Node _ (Leaf n @ leaf)) @ node -> match n\n\
+             \x20     1 -> 2\n\
+             \x20     _ -> 3\n\
+             \x20   'a' -> 4\n\
+             \x20   _ if t == t -> 5\n",
+        );
+        assert_eq!(Vec::<(String, String)>::new(), diagnostics);
+        assert_eq!(
+            vec![
+                "sum xs = (match xs [(Cons x rest) -> (+ x (sum rest))] [Nil -> 0])",
+                "classify t = (match (. t root) \
+                 [((Node (Leaf 0) _) @ node) if (> (weight node) 10) -> 1] \
+                 [((Node _ ((Leaf n) @ leaf)) @ node) -> (match n [1 -> 2] [_ -> 3])] \
+                 [Char('a') -> 4] \
+                 [_ if (== t t) -> 5])",
+            ],
+            definitions
+        );
+    }
+
+    #[test]
+    fn test_operand_spans() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let src = "f (x) y.len + -\"a{b}c\"";
+        let lexer = Lexer::new(src, 4, interner.clone());
+        let mut parser = Parser::new(lexer, interner.clone());
+        let expr = parser.parse_expr().unwrap();
+        let text = |span: Span| &src[span.start() as usize..span.end() as usize];
+
+        let ExprKind::Binary { lhs, rhs, .. } = &expr.kind else {
+            panic!("expected an operator, found {:?}", expr.kind);
+        };
+        assert_eq!("f (x) y.len", text(lhs.meta));
+        let ExprKind::Application { func, args } = &lhs.kind else {
+            panic!("expected an application, found {:?}", lhs.kind);
+        };
+        assert_eq!("f", text(func.meta));
+        let texts: Vec<_> = args.iter().map(|arg| text(arg.meta)).collect();
+        assert_eq!(vec!["(x)", "y.len"], texts);
+        let ExprKind::MethodCall { receiver, .. } = &args[1].kind else {
+            panic!("expected a method call, found {:?}", args[1].kind);
+        };
+        assert_eq!("y", text(receiver.meta));
+
+        assert_eq!("-\"a{b}c\"", text(rhs.meta));
+        let ExprKind::Negate(operand) = &rhs.kind else {
+            panic!("expected a negation, found {:?}", rhs.kind);
+        };
+        assert_eq!("\"a{b}c\"", text(operand.meta));
+        let ExprKind::Binary { lhs, rhs, .. } = &operand.kind else {
+            panic!("expected `++`, found {:?}", operand.kind);
+        };
+        let ExprKind::Binary {
+            lhs: show,
+            rhs: last,
+            ..
+        } = &rhs.kind
+        else {
+            panic!("expected `++`, found {:?}", rhs.kind);
+        };
+        assert_eq!(
+            vec!["a", "{b}", "c"],
+            vec![text(lhs.meta), text(show.meta), text(last.meta)]
+        );
+    }
+
+    #[test]
+    fn test_match_errors_and_spans() {
+        assert_eq!(
+            Err((
+                "expected `->`, found end of line".to_string(),
+                "".to_string()
+            )),
+            parse("match x\n  Just y\n  None -> 0\n")
+        );
+        assert_eq!(
+            Err((
+                "expected a variable after `@`, found identifier".to_string(),
+                "Xs".to_string()
+            )),
+            parse("match x\n  Cons _ _ @ Xs -> 0\n")
+        );
+        assert_eq!(
+            Err((
+                "expected a pattern, found end of block".to_string(),
+                "".to_string()
+            )),
+            parse("match x\ny")
+        );
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let src = "match (a)\n  (Cons x _) @ xs if x > 0 -> 1\n";
+        let lexer = Lexer::new(src, 4, interner.clone());
+        let mut parser = Parser::new(lexer, interner.clone());
+        let expr = parser.parse_expr().unwrap();
+        let text = |span: Span| &src[span.start() as usize..span.end() as usize];
+        let ExprKind::Match { value, arms } = &expr.kind else {
+            panic!("expected a match, found {:?}", expr.kind);
+        };
+        assert_eq!("(a)", text(value.meta));
+        assert_eq!("(Cons x _) @ xs if x > 0 -> 1", text(arms[0].span));
+        assert_eq!("(Cons x _) @ xs", text(arms[0].pattern.span));
+        let PatternKind::Alias { pattern, .. } = &arms[0].pattern.kind else {
+            panic!("expected an alias, found {:?}", arms[0].pattern);
+        };
+        assert_eq!("(Cons x _)", text(pattern.span));
+    }
+
+    #[test]
+    fn test_let_and_where() {
+        assert_eq!(
+            ok("(let [x = 1 | f y = (+ y x)] (f x))"),
+            parse("let x = 1\n    f y = y + x\n    in f x")
+        );
+        assert_eq!(
+            ok("(+ 1 (let [x = 1] (let [y = 2] (* x y))))"),
+            parse("1 + let x = 1 in let y = 2 in x * y")
+        );
+
+        let (definitions, diagnostics) = parse_file(
+            "area r = pi * square r\n\
+             \x20 where\n\
+             \x20   pi :: Float\n\
+             \x20   pi = 3\n\
+             \x20   square x = x * x\n\
+             \x20     where y = x\n\
+             fact 0 = 1\n\
+             fact n = n * fact (n - 1) where\n\
+             \x20   one = 1\n\
+             main =\n\
+             \x20 let go 0 = 0\n\
+             \x20     go n = go (n - 1)\n\
+             \x20 in go 10\n",
+        );
+        assert_eq!(Vec::<(String, String)>::new(), diagnostics);
+        assert_eq!(
+            vec![
+                "area r = (* pi (square r)) \
+                 where [pi :: Float ; pi = 3 | square x = (* x x) where [y = x]]",
+                "fact 0 = 1 | fact n = (* n (fact (- n 1))) where [one = 1]",
+                "main = (let [go 0 = 0 ; go n = (go (- n 1))] (go 10))",
+            ],
+            definitions
+        );
+    }
+
+    #[test]
+    fn test_in_and_where_between_blocks() {
+        // `in` 和 `where` 在块和外层的行之间的列，由 layout 关闭块
+        let (definitions, diagnostics) = parse_file(
+            "f = let a = 1\n\
+             \x20       b = 2\n\
+             \x20   in a\n\
+             g x = match x\n\
+             \x20       A -> 1\n\
+             \x20   where y = 1\n",
+        );
+        assert_eq!(Vec::<(String, String)>::new(), diagnostics);
+        assert_eq!(
+            vec![
+                "f = (let [a = 1 | b = 2] a)",
+                "g x = (match x [A -> 1]) where [y = 1]",
+            ],
+            definitions
+        );
+    }
+
+    #[test]
+    fn test_let_and_where_diagnostics() {
+        let (definitions, diagnostics) =
+            parse_file("f = x\n  where\n    x = 1\n    y = 2\n    x = 3\ng = 1\n");
+        assert_eq!(
+            vec!["f = x where [x = 1 | y = 2 | x = 3]", "g = 1"],
+            definitions
+        );
+        assert_eq!(
+            vec![(
+                "the equations of `x` are not adjacent".to_string(),
+                "x = 3".to_string()
+            )],
+            diagnostics
+        );
+
+        // 局部的绑定不和顶层的冲突
+        let (_, diagnostics) = parse_file("x = let x = 1 in x\ny = x where x = 2\n");
+        assert_eq!(Vec::<(String, String)>::new(), diagnostics);
+
+        let (_, diagnostics) = parse_file("f = let type T = Int in 1\n");
+        assert_eq!(
+            vec![(
+                "only functions can be defined in a `let` block".to_string(),
+                "type T = Int".to_string()
+            )],
+            diagnostics
+        );
+
+        // 块里的语法错误跳过整个顶层定义
+        let (definitions, diagnostics) =
+            parse_file("f = 1\ng = x where x = )\nh = 2\n");
+        assert_eq!(
+            vec!["f = 1", "error <g = x where x = )>", "h = 2"],
+            definitions
+        );
+        assert_eq!(
+            vec![(
+                "expected an expression, found `)`".to_string(),
+                ")".to_string()
+            )],
+            diagnostics
+        );
+
+        assert_eq!(
+            Err((
+                "expected an expression, found end of block".to_string(),
+                "".to_string()
+            )),
+            parse("let x = 1 + in x")
+        );
+    }
+
+    #[test]
+    fn test_lambdas_and_sections() {
+        assert_eq!(ok("(\\x y -> (+ x y))"), parse("\\x y -> x + y"));
+        assert_eq!(
+            ok("(map (\\(Just x) _ -> (\\y -> y)) xs)"),
+            parse("map (\\(Just x) _ -> \\y -> y) xs")
+        );
+        assert_eq!(ok("(map (+ 1) xs)"), parse("map (+ 1) xs"));
+        assert_eq!(ok("(map (x /) xs)"), parse("map (x /) xs"));
+        assert_eq!(ok("(foldr + 0)"), parse("foldr (+) 0"));
+        assert_eq!(ok("(neg 1)"), parse("(- 1)"));
+        assert_eq!(ok("(+ (* a b))"), parse("(+ a * b)"));
+        assert_eq!(ok("((* a b) +)"), parse("(a * b +)"));
+        assert_eq!(ok("((+ a b) +)"), parse("(a + b +)"));
+        assert_eq!(ok("((+ a b) *)"), parse("((a + b) *)"));
+    }
+
+    #[test]
+    fn test_lambda_and_section_errors() {
+        let err =
+            |message: &str, text: &str| Err((message.to_string(), text.to_string()));
+        assert_eq!(
+            err(
+                "`*` [infixl 7] can not be the operator of a section after `+` [infixl 6], \
+                 add parentheses",
+                "*"
+            ),
+            parse("(a + b *)")
+        );
+        assert_eq!(
+            err(
+                "cannot mix `==` [infix 4] and `==` [infix 4] in the same infix expression, \
+                 add parentheses",
+                "=="
+            ),
+            parse("(a == b ==)")
+        );
+        assert_eq!(
+            err("expected a parameter, found `->`", "->"),
+            parse("\\ -> 1")
+        );
+        assert_eq!(
+            err("expected a parameter or `->`, found `=`", "="),
+            parse("\\x = 1")
+        );
+        // section 只能出现在括号里
+        assert_eq!(
+            err("expected an expression, found `)`", ")"),
+            parse("1 + )")
+        );
+    }
+
+    #[test]
+    fn test_qualified_names() {
+        assert_eq!(ok("F.x"), parse("F.x"));
+        assert_eq!(
+            ok("(Data.Vec.map f V.empty)"),
+            parse("Data.Vec.map f V.empty")
+        );
+        assert_eq!(ok("(. F.x len)"), parse("F.x.len"));
+        assert_eq!(ok("(M.Just 1)"), parse("M.Just 1"));
+        assert_eq!(ok("(. x len)"), parse("x.len"));
+        assert_eq!(ok("Just"), parse("Just"));
+        // 有空格的 `.` 不是限定名
+        assert_eq!(ok("(. F x)"), parse("F .x"));
+    }
+
+    #[test]
+    fn test_imports_and_exports() {
+        let (definitions, diagnostics) = parse_file(
+            "module Data.Vec.Extra\n\
+             import Data.Vec\n\
+             import Data.Map (Map, insert, (<+>))\n\
+             import Data.Map as M hiding (insert)\n\
+             import Prelude as P ()\n\
+             export (sum, Vec, (!=))\n\
+             sum xs = M.foldr (+) 0 xs\n",
+        );
+        assert_eq!(Vec::<(String, String)>::new(), diagnostics);
+        assert_eq!(
+            vec![
+                "module Data.Vec.Extra <module Data.Vec.Extra>",
+                "import Data.Vec <import Data.Vec>",
+                "import Data.Map [Map, insert, <+>] <import Data.Map (Map, insert, (<+>))>",
+                "import Data.Map as M hiding [insert] <import Data.Map as M hiding (insert)>",
+                "import Prelude as P [] <import Prelude as P ()>",
+                "export [sum <sum>, Vec <Vec>, != <(!=)>]",
+                "sum xs = (M.foldr + 0 xs)",
+            ],
+            definitions
+        );
+    }
+
+    #[test]
+    fn test_module_diagnostics() {
+        let (definitions, diagnostics) = parse_file("f = 1\nmodule Main\n");
+        assert_eq!(2, definitions.len());
+        assert_eq!(
+            vec![(
+                "the module header must be the first line of the file".to_string(),
+                "module Main".to_string()
+            )],
+            diagnostics
+        );
+
+        let (_, diagnostics) = parse_file("import Data.Map (insert 1)\n");
+        assert_eq!(
+            vec![("expected `)`, found literal".to_string(), "1".to_string())],
+            diagnostics
+        );
+
+        let (_, diagnostics) = parse_file("import Data.Map ((foo))\n");
+        assert_eq!(
+            vec![(
+                "expected an operator, found identifier".to_string(),
+                "foo".to_string()
+            )],
+            diagnostics
+        );
+
+        let (_, diagnostics) = parse_file("f = let import A in 1\n");
+        assert_eq!(
+            vec![(
+                "only functions can be defined in a `let` block".to_string(),
+                "import A".to_string()
+            )],
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_classes_and_instances() {
+        let (definitions, diagnostics) = parse_file(
+            "--| things that can be shown\n\
+             class Show a where show :: a -> String\n\
+             class Eq a => Ord a where\n\
+             \x20   compare :: a -> a -> Ordering\n\
+             \x20   --| the larger one\n\
+             \x20   max :: a -> a -> a\n\
+             \x20   max x y = compare x y\n\
+             class (Functor f, Show a) => Marker f a\n\
+             instance Show Int where\n\
+             \x20   show x = intToString x\n\
+             instance Show a => Show (Vec a) where\n\
+             \x20   show :: Vec a -> String\n\
+             \x20   show xs = xs.join \", \"\n\
+             instance Eq Int\n",
+        );
+        assert_eq!(Vec::<(String, String)>::new(), diagnostics);
+        assert_eq!(
+            vec![
+                "class Show a { show :: a -> String } <class Show a where show :: a -> String>",
+                "class (Eq a) => Ord a { compare :: a -> a -> Ordering ; max :: a -> a -> a ; \
+                 max x y = (compare x y) } <class Eq a => Ord a where\n    \
+                 compare :: a -> a -> Ordering\n    --| the larger one\n    \
+                 max :: a -> a -> a\n    max x y = compare x y>",
+                "class (Functor f), (Show a) => Marker f a {  } \
+                 <class (Functor f, Show a) => Marker f a>",
+                "instance (Show Int) { show x = (intToString x) } \
+                 <instance Show Int where\n    show x = intToString x>",
+                "instance (Show a) => (Show (Vec a)) { show :: Vec a -> String | \
+                 show xs = ((. xs join) \", \") } <instance Show a => Show (Vec a) where\n    \
+                 show :: Vec a -> String\n    show xs = xs.join \", \">",
+                "instance (Eq Int) {  } <instance Eq Int>",
+            ],
+            definitions
+        );
+    }
+
+    #[test]
+    fn test_class_and_instance_errors() {
+        let (definitions, diagnostics) = parse_file(
+            "class Show a where\n\
+             \x20   show :: a -> String\n\
+             \x20   show :: a -> Text\n\
+             \x20   display x = show x\n\
+             \x20   type T = Int\n\
+             instance Show Int where\n\
+             \x20   show x = 1\n\
+             \x20   display = 2\n\
+             \x20   show y = 2\n\
+             \x20   infixl 6 <+>\n\
+             main = 1\n",
+        );
+        assert_eq!(3, definitions.len());
+        assert_eq!(
+            vec![
+                (
+                    "duplicate signature of `show` in this `class` block".to_string(),
+                    "show :: a -> Text".to_string()
+                ),
+                (
+                    "only method signatures and default methods can be defined in a `class` block"
+                        .to_string(),
+                    "type T = Int".to_string()
+                ),
+                (
+                    "`display` is not a method of the class `Show`".to_string(),
+                    "display x = show x".to_string()
+                ),
+                (
+                    "duplicate method `show` in this `instance` block".to_string(),
+                    "show y = 2".to_string()
+                ),
+                (
+                    "only methods can be defined in an `instance` block".to_string(),
+                    "infixl 6 <+>".to_string()
+                ),
+            ],
+            diagnostics
+        );
+
+        let (_, diagnostics) = parse_file("class Show Int where\n");
+        assert_eq!(
+            vec![(
+                "expected a class and its type variables like `Show a`".to_string(),
+                "Show Int".to_string()
+            )],
+            diagnostics
+        );
+
+        let (_, diagnostics) = parse_file("instance a => Show a\n");
+        assert_eq!(
+            vec![(
+                "expected constraints like `Show a` before `=>`".to_string(),
+                "a".to_string()
+            )],
+            diagnostics
+        );
+
+        let (_, diagnostics) = parse_file("instance Int\n");
+        assert_eq!(
+            vec![(
+                "expected a class and its types like `Show Int`".to_string(),
+                "Int".to_string()
+            )],
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_every_syntax_error_is_reported() {
+        let (definitions, diagnostics) = parse_file(
+            "f x y\n\
+             type T = data {\n\
+             \x20   A | 1\n\
+             }\n\
+             g = 1\n\
+             h = let\n\
+             \x20   x = (1 +\n\
+             \x20   y = 2\n\
+             \x20 in x\n\
+             }\n\
+             type U = data { U } can {\n\
+             \x20   ok = 1\n\
+             \x20   bad = )\n\
+             }\n\
+             k = 2\n",
+        );
+        assert_eq!(
+            vec![
+                "error <f x y>",
+                "error <type T = data {\n    A | 1\n}>",
+                "g = 1",
+                // 没有闭合的括号里没有换行，`}` 也被跳过了
+                "error <h = let\n    x = (1 +\n    y = 2\n  in x\n}>",
+                "type U = data { U <U> } can { ok = 1 }",
+                "k = 2",
+            ],
+            definitions
+        );
+        assert_eq!(
+            vec![
+                (
+                    "expected a parameter or `=`, found end of line".to_string(),
+                    "".to_string()
+                ),
+                (
+                    "expected a type or constructor name, found literal".to_string(),
+                    "1".to_string()
+                ),
+                ("expected `)`, found `=`".to_string(), "=".to_string()),
+                (
+                    "expected an expression, found end of block".to_string(),
+                    "".to_string()
+                ),
+            ],
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_lexical_errors() {
+        // 只有 lexer 的错误，定义的其余部分照常解析
+        let (definitions, diagnostics) = parse_file("x = 300u8\ny = f 1 300u8 2\n");
+        let out_of_range = (
+            "literal out of range for `u8`".to_string(),
+            "300u8".to_string(),
+        );
+        assert_eq!(vec![out_of_range.clone(), out_of_range], diagnostics);
+        assert_eq!(vec!["x = <error>", "y = (f 1 <error> 2)"], definitions);
+
+        // lexer 的错误在语法错误之前
+        let (definitions, diagnostics) = parse_file("f = )\ng = 1 + 0b\n");
+        assert_eq!(
+            vec![
+                ("number literal has no digits".to_string(), "0b".to_string()),
+                (
+                    "expected an expression, found `)`".to_string(),
+                    ")".to_string()
+                ),
+            ],
+            diagnostics
+        );
+        assert_eq!(vec!["error <f = )>", "g = (+ 1 <error>)"], definitions);
+    }
+
+    #[test]
+    fn test_errors_point_at_the_enclosing_construct() {
+        let src = "f = 1\ntype T a = data a {\n    Just a | 1\n}\n";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = Lexer::new(src, 4, interner.clone());
+        let mut parser = Parser::new(lexer, interner);
+        assert_eq!(2, parser.by_ref().count());
+
+        let text = |span: Span| &src[span.start() as usize..span.end() as usize];
+        let [err] = parser.diagnostics() else {
+            panic!("one error expected: {:?}", parser.diagnostics());
+        };
+        assert_eq!("1", text(err.primary_span));
+        let note = err.children.as_deref().expect("a note");
+        assert_eq!(Severity::Note, note.severity);
+        assert_eq!("while parsing this type declaration", note.message);
+        assert_eq!(
+            "type T a = data a {\n    Just a | 1",
+            text(note.primary_span)
+        );
+
+        // 最里面的定义
+        let src = "f = x where\n    x = 1 +\n";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = Lexer::new(src, 4, interner.clone());
+        let mut parser = Parser::new(lexer, interner);
+        assert_eq!(1, parser.by_ref().count());
+        let note = parser.diagnostics()[0].children.as_deref().expect("a note");
+        assert_eq!("while parsing this definition of `x`", note.message);
+    }
+
+    #[test]
+    fn test_fixity_declarations_of_the_file() {
+        // 声明在使用之后也可以
+        let (definitions, diagnostics) = parse_file(
+            "infixl 6 <+>\n\
+             infixl 7 <*>\n\
+             x = a <+> b <*> c\n\
+             y = a <$> b + c\n\
+             infixr 0 <$>\n",
+        );
+        assert_eq!(Vec::<(String, String)>::new(), diagnostics);
+        assert_eq!(
+            vec![
+                "infixl 6 <+>",
+                "infixl 7 <*>",
+                "x = (<+> a (<*> b c))",
+                "y = (<$> a (+ b c))",
+                "infixr 0 <$>",
+            ],
+            definitions
+        );
+
+        let (definitions, diagnostics) =
+            parse_file("infixl 6 <+>\nx = a <+> b\ninfixr 5 <+>\n");
+        assert_eq!(3, definitions.len());
+        assert_eq!(
+            vec![(
+                "duplicate fixity declaration for `<+>`".to_string(),
+                "infixr 5 <+>".to_string()
+            )],
+            diagnostics
+        );
+
+        // 声明本身的错误只报告一次
+        let (_, diagnostics) = parse_file("infixl 10 <+>\n");
+        assert_eq!(
+            vec![(
+                "precedence must be between 0 and 9, found 10".to_string(),
+                "10".to_string()
+            )],
+            diagnostics
+        );
+
+        // 块里的声明不属于文件
+        let (definitions, diagnostics) = parse_file(
+            "f = a\n  where\n    infixr 0 <+>\n\
+             g = let\n      infixr 0 <$>\n    in a\n\
+             x = a <+> b + c\n\
+             y = a <$> b + c\n",
+        );
+        assert_eq!(
+            vec![
+                (
+                    "only functions can be defined in a `where` block".to_string(),
+                    "infixr 0 <+>".to_string()
+                ),
+                (
+                    "only functions can be defined in a `let` block".to_string(),
+                    "infixr 0 <$>".to_string()
+                ),
+            ],
+            diagnostics
+        );
+        assert_eq!(
+            vec!["x = (+ (<+> a b) c)", "y = (+ (<$> a b) c)"],
+            definitions[2..]
+        );
+    }
+
+    #[test]
+    fn test_minimum_of_signed_literals() {
+        let (definitions, diagnostics) = parse_file(
+            "a = -128i8\n\
+             b = -9223372036854775808\n\
+             c = - -128i8\n\
+             d = (- 128i8)\n\
+             e = -127i8 * 2\n",
+        );
+        assert_eq!(Vec::<(String, String)>::new(), diagnostics);
+        assert_eq!(
+            vec![
+                "a = (neg SuffixedInteger(128, I8))",
+                "b = (neg 9223372036854775808)",
+                "c = (neg (neg SuffixedInteger(128, I8)))",
+                "d = (neg SuffixedInteger(128, I8))",
+                "e = (neg (* SuffixedInteger(127, I8) 2))",
+            ],
+            definitions
+        );
 
-        unimplemented!()
+        let (definitions, diagnostics) = parse_file(
+            "a = 128i8\n\
+             b = -128i8 * 2\n\
+             c = -(9223372036854775808)\n\
+             f 128i8 = 1\n\
+             g = 1 - 128i8\n",
+        );
+        assert_eq!(5, definitions.len());
+        let out_of_range = |ty: &str, text: &str| {
+            (format!("literal out of range for `{ty}`"), text.to_string())
+        };
+        assert_eq!(
+            vec![
+                out_of_range("i8", "128i8"),
+                out_of_range("i8", "128i8"),
+                out_of_range("Int", "9223372036854775808"),
+                out_of_range("i8", "128i8"),
+                out_of_range("i8", "128i8"),
+            ],
+            diagnostics
+        );
     }
 }
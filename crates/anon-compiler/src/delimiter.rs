@@ -4,10 +4,68 @@ pub enum Delimiter {
     LParen,
     /// )
     RParen,
+    /// [
+    LBracket,
+    /// ]
+    RBracket,
+    /// {
+    LBrace,
+    /// }
+    RBrace,
     /// ,
     Comma,
     /// ::
     Annotate,
     /// _
     UnderScore,
+    /// |, separates data constructors
+    Bar,
+    /// @, as-pattern
+    At,
+    /// .
+    Dot,
+    /// \, starts a lambda like `\x -> x`
+    Backslash,
+}
+
+impl Delimiter {
+    /// the delimiter spelled `symbol`, if any
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        let delimiter = match symbol {
+            "(" => Self::LParen,
+            ")" => Self::RParen,
+            "[" => Self::LBracket,
+            "]" => Self::RBracket,
+            "{" => Self::LBrace,
+            "}" => Self::RBrace,
+            "," => Self::Comma,
+            "::" => Self::Annotate,
+            "_" => Self::UnderScore,
+            "|" => Self::Bar,
+            "@" => Self::At,
+            "." => Self::Dot,
+            "\\" => Self::Backslash,
+            _ => return None,
+        };
+        Some(delimiter)
+    }
+
+    /// how the delimiter is spelled
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LParen => "(",
+            Self::RParen => ")",
+            Self::LBracket => "[",
+            Self::RBracket => "]",
+            Self::LBrace => "{",
+            Self::RBrace => "}",
+            Self::Comma => ",",
+            Self::Annotate => "::",
+            Self::UnderScore => "_",
+            Self::Bar => "|",
+            Self::At => "@",
+            Self::Dot => ".",
+            Self::Backslash => "\\",
+        }
+    }
 }
@@ -0,0 +1,212 @@
+//! Decoding of escape sequences in string and character literals
+
+use anon_core::span::{Span, SpanIndex};
+
+/// the largest value accepted by `\x..`, which only denotes ASCII
+const MAX_HEX_ESCAPE: u32 = 0x7F;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeErrorKind {
+    /// `\q`
+    Unknown(char),
+    /// a `\` at the very end of the literal
+    LoneBackslash,
+    /// `\x` not followed by two hex digits
+    InvalidHex,
+    /// `\x80` and above
+    HexOutOfRange(u32),
+    /// `\u` not followed by `{`
+    MissingUnicodeBrace,
+    /// `\u{41` without the closing `}`
+    UnterminatedUnicode,
+    /// `\u{}`, `\u{1234567}` or a non hex digit inside the braces
+    InvalidUnicode,
+    /// `\u{D800}`, `\u{110000}` ...
+    InvalidCodePoint(u32),
+}
+
+impl std::fmt::Display for EscapeErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(c) => write!(f, "unknown escape sequence `\\{c}`"),
+            Self::LoneBackslash => write!(f, "incomplete escape sequence `\\`"),
+            Self::InvalidHex => {
+                write!(f, "`\\x` must be followed by exactly two hex digits")
+            }
+            Self::HexOutOfRange(value) => write!(
+                f,
+                "`\\x{value:02X}` is out of range, `\\x` escapes only go up to `\\x7F`"
+            ),
+            Self::MissingUnicodeBrace => {
+                write!(f, "`\\u` must be followed by `{{`, e.g. `\\u{{41}}`")
+            }
+            Self::UnterminatedUnicode => {
+                write!(f, "unterminated unicode escape, missing `}}`")
+            }
+            Self::InvalidUnicode => write!(
+                f,
+                "a unicode escape must contain between 1 and 6 hex digits"
+            ),
+            Self::InvalidCodePoint(value) => {
+                write!(f, "`\\u{{{value:X}}}` is not a valid unicode scalar value")
+            }
+        }
+    }
+}
+
+/// an invalid escape sequence, the span covers the whole sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EscapeError {
+    pub span: Span,
+    pub kind: EscapeErrorKind,
+}
+
+/// decodes the escape sequences in `content`, which is the text between the quotes.
+/// `offset` is the position of `content` in the source file, used for the error spans.
+///
+/// every malformed escape is reported, not only the first one
+pub fn unescape(content: &str, offset: SpanIndex) -> Result<String, Vec<EscapeError>> {
+    let mut decoded = String::with_capacity(content.len());
+    let mut errors = Vec::new();
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+
+        let result = match chars.next() {
+            Some((_, 'n')) => Ok('\n'),
+            Some((_, 't')) => Ok('\t'),
+            Some((_, 'r')) => Ok('\r'),
+            Some((_, '0')) => Ok('\0'),
+            Some((_, '\\')) => Ok('\\'),
+            Some((_, '\'')) => Ok('\''),
+            Some((_, '"')) => Ok('"'),
+            // 插值字符串里的字面量花括号
+            Some((_, '{')) => Ok('{'),
+            Some((_, '}')) => Ok('}'),
+            Some((_, 'x')) => {
+                let digits: String = (0..2)
+                    .map_while(|_| chars.next_if(|(_, c)| c.is_ascii_hexdigit()))
+                    .map(|(_, c)| c)
+                    .collect();
+                if digits.len() != 2 {
+                    Err(EscapeErrorKind::InvalidHex)
+                } else {
+                    let value = u32::from_str_radix(&digits, 16)
+                        .expect("two hex digits always fit in u32");
+                    if value > MAX_HEX_ESCAPE {
+                        Err(EscapeErrorKind::HexOutOfRange(value))
+                    } else {
+                        Ok(char::from(value as u8))
+                    }
+                }
+            }
+            Some((_, 'u')) => {
+                if chars.next_if(|&(_, c)| c == '{').is_none() {
+                    Err(EscapeErrorKind::MissingUnicodeBrace)
+                } else {
+                    let mut digits = String::new();
+                    let mut closed = false;
+                    while let Some((_, c)) = chars.next_if(|&(_, c)| c != '\\') {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        digits.push(c);
+                    }
+
+                    if !closed {
+                        Err(EscapeErrorKind::UnterminatedUnicode)
+                    } else if digits.is_empty()
+                        || digits.len() > 6
+                        || !digits.chars().all(|c| c.is_ascii_hexdigit())
+                    {
+                        Err(EscapeErrorKind::InvalidUnicode)
+                    } else {
+                        let value = u32::from_str_radix(&digits, 16)
+                            .expect("at most six hex digits always fit in u32");
+                        char::from_u32(value)
+                            .ok_or(EscapeErrorKind::InvalidCodePoint(value))
+                    }
+                }
+            }
+            Some((_, other)) => Err(EscapeErrorKind::Unknown(other)),
+            None => Err(EscapeErrorKind::LoneBackslash),
+        };
+
+        match result {
+            Ok(c) => decoded.push(c),
+            Err(kind) => {
+                let end = chars.peek().map_or(content.len(), |&(idx, _)| idx);
+                let span =
+                    Span::new(offset + start as SpanIndex, offset + end as SpanIndex)
+                        .expect("escape sequences end after they start");
+                errors.push(EscapeError { span, kind });
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(decoded)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_simple_escapes() {
+        assert_eq!(
+            Ok("a\nb\tc\rd\0e\\f'g\"h{}".to_string()),
+            unescape(r#"a\nb\tc\rd\0e\\f\'g\"h\{\}"#, 0)
+        );
+    }
+
+    #[test]
+    fn test_no_escapes() {
+        assert_eq!(Ok("plain text".to_string()), unescape("plain text", 0));
+    }
+
+    #[test]
+    fn test_hex_and_unicode() {
+        assert_eq!(Ok("A~".to_string()), unescape(r"\x41\x7e", 0));
+        assert_eq!(Ok("é😀".to_string()), unescape(r"\u{e9}\u{1F600}", 0));
+    }
+
+    #[test]
+    fn test_errors_point_at_the_escape() {
+        let errors = unescape(r"ok \q and \x8F and \u{D800}", 10).unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.kind, e.span.start(), e.span.end()))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (EscapeErrorKind::Unknown('q'), 13, 15),
+                (EscapeErrorKind::HexOutOfRange(0x8F), 20, 24),
+                (EscapeErrorKind::InvalidCodePoint(0xD800), 29, 37),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_malformed_escapes() {
+        let kind = |s: &str| unescape(s, 0).unwrap_err()[0].kind;
+
+        assert_eq!(EscapeErrorKind::InvalidHex, kind(r"\x4"));
+        assert_eq!(EscapeErrorKind::MissingUnicodeBrace, kind(r"\u41"));
+        assert_eq!(EscapeErrorKind::UnterminatedUnicode, kind(r"\u{41"));
+        assert_eq!(EscapeErrorKind::InvalidUnicode, kind(r"\u{}"));
+        assert_eq!(EscapeErrorKind::InvalidUnicode, kind(r"\u{1234567}"));
+        assert_eq!(EscapeErrorKind::InvalidUnicode, kind(r"\u{zz}"));
+        assert_eq!(EscapeErrorKind::LoneBackslash, kind("\\"));
+    }
+}
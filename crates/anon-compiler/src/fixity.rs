@@ -0,0 +1,449 @@
+//! Fixity of user defined operators, collected from `infixl`/`infixr`/`infix`
+//! declarations before the expressions of a module are parsed.
+
+use std::collections::HashMap;
+
+use anon_ast::{
+    fixity::{Associativity, Fixity, FixityDecl},
+    literal::Literal,
+    module::{Import, ImportNames, ListedName},
+};
+use anon_core::{
+    diagnostic::{Severity, SimpleDiagnostic},
+    interner::{Interner, Symbol},
+    span::{Span, Spanned},
+};
+
+use crate::{delimiter::Delimiter, keyword::Keyword, operator::Operator, token::Token};
+
+/// the fixities visible in one module
+///
+/// operators declared in the module are exported with it if its `export` list names them,
+/// operators imported from other modules are only visible here
+#[derive(Debug, Clone, Default)]
+pub struct FixityTable {
+    declared: HashMap<Symbol, (Fixity, Span)>,
+    // 导入的运算符和导入它的 `import`
+    imported: HashMap<Symbol, (Fixity, Span)>,
+}
+
+impl FixityTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// builds the table of a module from its fixity declarations
+    pub fn from_decls(
+        decls: &[FixityDecl],
+        interner: &Interner,
+    ) -> (Self, Vec<SimpleDiagnostic>) {
+        let mut table = Self::new();
+        let diagnostics = decls
+            .iter()
+            .flat_map(|decl| decl.operators.iter().map(move |&op| (op, decl)))
+            .filter_map(|(op, decl)| {
+                table.declare(op, decl.fixity, decl.span, interner).err()
+            })
+            .collect();
+        (table, diagnostics)
+    }
+
+    /// declares the fixity of `op` in this module, an operator can only be declared once
+    pub fn declare(
+        &mut self,
+        op: Symbol,
+        fixity: Fixity,
+        span: Span,
+        interner: &Interner,
+    ) -> Result<(), SimpleDiagnostic> {
+        if let Some(&(_, previous)) = self.declared.get(&op) {
+            let name = interner.resolve(op).unwrap_or("<unknown>");
+            return Err(SimpleDiagnostic::new(
+                Severity::Error,
+                format!("duplicate fixity declaration for `{name}`"),
+                span,
+                Some(Box::new(SimpleDiagnostic::new(
+                    Severity::Note,
+                    "previously declared here".to_string(),
+                    previous,
+                    None,
+                ))),
+            ));
+        }
+        self.declared.insert(op, (fixity, span));
+        Ok(())
+    }
+
+    /// makes the operators exported by another module and named by `import` visible in
+    /// this one, local declarations take precedence over imported ones
+    ///
+    /// an operator imported with two different fixities is an error
+    pub fn import(
+        &mut self,
+        exporter: &FixityTable,
+        import: &Import,
+        interner: &Interner,
+    ) -> Vec<SimpleDiagnostic> {
+        let listed =
+            |names: &[ListedName], op| names.iter().any(|name| name.name == op);
+        let mut diagnostics = Vec::new();
+        for (op, fixity) in exporter.exports() {
+            let visible = match &import.names {
+                ImportNames::All => true,
+                ImportNames::Only(names) => listed(names, op),
+                ImportNames::Hiding(names) => !listed(names, op),
+            };
+            if !visible || self.declared.contains_key(&op) {
+                continue;
+            }
+            match self.imported.get(&op) {
+                Some(&(previous, span)) if previous != fixity => {
+                    let name = interner.resolve(op).unwrap_or("<unknown>");
+                    diagnostics.push(SimpleDiagnostic::new(
+                        Severity::Error,
+                        format!("`{name}` is imported with two different fixities"),
+                        import.span,
+                        Some(Box::new(SimpleDiagnostic::new(
+                            Severity::Note,
+                            "previously imported here".to_string(),
+                            span,
+                            None,
+                        ))),
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    self.imported.insert(op, (fixity, import.span));
+                }
+            }
+        }
+        diagnostics
+    }
+
+    /// the fixities declared in this module, in the order of their declarations
+    pub fn exports(&self) -> impl Iterator<Item = (Symbol, Fixity)> + '_ {
+        let mut declared: Vec<_> = self.declared.iter().collect();
+        declared.sort_by_key(|(_, (_, span))| span.start());
+        declared.into_iter().map(|(&op, &(fixity, _))| (op, fixity))
+    }
+
+    /// the table of what the module exports, the operators named by its `export` list,
+    /// every declared operator without one
+    pub fn exported(&self, export: Option<&[ListedName]>) -> FixityTable {
+        let declared = self
+            .declared
+            .iter()
+            .filter(|&(&op, _)| {
+                export.is_none_or(|names| names.iter().any(|name| name.name == op))
+            })
+            .map(|(&op, &declared)| (op, declared))
+            .collect();
+        Self {
+            declared,
+            imported: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, op: Symbol) -> Option<Fixity> {
+        self.declared
+            .get(&op)
+            .or_else(|| self.imported.get(&op))
+            .map(|&(fixity, _)| fixity)
+    }
+
+    /// the fixity of `op`, falling back to the default `infixl 9`
+    pub fn fixity_of(&self, op: Symbol) -> Fixity {
+        self.get(op).unwrap_or_default()
+    }
+}
+
+/// the fixity of a built-in binary operator, `None` for operators that are not
+/// used infix in expressions, e.g. `=` or `->`
+///
+/// a [FixityTable] entry with the same spelling overrides it
+pub fn builtin_fixity(op: Operator) -> Option<Fixity> {
+    use Associativity::{Left, None as NonAssoc, Right};
+
+    let (associativity, precedence) = match op {
+        Operator::Apply => (Right, 0),
+        Operator::Or => (Right, 2),
+        Operator::And => (Right, 3),
+        Operator::EqEq
+        | Operator::NotEq
+        | Operator::Lt
+        | Operator::Le
+        | Operator::Gt
+        | Operator::Ge => (NonAssoc, 4),
+        Operator::Range => (NonAssoc, 5),
+        Operator::Add | Operator::Negate => (Left, 6),
+        Operator::Mul | Operator::Div | Operator::Mod => (Left, 7),
+        Operator::ComposeRight | Operator::ComposeLeft => (Right, 9),
+        Operator::Eq | Operator::Arrow | Operator::FatArrow | Operator::Not => {
+            return None;
+        }
+    };
+    Some(Fixity::new(associativity, precedence))
+}
+
+/// finds every fixity declaration of a module, so that operators can be used
+/// before the line declaring their fixity
+///
+/// only the unindented lines are read, a declaration nested in a `let`, `where` or `can`
+/// block is not one of the module
+pub fn collect_fixity_decls(
+    tokens: &[Spanned<Token>],
+) -> (Vec<FixityDecl>, Vec<SimpleDiagnostic>) {
+    let mut decls = Vec::new();
+    let mut diagnostics = Vec::new();
+    // INDENT 和 DEDENT 总是成对出现，括号里的行没有它们
+    let mut depth = 0usize;
+
+    for (idx, tok) in tokens.iter().enumerate() {
+        match tok.value {
+            Token::Indent => depth += 1,
+            Token::Dedent => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        let at_line_start = idx == 0
+            || matches!(
+                tokens[idx - 1].value,
+                Token::Newline | Token::Indent | Token::Dedent
+            );
+        if depth == 0 && at_line_start && fixity_keyword(tok.value).is_some() {
+            match parse_fixity_decl(&tokens[idx..]) {
+                Ok((decl, _)) => decls.push(decl),
+                Err(err) => diagnostics.push(err),
+            }
+        }
+    }
+
+    (decls, diagnostics)
+}
+
+fn fixity_keyword(tok: Token) -> Option<Associativity> {
+    match tok {
+        Token::Keyword(Keyword::InfixL) => Some(Associativity::Left),
+        Token::Keyword(Keyword::InfixR) => Some(Associativity::Right),
+        Token::Keyword(Keyword::Infix) => Some(Associativity::None),
+        _ => None,
+    }
+}
+
+/// parses `infixl 6 <+>, <->` at the start of `tokens`,
+/// returns the declaration and the number of tokens it spans
+///
+/// the precedence defaults to 9 when omitted
+pub fn parse_fixity_decl(
+    tokens: &[Spanned<Token>],
+) -> Result<(FixityDecl, usize), SimpleDiagnostic> {
+    let error = |message: &str, span: Span| {
+        SimpleDiagnostic::new(Severity::Error, message.to_string(), span, None)
+    };
+
+    let keyword = tokens
+        .first()
+        .expect("a fixity declaration starts with a keyword");
+    let associativity = fixity_keyword(keyword.value)
+        .expect("a fixity declaration starts with infix, infixl or infixr");
+    let mut span = keyword.context;
+    let mut idx = 1;
+
+    let mut precedence = Fixity::MAX_PRECEDENCE;
+    if let Some(tok) = tokens.get(idx)
+        && let Token::Literal(Literal::Integer(value)) = tok.value
+    {
+        precedence = u8::try_from(value)
+            .ok()
+            .filter(|&p| p <= Fixity::MAX_PRECEDENCE)
+            .ok_or_else(|| {
+                error(
+                    &format!(
+                        "precedence must be between 0 and {}, found {value}",
+                        Fixity::MAX_PRECEDENCE
+                    ),
+                    tok.context,
+                )
+            })?;
+        span = span.to(tok.context);
+        idx += 1;
+    }
+
+    let mut operators = Vec::new();
+    loop {
+        match tokens.get(idx) {
+            Some(Spanned {
+                context,
+                value: Token::SymbolicIdentifier(op),
+            }) => {
+                operators.push(*op);
+                span = span.to(*context);
+                idx += 1;
+            }
+            other => {
+                let at = other.map_or(Span::empty(span.end()), |tok| tok.context);
+                return Err(error("expected an operator, e.g. `<+>`", at));
+            }
+        }
+
+        match tokens.get(idx) {
+            Some(tok) if tok.value == Token::Delimiter(Delimiter::Comma) => idx += 1,
+            _ => break,
+        }
+    }
+
+    match tokens.get(idx) {
+        None
+        | Some(Spanned {
+            value: Token::Newline | Token::EOF,
+            ..
+        }) => {}
+        Some(tok) => {
+            return Err(error(
+                "unexpected token after fixity declaration",
+                tok.context,
+            ));
+        }
+    }
+
+    let fixity = Fixity::new(associativity, precedence);
+    Ok((
+        FixityDecl {
+            fixity,
+            operators,
+            span,
+        },
+        idx,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use anon_ast::module::ModulePath;
+
+    use super::*;
+    use crate::Lexer;
+
+    fn lex(src: &str, interner: &Rc<RefCell<Interner>>) -> Vec<Spanned<Token>> {
+        Lexer::new(src, 4, interner.clone()).collect()
+    }
+
+    #[test]
+    fn test_collect_fixity_decls() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let tokens = lex(
+            "x = a <+> b >>= c\ninfixl 6 <+>, <->\ninfixr 1 >>=\ninfix ===\n",
+            &interner,
+        );
+
+        let (decls, diagnostics) = collect_fixity_decls(&tokens);
+        assert!(diagnostics.is_empty());
+
+        let sym = |s: &str| interner.borrow_mut().intern_or_get(s);
+        let expected = vec![
+            (
+                Fixity::new(Associativity::Left, 6),
+                vec![sym("<+>"), sym("<->")],
+            ),
+            (Fixity::new(Associativity::Right, 1), vec![sym(">>=")]),
+            (Fixity::new(Associativity::None, 9), vec![sym("===")]),
+        ];
+        let found: Vec<_> = decls
+            .iter()
+            .map(|d| (d.fixity, d.operators.clone()))
+            .collect();
+        assert_eq!(expected, found);
+
+        let spans: Vec<_> = decls
+            .iter()
+            .map(|d| (d.span.start(), d.span.end()))
+            .collect();
+        assert_eq!(vec![(18, 35), (36, 48), (49, 58)], spans);
+    }
+
+    #[test]
+    fn test_nested_fixity_decls_are_skipped() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let tokens = lex(
+            "type T = data { T } can {\n  infixl 6 <+>\n}\n\
+             f = x\n  where\n    infixl 6 <->\n    g =\n      infix 4 <=>\n\
+             infixr 1 >>=\n",
+            &interner,
+        );
+
+        let (decls, diagnostics) = collect_fixity_decls(&tokens);
+        assert!(diagnostics.is_empty());
+        let sym = |s: &str| interner.borrow_mut().intern_or_get(s);
+        let found: Vec<_> = decls.iter().map(|d| d.operators.clone()).collect();
+        assert_eq!(vec![vec![sym(">>=")]], found);
+    }
+
+    #[test]
+    fn test_invalid_fixity_decls() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let tokens = lex("infixl 10 <+>\ninfixr 5 +\ninfix 4 <=> x\n", &interner);
+
+        let (decls, diagnostics) = collect_fixity_decls(&tokens);
+        assert!(decls.is_empty());
+
+        let spans: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.primary_span.start(), d.primary_span.end()))
+            .collect();
+        assert_eq!(vec![(7, 9), (23, 24), (37, 38)], spans);
+    }
+
+    #[test]
+    fn test_duplicate_fixity() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let tokens = lex("infixl 6 <+>\ninfixr 5 <+>\n", &interner);
+
+        let (decls, _) = collect_fixity_decls(&tokens);
+        let (table, diagnostics) = FixityTable::from_decls(&decls, &interner.borrow());
+
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            "duplicate fixity declaration for `<+>`",
+            diagnostics[0].message
+        );
+        let op = interner.borrow_mut().intern_or_get("<+>");
+        assert_eq!(Some(Fixity::new(Associativity::Left, 6)), table.get(op));
+    }
+
+    #[test]
+    fn test_fixities_are_scoped_per_module() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let sym = |s: &str| interner.borrow_mut().intern_or_get(s);
+
+        let (decls, _) = collect_fixity_decls(&lex("infixr 2 <|>\n", &interner));
+        let (library, _) = FixityTable::from_decls(&decls, &interner.borrow());
+
+        let (decls, _) = collect_fixity_decls(&lex("infixl 4 <$>\n", &interner));
+        let (mut user, _) = FixityTable::from_decls(&decls, &interner.borrow());
+
+        // not imported yet
+        assert_eq!(None, user.get(sym("<|>")));
+        assert_eq!(Fixity::default(), user.fixity_of(sym("<|>")));
+
+        let import = Import {
+            module: ModulePath {
+                segments: vec![sym("Library")],
+                span: Span::empty(0u32),
+            },
+            alias: None,
+            names: ImportNames::All,
+            span: Span::empty(0u32),
+        };
+        let errors = user.import(&library, &import, &interner.borrow());
+        assert!(errors.is_empty());
+        assert_eq!(
+            Some(Fixity::new(Associativity::Right, 2)),
+            user.get(sym("<|>"))
+        );
+
+        // only the module's own declarations are exported
+        let exported: Vec<_> = user.exports().map(|(op, _)| op).collect();
+        assert_eq!(vec![sym("<$>")], exported);
+    }
+}
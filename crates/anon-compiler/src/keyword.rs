@@ -14,4 +14,74 @@ pub enum Keyword {
 
     Data,
     Type,
+    Where,
+    /// `can { ... }`, the methods of a type
+    Can,
+    /// `refine Name = Type where predicate`
+    Refine,
+    /// `module Foo.Bar`, the header of a file
+    Module,
+
+    /// infix, non-associative fixity declaration
+    Infix,
+    /// infixl
+    InfixL,
+    /// infixr
+    InfixR,
+}
+
+impl Keyword {
+    /// the keyword spelled `word`, if any
+    pub fn from_word(word: &str) -> Option<Self> {
+        let keyword = match word {
+            "let" => Self::Let,
+            "in" => Self::In,
+            "if" => Self::If,
+            "then" => Self::Then,
+            "else" => Self::Else,
+            "match" => Self::Match,
+            "case" => Self::Case,
+            "import" => Self::Import,
+            "export" => Self::Export,
+            "class" => Self::Class,
+            "instance" => Self::Instance,
+            "data" => Self::Data,
+            "type" => Self::Type,
+            "where" => Self::Where,
+            "can" => Self::Can,
+            "refine" => Self::Refine,
+            "module" => Self::Module,
+            "infix" => Self::Infix,
+            "infixl" => Self::InfixL,
+            "infixr" => Self::InfixR,
+            _ => return None,
+        };
+        Some(keyword)
+    }
+
+    /// how the keyword is spelled
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Let => "let",
+            Self::In => "in",
+            Self::If => "if",
+            Self::Then => "then",
+            Self::Else => "else",
+            Self::Match => "match",
+            Self::Case => "case",
+            Self::Import => "import",
+            Self::Export => "export",
+            Self::Class => "class",
+            Self::Instance => "instance",
+            Self::Data => "data",
+            Self::Type => "type",
+            Self::Where => "where",
+            Self::Can => "can",
+            Self::Refine => "refine",
+            Self::Module => "module",
+            Self::Infix => "infix",
+            Self::InfixL => "infixl",
+            Self::InfixR => "infixr",
+        }
+    }
 }
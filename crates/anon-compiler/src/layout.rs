@@ -0,0 +1,441 @@
+//! The layout pass between [crate::Lexer] and the parser.
+//!
+//! The lexer only knows about the indentation of whole lines, but blocks also open in the
+//! middle of a line, e.g. `let x = 1 in x` or the arms after `match x`.
+//! Following the offside rule, this pass makes the blocks explicit:
+//!
+//! - the first token after `let`, `where` or `can`, or the first token of the line after `match`,
+//!   opens a block at its column with [Token::VirtualOpen]
+//! - a line starting at the column of the innermost block is a new item of the block and is
+//!   preceded by [Token::VirtualSemi], the top level items of a file too
+//! - a line starting left of a block closes it with [Token::VirtualClose]
+//!
+//! Blocks are also closed where the parser could not go on otherwise, the `parse-error(t)`
+//! rule of Haskell: `in` closes a `let` block and a closing bracket closes the blocks opened
+//! inside of it. A layout keyword followed by a `{` that ends the line, like `can {`, opens
+//! the block inside the braces.
+//!
+//! `NEWLINE`, `INDENT` and `DEDENT` are consumed by this pass.
+
+use std::{collections::VecDeque, iter::Peekable};
+
+use anon_core::span::{Span, Spanned};
+
+use crate::{
+    Lexer, delimiter::Delimiter, keyword::Keyword, token::Token,
+    token_stream::SpannedTokenStream,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    /// the top level items of a file, opened and closed without virtual tokens
+    File,
+    Let,
+    Where,
+    Match,
+    /// the methods in `can { ... }`
+    Can,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    /// a block opened by layout, at the column of its first token
+    Implicit { column: usize, kind: BlockKind },
+    /// a bracket written in the source
+    Explicit,
+}
+
+/// a layout keyword waiting for the token that opens its block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    NextToken(BlockKind),
+    NextLine(BlockKind),
+}
+
+impl Pending {
+    fn kind(self) -> BlockKind {
+        match self {
+            Self::NextToken(kind) | Self::NextLine(kind) => kind,
+        }
+    }
+}
+
+fn layout_keyword(token: Token) -> Option<Pending> {
+    match token {
+        Token::Keyword(Keyword::Let) => Some(Pending::NextToken(BlockKind::Let)),
+        Token::Keyword(Keyword::Where) => Some(Pending::NextToken(BlockKind::Where)),
+        Token::Keyword(Keyword::Can) => Some(Pending::NextToken(BlockKind::Can)),
+        Token::Keyword(Keyword::Match) => Some(Pending::NextLine(BlockKind::Match)),
+        _ => None,
+    }
+}
+
+/// inserts the virtual block tokens into the tokens of a [Lexer]
+#[derive(Debug, Clone)]
+pub struct Layout<'a, I: Iterator<Item = Spanned<Token>>> {
+    tokens: Peekable<I>,
+    src: &'a str,
+    tab_width: u32,
+    contexts: Vec<Context>,
+    pending: Option<Pending>,
+    // `pending` 出现时 contexts 的深度，之后才打开的括号关闭时不会结束它
+    pending_depth: usize,
+    // 下一个 token 是一行的开头
+    at_line_start: bool,
+    // 行首的 doc comment 和错误，等这一行的第一个 token 决定了 layout 之后再输出
+    held: Vec<Spanned<Token>>,
+    output: VecDeque<Spanned<Token>>,
+    finished: bool,
+}
+
+impl<'a> Layout<'a, Lexer<'a>> {
+    pub fn from_lexer(lexer: Lexer<'a>) -> Self {
+        let (src, tab_width) = (lexer.source(), lexer.config().tab_width);
+        Self::new(lexer.for_layout(), src, tab_width)
+    }
+}
+
+impl<'a, I: Iterator<Item = Spanned<Token>>> Layout<'a, I> {
+    /// `tokens` must be the tokens of `src`, lexed with the same `tab_width`
+    pub fn new(tokens: I, src: &'a str, tab_width: u32) -> Self {
+        Self {
+            tokens: tokens.peekable(),
+            src,
+            tab_width,
+            contexts: Vec::new(),
+            pending: Some(Pending::NextToken(BlockKind::File)),
+            pending_depth: 0,
+            at_line_start: true,
+            held: Vec::new(),
+            output: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// the column of the start of `span`, a tab counts for `tab_width` columns
+    fn column(&self, span: Span) -> usize {
+        let start = span.start() as usize;
+        let line_start = self.src[..start].rfind('\n').map_or(0, |idx| idx + 1);
+        self.src[line_start..start]
+            .chars()
+            .map(|c| {
+                if c == '\t' {
+                    self.tab_width as usize
+                } else {
+                    1
+                }
+            })
+            .sum()
+    }
+
+    fn emit(&mut self, token: Token, span: Span) {
+        self.output.push_back(Spanned::new(span, token));
+    }
+
+    fn emit_held(&mut self) {
+        self.output.extend(self.held.drain(..));
+    }
+
+    fn step(&mut self, token: Spanned<Token>) {
+        match token.value {
+            Token::Newline => self.at_line_start = true,
+            Token::Indent | Token::Dedent => {}
+            Token::EOF => self.finish(token.context),
+            Token::Error | Token::DocComment(_) if self.at_line_start => {
+                self.held.push(token);
+            }
+            _ => self.significant(token),
+        }
+    }
+
+    fn significant(&mut self, token: Spanned<Token>) {
+        let at = Span::empty(token.context.start());
+
+        if let Some(pending) = self.pending
+            && (matches!(pending, Pending::NextToken(_)) || self.at_line_start)
+        {
+            self.pending = None;
+            let kind = pending.kind();
+
+            // `can {` 这样在行尾的花括号，块从花括号里的第一个 token 开始，
+            // 其他的花括号是显式的块，没有 layout
+            if token.value == Token::Delimiter(Delimiter::LBrace) {
+                let ends_line = self.tokens.peek().is_none_or(|next| {
+                    matches!(next.value, Token::Newline | Token::EOF)
+                });
+                self.line_start(token.context);
+                self.emit_held();
+                self.token(token);
+                if ends_line {
+                    self.set_pending(Pending::NextToken(kind));
+                }
+                return;
+            }
+
+            let column = self.column(token.context);
+            let fits = match self.contexts.last() {
+                Some(Context::Implicit { column: outer, .. }) => column > *outer,
+                Some(Context::Explicit) | None => true,
+            };
+            if kind != BlockKind::File {
+                self.emit(Token::VirtualOpen, at);
+            }
+            if fits {
+                self.contexts.push(Context::Implicit { column, kind });
+                self.at_line_start = false;
+                self.emit_held();
+                self.token(token);
+                return;
+            }
+            // 比外层的块缩进得还少，是一个空的块
+            self.emit(Token::VirtualClose, at);
+        }
+
+        self.line_start(token.context);
+        self.emit_held();
+        self.token(token);
+    }
+
+    /// closes the blocks the line at `span` is left of and separates it from the previous item
+    fn line_start(&mut self, span: Span) {
+        if !std::mem::take(&mut self.at_line_start) {
+            return;
+        }
+        let column = self.column(span);
+        let at = Span::empty(span.start());
+
+        while let Some(&Context::Implicit {
+            column: block,
+            kind,
+        }) = self.contexts.last()
+            && column < block
+            && kind != BlockKind::File
+        {
+            self.contexts.pop();
+            self.emit(Token::VirtualClose, at);
+        }
+        if let Some(&Context::Implicit {
+            column: block,
+            kind,
+        }) = self.contexts.last()
+            && (column == block || kind == BlockKind::File && column < block)
+        {
+            self.emit(Token::VirtualSemi, at);
+        }
+    }
+
+    /// emits a token that is not at the start of a block
+    fn token(&mut self, token: Spanned<Token>) {
+        let at = Span::empty(token.context.start());
+        match token.value {
+            Token::Keyword(Keyword::In) => {
+                if let Some(Context::Implicit {
+                    kind: BlockKind::Let,
+                    ..
+                }) = self.contexts.last()
+                {
+                    self.contexts.pop();
+                    self.emit(Token::VirtualClose, at);
+                }
+            }
+            Token::Delimiter(
+                Delimiter::LParen | Delimiter::LBracket | Delimiter::LBrace,
+            ) => {
+                self.contexts.push(Context::Explicit);
+            }
+            Token::Delimiter(
+                Delimiter::RParen | Delimiter::RBracket | Delimiter::RBrace,
+            ) => {
+                // `(match x)` 的块是空的，`match (x)` 的块还没开始
+                if self.contexts.len() <= self.pending_depth
+                    && self.pending.take().is_some()
+                {
+                    self.emit(Token::VirtualOpen, at);
+                    self.emit(Token::VirtualClose, at);
+                }
+                if self.contexts.contains(&Context::Explicit) {
+                    while let Some(Context::Implicit { .. }) = self.contexts.pop() {
+                        self.emit(Token::VirtualClose, at);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.output.push_back(token);
+        if let Some(pending) = layout_keyword(token.value) {
+            self.set_pending(pending);
+        }
+    }
+
+    fn set_pending(&mut self, pending: Pending) {
+        self.pending = Some(pending);
+        self.pending_depth = self.contexts.len();
+    }
+
+    /// closes every open block and emits EOF
+    fn finish(&mut self, eof: Span) {
+        self.emit_held();
+        if self
+            .pending
+            .take()
+            .is_some_and(|pending| pending.kind() != BlockKind::File)
+        {
+            self.emit(Token::VirtualOpen, eof);
+            self.emit(Token::VirtualClose, eof);
+        }
+        while let Some(context) = self.contexts.pop() {
+            if let Context::Implicit { kind, .. } = context
+                && kind != BlockKind::File
+            {
+                self.emit(Token::VirtualClose, eof);
+            }
+        }
+        self.emit(Token::EOF, eof);
+        self.finished = true;
+    }
+}
+
+impl<'a, I: Iterator<Item = Spanned<Token>>> Iterator for Layout<'a, I> {
+    type Item = Spanned<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.output.pop_front() {
+                return Some(token);
+            }
+            if self.finished {
+                return None;
+            }
+            match self.tokens.next() {
+                Some(token) => self.step(token),
+                None => self.finished = true,
+            }
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Spanned<Token>>> SpannedTokenStream<'a> for Layout<'a, I> {}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use anon_core::interner::Interner;
+
+    use super::*;
+
+    /// the source text of every token, virtual tokens are written `<{`, `;` and `}>`
+    fn layout(src: &str) -> String {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = Lexer::new(src, 4, interner);
+        Layout::from_lexer(lexer)
+            .map(|token| match token.value {
+                Token::VirtualOpen => "<{",
+                Token::VirtualSemi => ";",
+                Token::VirtualClose => "}>",
+                Token::EOF => "EOF",
+                _ => {
+                    let span = token.context;
+                    &src[span.start() as usize..span.end() as usize]
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_top_level_items() {
+        assert_eq!("EOF", layout(""));
+        assert_eq!("f = 1 ; g = x EOF", layout("f = 1\n\n-- c\ng =\n    x\n"));
+    }
+
+    #[test]
+    fn test_let_in_on_one_line() {
+        assert_eq!(
+            "f = let <{ x = 1 ; y = 2 }> in x EOF",
+            layout("f = let x = 1\n        y = 2 in x\n")
+        );
+        assert_eq!("f = let <{ x = 1 }> in x EOF", layout("f = let x = 1 in x"));
+    }
+
+    #[test]
+    fn test_let_block_closed_by_dedent() {
+        let src = "f =\n  let\n    a = 1\n    b = 2\n  in a\ng = 3\n";
+        assert_eq!("f = let <{ a = 1 ; b = 2 }> in a ; g = 3 EOF", layout(src));
+    }
+
+    #[test]
+    fn test_dedent_between_blocks() {
+        // `in` 和 `where` 在外层的行和块之间的列
+        let src = "f = let a = 1\n        b = 2\n    in a\n";
+        assert_eq!("f = let <{ a = 1 ; b = 2 }> in a EOF", layout(src));
+        let src = "f x = match x\n        A -> 1\n    where y = 1\n";
+        assert_eq!(
+            "f x = match x <{ A -> 1 }> where <{ y = 1 }> EOF",
+            layout(src)
+        );
+    }
+
+    #[test]
+    fn test_match_arms() {
+        let src = "f x =\n  match x\n    Just y -> y\n    None ->\n      0\n  where\n    z = 1\n";
+        assert_eq!(
+            "f x = match x <{ Just y -> y ; None -> 0 }> where <{ z = 1 }> EOF",
+            layout(src)
+        );
+    }
+
+    #[test]
+    fn test_nested_blocks_close_together() {
+        let src = "f = match a\n      X -> let b = 1\n               c = 2\ng = h\n";
+        assert_eq!(
+            "f = match a <{ X -> let <{ b = 1 ; c = 2 }> }> ; g = h EOF",
+            layout(src)
+        );
+    }
+
+    #[test]
+    fn test_empty_blocks() {
+        // 下一行没有比外层的块缩进更多
+        assert_eq!("f = match x <{ }> ; g EOF", layout("f = match x\ng\n"));
+        assert_eq!("x = let <{ }> EOF", layout("x = let"));
+        assert_eq!("( match x <{ }> ) EOF", layout("(match x)"));
+        assert_eq!(
+            "match ( x ) <{ X -> 1 }> EOF",
+            layout("match (x)\n  X -> 1\n")
+        );
+    }
+
+    #[test]
+    fn test_brackets_close_blocks() {
+        assert_eq!(
+            "f = ( let <{ x = 1 }> ) y EOF",
+            layout("f = (let x = 1) y\n")
+        );
+        assert_eq!(
+            "f = [ let <{ x = ( 1 , 2 ) }> ] EOF",
+            layout("f = [let x = (1,\n 2)]\n")
+        );
+    }
+
+    #[test]
+    fn test_layout_keyword_before_brace_at_line_end() {
+        let src = "t where {\n    f = 1\n    g = 2\n}\nh\n";
+        assert_eq!("t where { <{ f = 1 ; g = 2 }> } ; h EOF", layout(src));
+
+        // 行内的花括号是显式的块，没有 layout
+        assert_eq!("t where { f } EOF", layout("t where { f }\n"));
+        let src = "type T = data { T }\ncan {\n  f = 1\n  g = 2\n}\nh\n";
+        assert_eq!(
+            "type T = data { T } ; can { <{ f = 1 ; g = 2 }> } ; h EOF",
+            layout(src)
+        );
+    }
+
+    #[test]
+    fn test_doc_comment_follows_separator() {
+        let src = "f = 1\n--| doc\ng = 2\n";
+        assert_eq!("f = 1 ; --| doc g = 2 EOF", layout(src));
+    }
+}
@@ -1,299 +1,1057 @@
-use core::panic;
-use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+use std::{borrow::Cow, cell::RefCell, collections::VecDeque, rc::Rc};
 
-use anon_core::interner::Interner;
-use pest::{
-    Parser as _,
-    iterators::{Pair, Pairs},
+use anon_ast::literal::Literal;
+use anon_core::{
+    diagnostic::{Severity, SimpleDiagnostic},
+    interner::Interner,
+    span::{Span, SpanIndex, Spanned},
 };
+use unicode_ident::{is_xid_continue, is_xid_start};
+use unicode_normalization::{UnicodeNormalization, is_nfc};
 
 use crate::{
-    line_tokenizer::{LineTokenizer, PestParser, Rule},
+    delimiter::Delimiter,
+    escape::unescape,
+    keyword::Keyword,
+    number::{parse_float, parse_integer},
+    operator::Operator,
+    string_literal::{parse_string, shift, string_tokens},
     token::Token,
+    token_stream::SpannedTokenStream,
 };
 
+mod config;
+mod incremental;
+
+pub use config::{LexerConfig, TabPolicy};
+pub use incremental::{IncrementalLexer, TextEdit};
+
+/// normalises an identifier to NFC, so that visually identical names are interned
+/// to the same symbol
+pub fn normalize_ident(ident: &str) -> Cow<'_, str> {
+    if is_nfc(ident) {
+        Cow::Borrowed(ident)
+    } else {
+        Cow::Owned(ident.nfc().collect())
+    }
+}
+
+/// the characters user defined operators are made of
+fn is_symbol_char(c: char) -> bool {
+    matches!(
+        c,
+        '!' | '#'
+            | '$'
+            | '%'
+            | '&'
+            | '*'
+            | '+'
+            | '.'
+            | '/'
+            | '<'
+            | '='
+            | '>'
+            | '?'
+            | '@'
+            | '^'
+            | '|'
+            | '-'
+            | '~'
+            | ':'
+    )
+}
+
+fn starts_with_newline(text: &str) -> bool {
+    text.starts_with('\n') || text.starts_with("\r\n")
+}
+
+/// pushes the brackets opened by the `tokens` of a line and pops the closed ones,
+/// `true` marks a bracket whose lines are joined.
+///
+/// a `{` that ends its line opens an indented block like `can {` and keeps the layout,
+/// a closing bracket without an opening one is ignored
+pub(crate) fn update_brackets(open: &mut Vec<bool>, tokens: &[Spanned<Token>]) {
+    for (idx, token) in tokens.iter().enumerate() {
+        match token.value {
+            Token::Delimiter(Delimiter::LParen | Delimiter::LBracket) => {
+                open.push(true)
+            }
+            Token::Delimiter(Delimiter::LBrace) => {
+                let ends_line = tokens
+                    .get(idx + 1)
+                    .is_none_or(|next| next.value == Token::Newline);
+                open.push(!ends_line);
+            }
+            Token::Delimiter(
+                Delimiter::RParen | Delimiter::RBracket | Delimiter::RBrace,
+            ) => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// the length of the nested `{- ... -}` comment at the start of `text`,
+/// `None` if it is not closed
+pub(crate) fn block_comment_len(text: &str) -> Option<usize> {
+    debug_assert!(text.starts_with("{-"));
+    let mut pos = 2;
+    loop {
+        let rest = &text[pos..];
+        if rest.starts_with("{-")
+            && let Some(len) = block_comment_len(rest)
+        {
+            pos += len;
+        } else if rest.starts_with("-}") {
+            return Some(pos + 2);
+        } else {
+            pos += rest.chars().next()?.len_utf8();
+        }
+    }
+}
+
+/// 单遍扫描源码的 lexer，直接在 `&str` 上工作，每次处理一行并维护缩进
 #[derive(Debug, Clone)]
 pub struct Lexer<'a> {
-    // 外部迭代器：提供 Rule::LINE 和 Rule::EOI
-    file_pairs: Pairs<'a, Rule>,
+    src: &'a str,
+    // 下一行开始的位置
+    pos: usize,
     // 缩进状态
     indent_stack: Vec<usize>,
-    // 输出缓冲区：用于存储 INDENT/DEDENT
-    output_buffer: VecDeque<Token>,
-    // Tab 宽度
-    tab_width: u32,
-    // ... 其他字段 ...
+    // 输出缓冲区：当前行的 token 和 INDENT/DEDENT
+    output_buffer: VecDeque<Spanned<Token>>,
+    // 当前行的 token，复用以避免每行分配
+    line_tokens: Vec<Spanned<Token>>,
+    // Tab 宽度和缩进规则
+    config: LexerConfig,
+    // 还没有闭合的括号，其中有 `true` 时换行不产生 layout token
+    open_brackets: Vec<bool>,
+    // 词法错误，lexer 遇到错误不会停止
+    diagnostics: Vec<SimpleDiagnostic>,
+    // 已经输出了 EOF
+    finished: bool,
     interner: Rc<RefCell<Interner>>,
 }
 
 impl<'a> Lexer<'a> {
-    pub fn new(
-        test_str: &'a str,
-        tab_width: u32,
-        interner: Rc<RefCell<Interner>>,
-    ) -> Self {
-        let file = PestParser::parse(Rule::File, test_str)
-            .expect("unsuccessful parse")
-            .next()
-            .unwrap();
-        Self::new_from_pair(file, tab_width, interner)
+    pub fn new(src: &'a str, tab_width: u32, interner: Rc<RefCell<Interner>>) -> Self {
+        Self::with_config(src, LexerConfig::new(tab_width), interner)
     }
 
-    pub fn new_from_pair(
-        file_pair: Pair<'a, Rule>,
-        tab_width: u32,
+    pub fn with_config(
+        src: &'a str,
+        config: LexerConfig,
         interner: Rc<RefCell<Interner>>,
     ) -> Self {
-        debug_assert_eq!(
-            file_pair.as_rule(),
-            Rule::File,
-            "Lexer should only receive File rule"
-        );
-
         Self {
-            file_pairs: file_pair.into_inner(), // LINE, LINE, ..., EOI
+            src,
+            pos: 0,
             // 根缩进 [0]
             indent_stack: vec![0],
             output_buffer: VecDeque::new(),
-            tab_width,
+            line_tokens: Vec::new(),
+            config,
+            open_brackets: Vec::new(),
+            diagnostics: Vec::new(),
+            finished: false,
             interner,
         }
     }
-}
 
-impl<'a> Iterator for Lexer<'a> {
-    type Item = Token;
+    pub fn source(&self) -> &'a str {
+        self.src
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        // 优先从缓存里读取
-        if let Some(buffered_token) = self.output_buffer.pop_front() {
-            return Some(buffered_token);
+    pub fn config(&self) -> LexerConfig {
+        self.config
+    }
+
+    /// leaves the dedents to the layout pass, see [LexerConfig::layout]
+    pub(crate) fn for_layout(mut self) -> Self {
+        self.config = self.config.with_layout();
+        self
+    }
+
+    /// the lexical errors reported so far
+    pub fn diagnostics(&self) -> &[SimpleDiagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<SimpleDiagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(start as SpanIndex, end as SpanIndex)
+            .expect("tokens never end before they start")
+    }
+
+    /// records a lexical error and returns the [Token::Error] that replaces the input
+    fn error(&mut self, span: Span, message: String) -> Token {
+        self.diagnostics.push(SimpleDiagnostic::new(
+            Severity::Error,
+            message,
+            span,
+            None,
+        ));
+        Token::Error
+    }
+
+    /// lexes one line starting at `self.pos`, returns false when the end of input is reached
+    /// instead of a line break
+    fn lex_line(&mut self) -> bool {
+        let line_start = self.pos;
+        let mut indent_count = 0usize;
+        for c in self.src[self.pos..].chars() {
+            match c {
+                ' ' => indent_count += 1,
+                '\t' => indent_count += self.config.tab_width as usize,
+                _ => break,
+            }
+            self.pos += 1;
+        }
+
+        let mut tokens = std::mem::take(&mut self.line_tokens);
+        tokens.clear();
+        let mut has_newline = false;
+        loop {
+            self.skip_trivia();
+            let rest = &self.src[self.pos..];
+            if rest.is_empty() {
+                break;
+            }
+            if starts_with_newline(rest) {
+                let len = if rest.starts_with('\n') { 1 } else { 2 };
+                tokens.push(Spanned::new(
+                    Self::span(self.pos, self.pos + len),
+                    Token::Newline,
+                ));
+                self.pos += len;
+                has_newline = true;
+                break;
+            }
+            self.lex_atom(&mut tokens);
+        }
+
+        self.layout_line(line_start, indent_count, &mut tokens);
+        self.line_tokens = tokens;
+        has_newline
+    }
+
+    /// closes the open indentation levels and emits EOF
+    fn finish(&mut self) {
+        let eof_span = Span::empty(self.src.len() as SpanIndex);
+        while self.indent_stack.len() > 1 {
+            self.indent_stack.pop();
+            self.output_buffer
+                .push_back(Spanned::new(eof_span, Token::Dedent));
+        }
+        self.output_buffer
+            .push_back(Spanned::new(eof_span, Token::EOF));
+        self.finished = true;
+    }
+
+    /// skips spaces, tabs and comments, but not doc comments
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = &self.src[self.pos..];
+            if rest.starts_with([' ', '\t']) {
+                self.pos += rest.find(|c| c != ' ' && c != '\t').unwrap_or(rest.len());
+            } else if rest.starts_with("--") && !rest.starts_with("--|") {
+                self.pos = self.line_end(self.pos);
+            } else if rest.starts_with("{-")
+                && let Some(len) = block_comment_len(rest)
+            {
+                self.pos += len;
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// the position of the line break at or after `from`, or the end of input
+    fn line_end(&self, from: usize) -> usize {
+        match self.src[from..].find('\n') {
+            Some(idx) if self.src[from..from + idx].ends_with('\r') => from + idx - 1,
+            Some(idx) => from + idx,
+            None => self.src.len(),
+        }
+    }
+
+    /// emits INDENT/DEDENT for the line and moves its tokens to the output buffer.
+    /// a line that starts inside brackets continues the previous one and has no layout
+    fn layout_line(
+        &mut self,
+        line_start: usize,
+        indent_count: usize,
+        tokens: &mut Vec<Spanned<Token>>,
+    ) {
+        let continued = self.open_brackets.contains(&true);
+        update_brackets(&mut self.open_brackets, tokens);
+        let joined = self.open_brackets.contains(&true);
+
+        // 空行（或只有注释的行）不参与缩进计算
+        if tokens.iter().all(|tok| tok.value == Token::Newline) {
+            if joined {
+                tokens.clear();
+            }
+            self.output_buffer.extend(tokens.drain(..));
+            return;
+        }
+
+        if joined {
+            // 括号还没有闭合，这一行的换行被忽略
+            if tokens.last().is_some_and(|tok| tok.value == Token::Newline) {
+                tokens.pop();
+            }
+        } else if tokens.last().is_none_or(|tok| tok.value != Token::Newline) {
+            // 文件最后一行可能没有换行符，补一个空的 NEWLINE
+            tokens.push(Spanned::new(
+                Span::empty(self.pos as SpanIndex),
+                Token::Newline,
+            ));
+        }
+        if continued {
+            self.output_buffer.extend(tokens.drain(..));
+            return;
         }
 
-        let file_pair = self.file_pairs.next()?;
-
-        match file_pair.as_rule() {
-            Rule::LINE => {
-                let line_pairs = file_pair.into_inner();
-                let line_tokenizer =
-                    LineTokenizer::new_line_pairs(line_pairs, self.interner.clone());
-                let (indent_count, tokens) = line_tokenizer.parse_line(self.tab_width);
-
-                // 这一行不是空行，需要计算indent
-                if tokens != vec![Token::Newline] {
-                    if indent_count > *self.indent_stack.last().unwrap_or(&0) {
-                        self.indent_stack.push(indent_count);
-                        self.output_buffer.push_back(Token::Indent);
-                    }
-                    // Check if we need to decrease indentation
-                    else if indent_count < *self.indent_stack.last().unwrap_or(&0) {
-                        while indent_count < *self.indent_stack.last().unwrap_or(&0) {
-                            self.indent_stack.pop();
-                            self.output_buffer.push_back(Token::Dedent);
-                        }
-                        // After dedenting, the indentation should match exactly
-                        if indent_count != *self.indent_stack.last().unwrap_or(&0) {
-                            panic!("Inconsistent indentation!");
-                        }
-                    }
-                    self.output_buffer.extend(tokens);
-                } else {
-                    self.output_buffer.extend(tokens);
+        // INDENT 覆盖行首的空白，DEDENT 是第一个 token 前的空 span
+        let first_start = tokens[0].context.start();
+        let indent_span = Span::new(line_start as SpanIndex, first_start)
+            .expect("tokens never start before their line");
+        self.check_tabs(line_start);
+
+        let outer = *self.indent_stack.last().unwrap_or(&0);
+        if indent_count > outer {
+            if let Some(width) = self.config.indent_width
+                && indent_count != outer + width
+            {
+                self.diagnostics.push(SimpleDiagnostic::new(
+                    self.config.severity,
+                    format!(
+                        "expected an indentation of {} columns, found {indent_count}",
+                        outer + width
+                    ),
+                    indent_span,
+                    None,
+                ));
+            }
+            self.indent_stack.push(indent_count);
+            self.output_buffer
+                .push_back(Spanned::new(indent_span, Token::Indent));
+        } else if indent_count < outer {
+            while indent_count < *self.indent_stack.last().unwrap_or(&0) {
+                self.indent_stack.pop();
+                self.output_buffer
+                    .push_back(Spanned::new(Span::empty(first_start), Token::Dedent));
+            }
+            // 缩进必须和外层的某一层完全一致，否则报错（layout 按块的列检查，不报错），
+            // 并把这一行当作新的缩进层级，保证 INDENT/DEDENT 成对出现
+            if indent_count != *self.indent_stack.last().unwrap_or(&0) {
+                if !self.config.layout {
+                    self.diagnostics.push(SimpleDiagnostic::new(
+                        Severity::Error,
+                        format!(
+                            "inconsistent indentation: width {indent_count} does not match any outer indentation level"
+                        ),
+                        indent_span,
+                        None,
+                    ));
+                    self.output_buffer
+                        .push_back(Spanned::new(indent_span, Token::Error));
                 }
+                self.indent_stack.push(indent_count);
+                self.output_buffer
+                    .push_back(Spanned::new(indent_span, Token::Indent));
+            }
+        }
+        self.output_buffer.extend(tokens.drain(..));
+    }
+
+    /// reports the indentation of the line at `line_start` if it breaks the [TabPolicy]
+    fn check_tabs(&mut self, line_start: usize) {
+        let rest = &self.src[line_start..];
+        let indent = &rest[..rest.len() - rest.trim_start_matches([' ', '\t']).len()];
+        if let Some(message) = self.config.check_tabs(indent) {
+            self.diagnostics.push(SimpleDiagnostic::new(
+                self.config.severity,
+                message.to_string(),
+                Self::span(line_start, line_start + indent.len()),
+                None,
+            ));
+        }
+    }
+
+    /// lexes the token at `self.pos`, which is not whitespace, a comment or a line break.
+    /// an interpolated string is lexed into several tokens
+    fn lex_atom(&mut self, tokens: &mut Vec<Spanned<Token>>) {
+        let start = self.pos;
+        let rest = &self.src[start..];
+        let c = rest
+            .chars()
+            .next()
+            .expect("lex_atom is never called at EOI");
+
+        let (end, token) = if rest.starts_with("--|") {
+            let end = self.line_end(start);
+            let text = self.src[start + "--|".len()..end].trim();
+            let sym = self.interner.borrow_mut().intern_or_get(text);
+            (end, Token::DocComment(sym))
+        } else if rest.starts_with("{-") {
+            // 能闭合的块注释已经被 skip_trivia 跳过了
+            let token = self.error(
+                Self::span(start, start + 2),
+                "unterminated block comment".to_string(),
+            );
+            (self.src.len(), token)
+        } else if let Some(open) = ["\"\"\"", "r\"\"\""]
+            .into_iter()
+            .find(|open| rest.starts_with(open))
+        {
+            match self.scan_multiline(start + open.len(), open.starts_with('r')) {
+                Some(end) => return self.string_literal(start, end, tokens),
+                None => {
+                    let token = self.error(
+                        Self::span(start, start + open.len()),
+                        "unterminated multi-line string literal".to_string(),
+                    );
+                    (self.src.len(), token)
+                }
+            }
+        } else if let Some(end) = self.scan_raw(start) {
+            return self.string_literal(start, end, tokens);
+        } else if c == '_' || is_xid_start(c) {
+            self.lex_word(start)
+        } else if is_symbol_char(c) {
+            let len = rest.find(|c| !is_symbol_char(c)).unwrap_or(rest.len());
+            let symbol = &rest[..len];
+            let token = if let Some(op) = Operator::from_symbol(symbol) {
+                Token::Operator(op)
+            } else if let Some(delimiter) = Delimiter::from_symbol(symbol) {
+                Token::Delimiter(delimiter)
+            } else {
+                Token::SymbolicIdentifier(
+                    self.interner.borrow_mut().intern_or_get(symbol),
+                )
+            };
+            (start + len, token)
+        } else if c.is_ascii_digit() {
+            self.lex_number(start)
+        } else if c == '\'' {
+            match self.scan_quoted(start, '\'') {
+                Some(end) if end > start + 2 => {
+                    let token = self.char_literal(start, end);
+                    (end, token)
+                }
+                _ => {
+                    let token = self.error(
+                        Self::span(start, start + 1),
+                        "unexpected character `'`".to_string(),
+                    );
+                    (start + 1, token)
+                }
+            }
+        } else if c == '"' {
+            match self.scan_quoted(start, '"') {
+                Some(end) => return self.string_literal(start, end, tokens),
+                None => {
+                    let end = self.unterminated_string_end(start);
+                    let token = self.error(
+                        Self::span(start, end),
+                        "unterminated string literal".to_string(),
+                    );
+                    (end, token)
+                }
+            }
+        } else if let Some(delimiter) = Delimiter::from_symbol(&rest[..c.len_utf8()]) {
+            (start + 1, Token::Delimiter(delimiter))
+        } else {
+            let end = start + c.len_utf8();
+            let token = self.error(
+                Self::span(start, end),
+                format!("unexpected character `{c}`"),
+            );
+            (end, token)
+        };
+
+        self.pos = end;
+        tokens.push(Spanned::new(Self::span(start, end), token));
+    }
+
+    /// keywords, `_` and identifiers
+    fn lex_word(&mut self, start: usize) -> (usize, Token) {
+        let rest = &self.src[start..];
+        let word_len = rest
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| !is_xid_continue(c))
+            .map_or(rest.len(), |(idx, _)| idx);
+        let word = &rest[..word_len];
+        let followed_by_prime = rest[word_len..].starts_with('\'');
+
+        if !followed_by_prime {
+            if let Some(keyword) = Keyword::from_word(word) {
+                return (start + word_len, Token::Keyword(keyword));
             }
-            Rule::EOI => {
-                while self.indent_stack.len() > 1 {
-                    self.indent_stack.pop();
-                    self.output_buffer.push_back(Token::Dedent);
+            if word == "_" {
+                return (start + 1, Token::Delimiter(Delimiter::UnderScore));
+            }
+        }
+
+        // 标识符末尾可以有若干个 `'` 和一个 `!`，`!` 后面不能紧跟符号字符
+        let mut len = word_len;
+        len += rest[len..].len() - rest[len..].trim_start_matches('\'').len();
+        if rest[len..].starts_with('!') && !rest[len + 1..].starts_with(is_symbol_char)
+        {
+            len += 1;
+        }
+
+        let ident = normalize_ident(&rest[..len]);
+        let sym = self.interner.borrow_mut().intern_or_get(&ident);
+        (start + len, Token::Identifier(sym))
+    }
+
+    /// `_DEC_DIGITS`: a digit followed by digits and `_`
+    fn dec_digits(&self, pos: usize) -> Option<usize> {
+        let rest = &self.src[pos..];
+        if !rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '_'))
+            .unwrap_or(rest.len());
+        Some(pos + len)
+    }
+
+    /// `e10`, `E+3`, `e-2`
+    fn exponent(&self, pos: usize) -> Option<usize> {
+        let rest = &self.src[pos..];
+        if !rest.starts_with(['e', 'E']) {
+            return None;
+        }
+        let sign = usize::from(rest[1..].starts_with(['+', '-']));
+        self.dec_digits(pos + 1 + sign)
+    }
+
+    fn lex_number(&mut self, start: usize) -> (usize, Token) {
+        let digits_end = self.dec_digits(start).expect("numbers start with a digit");
+        let float_end = if self.src[digits_end..].starts_with('.')
+            && let Some(fraction_end) = self.dec_digits(digits_end + 1)
+        {
+            Some(self.exponent(fraction_end).unwrap_or(fraction_end))
+        } else {
+            self.exponent(digits_end)
+        };
+
+        let body_end = match float_end {
+            Some(end) => end,
+            None if self.src[start..].starts_with('0')
+                && self.src[start + 1..]
+                    .starts_with(['x', 'X', 'o', 'O', 'b', 'B']) =>
+            {
+                start + 2
+            }
+            None => digits_end,
+        };
+        // 类型后缀交给 number 模块检查
+        let rest = &self.src[body_end..];
+        let end = body_end
+            + rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+
+        let text = &self.src[start..end];
+        let result = if float_end.is_some() {
+            parse_float(text)
+        } else {
+            parse_integer(text)
+        };
+        let token = match result {
+            Ok(literal) => Token::Literal(literal),
+            Err(err) => self.error(Self::span(start, end), err.to_string()),
+        };
+        (end, token)
+    }
+
+    /// the end of the `quote` delimited literal at `start`, `None` if it is not closed on this line.
+    /// a `\` escapes any character except a line break
+    fn scan_quoted(&self, start: usize, quote: char) -> Option<usize> {
+        let mut chars = self.src[start + 1..].char_indices();
+        while let Some((idx, c)) = chars.next() {
+            let pos = start + 1 + idx;
+            if c == quote {
+                return Some(pos + 1);
+            }
+            if starts_with_newline(&self.src[pos..]) {
+                return None;
+            }
+            if c == '\\' {
+                let escaped = &self.src[pos + 1..];
+                if escaped.is_empty() || starts_with_newline(escaped) {
+                    return None;
                 }
+                chars.next();
+            }
+        }
+        None
+    }
 
-                self.output_buffer.push_back(Token::EOF);
+    /// the end of the `r"..."` literal at `start`, `None` if there is none
+    fn scan_raw(&self, start: usize) -> Option<usize> {
+        let content = self.src[start..].strip_prefix("r\"")?;
+        let len = content.find(['"', '\n'])?;
+        content[len..]
+            .starts_with('"')
+            .then_some(start + "r\"".len() + len + 1)
+    }
+
+    /// the end of the multi-line string whose content starts at `from`,
+    /// `None` if it is never closed
+    fn scan_multiline(&self, from: usize, raw: bool) -> Option<usize> {
+        let mut chars = self.src[from..].char_indices();
+        while let Some((idx, c)) = chars.next() {
+            if self.src[from + idx..].starts_with("\"\"\"") {
+                return Some(from + idx + 3);
             }
-            x => {
-                unreachable!("Unreachable file rule: {:#?}", x)
+            if c == '\\' && !raw {
+                chars.next();
             }
         }
-        self.output_buffer.pop_front()
+        None
+    }
+
+    /// an unterminated string ends at the line break, a trailing `\` is part of it
+    fn unterminated_string_end(&self, start: usize) -> usize {
+        let mut chars = self.src[start + 1..].char_indices();
+        while let Some((idx, c)) = chars.next() {
+            let pos = start + 1 + idx;
+            if starts_with_newline(&self.src[pos..]) {
+                return pos;
+            }
+            if c == '\\' {
+                if starts_with_newline(&self.src[pos + 1..]) {
+                    return pos + 1;
+                }
+                chars.next();
+            }
+        }
+        self.src.len()
+    }
+
+    /// decodes the escapes between the quotes, reporting every malformed one
+    fn decode(&mut self, start: usize, end: usize) -> Option<Cow<'a, str>> {
+        let content = &self.src[start + 1..end - 1];
+        if !content.contains('\\') {
+            return Some(Cow::Borrowed(content));
+        }
+        match unescape(content, (start + 1) as SpanIndex) {
+            Ok(decoded) => Some(Cow::Owned(decoded)),
+            Err(errors) => {
+                for err in errors {
+                    self.error(err.span, err.kind.to_string());
+                }
+                None
+            }
+        }
+    }
+
+    fn char_literal(&mut self, start: usize, end: usize) -> Token {
+        let Some(decoded) = self.decode(start, end) else {
+            return Token::Error;
+        };
+        let mut chars = decoded.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Token::Literal(Literal::Char(c)),
+            _ => {
+                let raw_char = &self.src[start..end];
+                self.error(
+                    Self::span(start, end),
+                    format!("character literal `{raw_char}` must contain exactly one character"),
+                )
+            }
+        }
+    }
+
+    /// any kind of string literal, ending at `end`
+    fn string_literal(
+        &mut self,
+        start: usize,
+        end: usize,
+        tokens: &mut Vec<Spanned<Token>>,
+    ) {
+        self.pos = end;
+        match parse_string(&self.src[start..end], start) {
+            Ok(literal) => {
+                let interner = self.interner.clone();
+                tokens.extend(string_tokens(literal, &interner, |content| {
+                    self.lex_interpolation(content)
+                }));
+            }
+            Err(errors) => {
+                self.diagnostics.extend(errors);
+                tokens.push(Spanned::new(Self::span(start, end), Token::Error));
+            }
+        }
+    }
+
+    /// lexes the expression of an interpolation as a line of its own
+    fn lex_interpolation(&mut self, content: Span) -> Vec<Spanned<Token>> {
+        let start = content.start() as usize;
+        let src = &self.src[start..content.end() as usize];
+        let mut lexer = Lexer::with_config(src, self.config, self.interner.clone());
+        let mut tokens = Vec::new();
+        loop {
+            lexer.skip_trivia();
+            if lexer.pos == src.len() {
+                break;
+            }
+            lexer.lex_atom(&mut tokens);
+        }
+
+        self.diagnostics
+            .extend(lexer.take_diagnostics().into_iter().map(|mut diagnostic| {
+                diagnostic.primary_span = shift(diagnostic.primary_span, start);
+                diagnostic
+            }));
+        for token in &mut tokens {
+            token.context = shift(token.context, start);
+        }
+        tokens
     }
 }
 
-#[cfg(test)]
-mod test {
-    use pest::Parser;
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Spanned<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // 优先从缓存里读取
+            if let Some(buffered_token) = self.output_buffer.pop_front() {
+                return Some(buffered_token);
+            }
+            if self.finished {
+                return None;
+            }
+
+            if !self.lex_line() {
+                self.finish();
+            }
+        }
+    }
+}
 
-    use crate::{keyword::Keyword, line_tokenizer::PestParser};
+impl<'a> SpannedTokenStream<'a> for Lexer<'a> {}
 
+#[cfg(test)]
+mod test {
     use super::*;
+    use crate::pest_lexer::PestLexer;
 
-    #[test]
-    fn test_single_line() {
-        let test_str = "x = 1 \n";
-        let file = PestParser::parse(Rule::File, test_str)
-            .expect("unsuccessful parse")
-            .next()
-            .unwrap();
+    fn lex(src: &str) -> (Vec<Spanned<Token>>, Vec<SimpleDiagnostic>) {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut lexer = Lexer::new(src, 4, interner);
+        let tokens = lexer.by_ref().collect();
+        (tokens, lexer.take_diagnostics())
+    }
 
+    /// lexes `src` with both lexers, sharing the interner so that symbols are comparable
+    fn assert_same_as_pest(src: &str) {
         let interner = Rc::new(RefCell::new(Interner::new()));
-        let lexer = Lexer::new_from_pair(file, 4, interner.clone());
 
-        let tokens: Vec<_> = lexer.collect();
+        let mut lexer = Lexer::new(src, 4, interner.clone());
+        let tokens: Vec<_> = lexer.by_ref().collect();
+        let mut pest_lexer = PestLexer::new(src, 4, interner.clone());
+        let pest_tokens: Vec<_> = pest_lexer.by_ref().collect();
+        assert_eq!(pest_tokens, tokens, "tokens of {src:?}");
 
-        let expected = vec![
-            Token::Identifier(interner.borrow_mut().intern_or_get("x")),
-            Token::Operator(crate::operator::Operator::Eq),
-            Token::Literal(anon_ast::literal::Literal::Integer(1)),
-            Token::Newline,
-            Token::EOF,
-        ];
+        let diagnostics = |diagnostics: &[SimpleDiagnostic]| {
+            diagnostics
+                .iter()
+                .map(|d| (d.message.clone(), d.primary_span))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            diagnostics(pest_lexer.diagnostics()),
+            diagnostics(lexer.diagnostics()),
+            "diagnostics of {src:?}"
+        );
+    }
 
-        assert_eq!(expected, tokens);
+    #[test]
+    fn test_same_as_pest_on_samples() {
+        let samples_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../../samples");
+        for entry in std::fs::read_dir(samples_dir).expect("samples directory exists") {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "an") {
+                assert_same_as_pest(&std::fs::read_to_string(&path).unwrap());
+            }
+        }
     }
 
     #[test]
-    fn test_two_lines() {
-        let test_str = "x=1\ny=2\n";
-        let file = PestParser::parse(Rule::File, test_str)
-            .expect("unsuccessful parse")
-            .next()
-            .unwrap();
+    fn test_same_as_pest_on_edge_cases() {
+        for src in [
+            "",
+            "\n",
+            "a",
+            "a\n    b\n  c\nd\n",
+            "if a then\n    b\nelse\n    c\n",
+            "s = \"abc\nt\n",
+            "x = \"a\\\n",
+            "{- c -}   x\n  {- c -} y\n",
+            "a\n{- multi\nline -}   b\n",
+            "{-{-}\nx {- {- -}\n",
+            "--| doc\r\n-- c\r\nx\r\n",
+            "'' 'ab' '\\q' 'a\n",
+            "0x 0b102 1e 1.e5 1..5 0e5 1e+ 10f32 1.5.2\n",
+            "x!=y x!! let! let' _ _x _' __\n",
+            "\r x \rb\n",
+            "a\n\t  b\n    \tc\n",
+            "print \"hello {name}!\" \"{a}{ f x }\" \"\\{x\\}\"\n",
+            "s = \"{x -- c}\" \"a { b\" \"}\" \"{'\\q' ?}\"\n",
+            "r\"\\n{x}\" r\"a\nr \"\"\"\"\"\"\n",
+            "s =\n    \"\"\"\n    a {x}\n      b\n    \"\"\"\ny\n",
+            "r\"\"\"\r\n  raw\r\n\"\"\" \"\"\"\\\"\"\"\" \"\"\"never closed\n",
+            "f (a,\n        b)\n  [1,\n\n -- c\n 2]\ng\n",
+            "x = { a = 1\n  , b = 2 }\ncan {\n    f = (1,\n2)\n    g\n}\n",
+            "a ) ] }\n  b (\n",
+        ] {
+            assert_same_as_pest(src);
+        }
+    }
 
-        let interner = Rc::new(RefCell::new(Interner::new()));
-        let lexer = Lexer::new_from_pair(file, 4, interner.clone());
+    #[test]
+    fn test_same_as_pest_on_generated_input() {
+        const FRAGMENTS: &[&str] = &[
+            "a", "x'", "_", "_x", "let", "where", "can", "refine", "module", "infixl",
+            "panic!", "!=", "!", "<+>", "--", "--|", "-- c", "{-", "-}", "{- c -}",
+            " ", "  ", "\t", "\n", "\n", "\r\n", "\r", "0x1F", "1.5e3", "1_0u8",
+            "256u8", "1e", "..", ".", "'a'", "'", "''", "'\\n'", "\"s\"", "\"", "\\",
+            "\"a\\", "(", ")", "{", "}", ",", "::", "|", "@", "名", "é", "`", "=",
+            "->", "e", "9", "\"\"\"", "r\"", "r", "{x}", "\\{", "\\u{41}", "\"a{",
+        ];
+
+        // 固定种子的线性同余生成器，保证测试可以复现
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = |bound: usize| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 33) as usize % bound
+        };
 
-        let tokens: Vec<_> = lexer.collect();
+        for _ in 0..2000 {
+            let len = next(24);
+            let src: String =
+                (0..len).map(|_| FRAGMENTS[next(FRAGMENTS.len())]).collect();
+            assert_same_as_pest(&src);
+        }
+    }
 
-        let x_sym = interner.borrow_mut().intern_or_get("x");
-        let y_sym = interner.borrow_mut().intern_or_get("y");
+    #[test]
+    fn test_interpolated_string() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let src = "\"hi {name}!\"";
+        let tokens: Vec<_> = Lexer::new(src, 4, interner.clone()).collect();
+        let sym = |s: &str| interner.borrow_mut().intern_or_get(s);
 
         let expected = vec![
-            Token::Identifier(x_sym),
-            Token::Operator(crate::operator::Operator::Eq),
-            Token::Literal(anon_ast::literal::Literal::Integer(1)),
-            Token::Newline,
-            Token::Identifier(y_sym),
-            Token::Operator(crate::operator::Operator::Eq),
-            Token::Literal(anon_ast::literal::Literal::Integer(2)),
-            Token::Newline,
-            Token::EOF,
+            (0, 1, Token::StringStart),
+            (1, 4, Token::StringFragment(sym("hi "))),
+            (4, 5, Token::InterpolationStart),
+            (5, 9, Token::Identifier(sym("name"))),
+            (9, 10, Token::InterpolationEnd),
+            (10, 11, Token::StringFragment(sym("!"))),
+            (11, 12, Token::StringEnd),
+            (12, 12, Token::Newline),
+            (12, 12, Token::EOF),
         ];
-
-        assert_eq!(expected, tokens);
+        let found: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.context.start(), t.context.end(), t.value))
+            .collect();
+        assert_eq!(expected, found);
     }
 
     #[test]
-    fn test_two_line_indent() {
-        let test_str = "x=1\n    y=2\n";
-        let file = PestParser::parse(Rule::File, test_str)
-            .expect("unsuccessful parse")
-            .next()
-            .unwrap();
+    fn test_multiline_string_is_one_line() {
+        let (tokens, diagnostics) = lex("s =\n    \"\"\"\n  a\n    \"\"\" x\ny\n");
+        assert!(diagnostics.is_empty());
 
-        let interner = Rc::new(RefCell::new(Interner::new()));
-        let lexer = Lexer::new_from_pair(file, 4, interner.clone());
-
-        let tokens: Vec<_> = lexer.collect();
+        // 多行字符串里的换行不产生 NEWLINE，也不参与缩进计算
+        let values: Vec<_> = tokens.iter().map(|t| t.value).collect();
+        assert_eq!(
+            2,
+            values.iter().filter(|&&t| t == Token::Indent).count()
+                + values.iter().filter(|&&t| t == Token::Dedent).count()
+        );
+        assert!(matches!(
+            values[values.iter().position(|&t| t == Token::Indent).unwrap() + 1],
+            Token::Literal(Literal::String(_))
+        ));
+    }
 
-        let x_sym = interner.borrow_mut().intern_or_get("x");
-        let y_sym = interner.borrow_mut().intern_or_get("y");
+    #[test]
+    fn test_brackets_join_lines() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let src = "f (a,\n        b) [\n\n  ]\n  c";
+        let tokens: Vec<_> = Lexer::new(src, 4, interner.clone())
+            .map(|t| t.value)
+            .collect();
+        let sym = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
 
         let expected = vec![
-            Token::Identifier(x_sym),
-            Token::Operator(crate::operator::Operator::Eq),
-            Token::Literal(anon_ast::literal::Literal::Integer(1)),
+            sym("f"),
+            Token::Delimiter(Delimiter::LParen),
+            sym("a"),
+            Token::Delimiter(Delimiter::Comma),
+            sym("b"),
+            Token::Delimiter(Delimiter::RParen),
+            Token::Delimiter(Delimiter::LBracket),
+            Token::Delimiter(Delimiter::RBracket),
             Token::Newline,
             Token::Indent,
-            Token::Identifier(y_sym),
-            Token::Operator(crate::operator::Operator::Eq),
-            Token::Literal(anon_ast::literal::Literal::Integer(2)),
+            sym("c"),
             Token::Newline,
             Token::Dedent,
             Token::EOF,
         ];
-
         assert_eq!(expected, tokens);
     }
 
     #[test]
-    fn test_multiple_line_indent() {
-        let test_str = "x=1\n    y=2\n    z = 3 \nphi = 4 \n";
-        let file = PestParser::parse(Rule::File, test_str)
-            .expect("unsuccessful parse")
-            .next()
-            .unwrap();
+    fn test_brace_ending_a_line_keeps_layout() {
+        let (tokens, _) = lex("can {\n    f\n}\nx = { a\n  , b }\n");
+        let values: Vec<_> = tokens.iter().map(|t| t.value).collect();
 
-        let interner = Rc::new(RefCell::new(Interner::new()));
-        let lexer = Lexer::new_from_pair(file, 4, interner.clone());
+        assert_eq!(
+            1,
+            values.iter().filter(|&&t| t == Token::Indent).count(),
+            "only the block after `can {{` is indented: {values:?}"
+        );
+        assert_eq!(4, values.iter().filter(|&&t| t == Token::Newline).count());
+    }
 
-        let tokens: Vec<_> = lexer.collect();
+    fn lex_with(src: &str, config: LexerConfig) -> Vec<(String, Severity, Span)> {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut lexer = Lexer::with_config(src, config, interner);
+        lexer.by_ref().for_each(drop);
+        lexer
+            .take_diagnostics()
+            .into_iter()
+            .map(|d| (d.message, d.severity, d.primary_span))
+            .collect()
+    }
 
-        let x_sym = interner.borrow_mut().intern_or_get("x");
-        let y_sym = interner.borrow_mut().intern_or_get("y");
-        let z_sym = interner.borrow_mut().intern_or_get("z");
-        let phi_sym = interner.borrow_mut().intern_or_get("phi");
+    #[test]
+    fn test_tab_policy() {
+        let src = "a\n\tb\n\t c\n\t(d,\n\t e)\n";
+        assert!(lex_with(src, LexerConfig::default()).is_empty());
 
-        let expected = vec![
-            Token::Identifier(x_sym),
-            Token::Operator(crate::operator::Operator::Eq),
-            Token::Literal(anon_ast::literal::Literal::Integer(1)),
-            Token::Newline,
-            Token::Indent,
-            Token::Identifier(y_sym),
-            Token::Operator(crate::operator::Operator::Eq),
-            Token::Literal(anon_ast::literal::Literal::Integer(2)),
-            Token::Newline,
-            Token::Identifier(z_sym),
-            Token::Operator(crate::operator::Operator::Eq),
-            Token::Literal(anon_ast::literal::Literal::Integer(3)),
-            Token::Newline,
-            Token::Dedent,
-            Token::Identifier(phi_sym),
-            Token::Operator(crate::operator::Operator::Eq),
-            Token::Literal(anon_ast::literal::Literal::Integer(4)),
-            Token::Newline,
-            Token::EOF,
-        ];
+        let span = |start: u32, end: u32| Span::new(start, end).unwrap();
+        let forbid = LexerConfig::default().with_tabs(TabPolicy::Forbid);
+        assert_eq!(
+            vec![
+                (
+                    "tabs are not allowed in indentation".to_string(),
+                    Severity::Warning,
+                    span(2, 3)
+                ),
+                (
+                    "tabs are not allowed in indentation".to_string(),
+                    Severity::Warning,
+                    span(5, 7)
+                ),
+                (
+                    "tabs are not allowed in indentation".to_string(),
+                    Severity::Warning,
+                    span(9, 10)
+                ),
+            ],
+            lex_with(src, forbid)
+        );
 
-        assert_eq!(expected, tokens);
+        // 括号里的续行不检查
+        let no_mixing = LexerConfig::default()
+            .with_tabs(TabPolicy::NoMixing)
+            .with_severity(Severity::Error);
+        assert_eq!(
+            vec![(
+                "indentation mixes tabs and spaces".to_string(),
+                Severity::Error,
+                span(5, 7)
+            )],
+            lex_with(src, no_mixing)
+        );
     }
 
     #[test]
-    fn test_if() {
-        let test_str = "if x\n  then\n    y\n  else\n    z\n";
-        let file = PestParser::parse(Rule::File, test_str)
-            .expect("unsuccessful parse")
-            .next()
-            .unwrap();
+    fn test_indent_width() {
+        let config = LexerConfig::default().with_indent_width(4);
+        assert!(lex_with("a\n    b\n        c\n    d\ne\n", config).is_empty());
 
-        let interner = Rc::new(RefCell::new(Interner::new()));
-        let lexer = Lexer::new_from_pair(file, 4, interner.clone());
+        // 新的一层相对外层计算，`c` 比 `b` 深 4 列
+        let span = |start: u32, end: u32| Span::new(start, end).unwrap();
+        assert_eq!(
+            vec![(
+                "expected an indentation of 4 columns, found 2".to_string(),
+                Severity::Warning,
+                span(2, 4)
+            )],
+            lex_with("a\n  b\n      c\n", config)
+        );
+    }
 
-        let tokens: Vec<_> = lexer.collect();
+    #[test]
+    fn test_dedent_between_levels() {
+        let src = "f = let a = 1\n        b = 2\n    in a\n";
+        let error = "inconsistent indentation: width 4 does not match any outer indentation level";
+        let span = Span::new(28u32, 32u32).unwrap();
+        assert_eq!(
+            vec![(error.to_string(), Severity::Error, span)],
+            lex_with(src, LexerConfig::default())
+        );
+        assert!(lex_with(src, LexerConfig::default().with_layout()).is_empty());
+    }
 
-        let x_sym = interner.borrow_mut().intern_or_get("x");
-        let y_sym = interner.borrow_mut().intern_or_get("y");
-        let z_sym = interner.borrow_mut().intern_or_get("z");
+    #[test]
+    fn test_layout() {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut lexer =
+            Lexer::new("f =\n    x\n\n  -- c\n    y\ng", 4, interner.clone());
+        let tokens: Vec<_> = lexer.by_ref().map(|t| t.value).collect();
+        let sym = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
 
+        // 空行和只有注释的行不影响缩进，最后一行补一个 NEWLINE
         let expected = vec![
-            Token::Keyword(Keyword::If),
-            Token::Identifier(x_sym),
+            sym("f"),
+            Token::Operator(Operator::Eq),
             Token::Newline,
             Token::Indent,
-            Token::Keyword(Keyword::Then),
+            sym("x"),
             Token::Newline,
-            Token::Indent,
-            Token::Identifier(y_sym),
             Token::Newline,
-            Token::Dedent,
-            Token::Keyword(Keyword::Else),
             Token::Newline,
-            Token::Indent,
-            Token::Identifier(z_sym),
+            sym("y"),
             Token::Newline,
             Token::Dedent,
-            Token::Dedent,
+            sym("g"),
+            Token::Newline,
             Token::EOF,
         ];
-
         assert_eq!(expected, tokens);
+        assert!(lexer.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_leading_comment_does_not_indent() {
+        let (tokens, diagnostics) = lex("a
+{- c -}   b
+");
+
+        assert!(diagnostics.is_empty());
+        assert!(!tokens.iter().any(|t| t.value == Token::Indent));
     }
 }
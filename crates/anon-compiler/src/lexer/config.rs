@@ -0,0 +1,87 @@
+//! Indentation policies of the [super::Lexer].
+
+use anon_core::diagnostic::Severity;
+
+/// whether tabs may appear in the indentation of a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TabPolicy {
+    /// tabs count as `tab_width` columns
+    #[default]
+    Allow,
+    /// only spaces may indent a line
+    Forbid,
+    /// a line may be indented by tabs or by spaces, but not by both
+    NoMixing,
+}
+
+/// how the lexer measures and checks indentation
+///
+/// the checks only apply to lines that take part in layout,
+/// blank lines and lines inside brackets are never checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LexerConfig {
+    /// the number of columns a tab counts for
+    pub tab_width: u32,
+    pub tabs: TabPolicy,
+    /// every new indentation level must be exactly this many columns deeper
+    pub indent_width: Option<usize>,
+    /// the severity of the diagnostics for violated policies
+    pub severity: Severity,
+    /// the tokens go through the [crate::layout::Layout], which matches a dedent against
+    /// the columns of its blocks, so a line may dedent to a column between two indentation
+    /// levels, e.g. `in` left of the bindings of a `let` but right of the enclosing line
+    pub layout: bool,
+}
+
+impl LexerConfig {
+    pub fn new(tab_width: u32) -> Self {
+        Self {
+            tab_width,
+            tabs: TabPolicy::Allow,
+            indent_width: None,
+            severity: Severity::Warning,
+            layout: false,
+        }
+    }
+
+    pub fn with_tabs(self, tabs: TabPolicy) -> Self {
+        Self { tabs, ..self }
+    }
+
+    pub fn with_indent_width(self, indent_width: usize) -> Self {
+        Self {
+            indent_width: Some(indent_width),
+            ..self
+        }
+    }
+
+    pub fn with_severity(self, severity: Severity) -> Self {
+        Self { severity, ..self }
+    }
+
+    pub fn with_layout(self) -> Self {
+        Self {
+            layout: true,
+            ..self
+        }
+    }
+
+    /// the message of a violated [TabPolicy], `None` if `indent` is fine
+    pub(crate) fn check_tabs(&self, indent: &str) -> Option<&'static str> {
+        let has_tab = indent.contains('\t');
+        match self.tabs {
+            TabPolicy::Allow => None,
+            TabPolicy::Forbid if has_tab => Some("tabs are not allowed in indentation"),
+            TabPolicy::NoMixing if has_tab && indent.contains(' ') => {
+                Some("indentation mixes tabs and spaces")
+            }
+            TabPolicy::Forbid | TabPolicy::NoMixing => None,
+        }
+    }
+}
+
+impl Default for LexerConfig {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
@@ -0,0 +1,419 @@
+//! Relexing after an edit, for the language server.
+//!
+//! Lexing a line only depends on where it starts and on the indentation stack and the open
+//! brackets before it, so after an edit the lexer restarts at the line containing the edit
+//! and stops as soon as it reaches a line boundary, past the edit, with the same state as before.
+
+use std::{cell::RefCell, ops::Range, rc::Rc};
+
+use anon_core::{
+    diagnostic::SimpleDiagnostic,
+    interner::Interner,
+    span::{Span, SpanIndex, Spanned},
+};
+
+use super::{Lexer, LexerConfig};
+use crate::token::Token;
+
+/// replaces the text in `range` with `replacement`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Span,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Span, replacement: impl Into<String>) -> Self {
+        Self {
+            range,
+            replacement: replacement.into(),
+        }
+    }
+}
+
+/// the layout state of the lexer between two lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LayoutState {
+    indent_stack: Vec<usize>,
+    open_brackets: Vec<bool>,
+}
+
+/// the lexer state at the start of a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LineCheckpoint {
+    start: usize,
+    layout: LayoutState,
+    // 这一行的第一个 token 和第一个 diagnostic 的下标
+    first_token: usize,
+    first_diagnostic: usize,
+}
+
+/// the output of lexing from a checkpoint
+struct Relexed {
+    tokens: Vec<Spanned<Token>>,
+    lines: Vec<LineCheckpoint>,
+    diagnostics: Vec<SimpleDiagnostic>,
+    /// the old line the new lines joined up with, `None` if lexing reached the end of input
+    rejoined_at: Option<usize>,
+}
+
+/// a lexed file that can be edited and relexed incrementally
+#[derive(Debug, Clone)]
+pub struct IncrementalLexer {
+    src: String,
+    config: LexerConfig,
+    interner: Rc<RefCell<Interner>>,
+    tokens: Vec<Spanned<Token>>,
+    lines: Vec<LineCheckpoint>,
+    diagnostics: Vec<SimpleDiagnostic>,
+}
+
+impl IncrementalLexer {
+    pub fn new(
+        src: impl Into<String>,
+        tab_width: u32,
+        interner: Rc<RefCell<Interner>>,
+    ) -> Self {
+        Self::with_config(src, LexerConfig::new(tab_width), interner)
+    }
+
+    pub fn with_config(
+        src: impl Into<String>,
+        config: LexerConfig,
+        interner: Rc<RefCell<Interner>>,
+    ) -> Self {
+        let src = src.into();
+        let initial = LayoutState {
+            indent_stack: vec![0],
+            open_brackets: Vec::new(),
+        };
+        let relexed = relex(&src, config, &interner, 0, initial, |_, _| None);
+        Self {
+            src,
+            config,
+            interner,
+            tokens: relexed.tokens,
+            lines: relexed.lines,
+            diagnostics: relexed.diagnostics,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.src
+    }
+
+    /// the same tokens [Lexer] produces for the current source
+    pub fn tokens(&self) -> &[Spanned<Token>] {
+        &self.tokens
+    }
+
+    pub fn diagnostics(&self) -> &[SimpleDiagnostic] {
+        &self.diagnostics
+    }
+
+    /// applies `edit` and relexes the lines it affects,
+    /// returns the range of tokens in [Self::tokens] that were lexed again
+    ///
+    /// panics if the range of the edit is out of bounds or not on a char boundary
+    pub fn apply_edit(&mut self, edit: &TextEdit) -> Range<usize> {
+        let edit_start = edit.range.start() as usize;
+        let edit_end = edit.range.end() as usize;
+        assert!(
+            self.src.is_char_boundary(edit_start)
+                && self.src.is_char_boundary(edit_end),
+            "edit range {edit_start}..{edit_end} is not on char boundaries of the source"
+        );
+        self.src
+            .replace_range(edit_start..edit_end, &edit.replacement);
+        let new_edit_end = edit_start + edit.replacement.len();
+        let delta = new_edit_end as isize - edit_end as isize;
+
+        // 从包含编辑位置的那一行开始重新 lex，它之前的行不受影响
+        let restart = self
+            .lines
+            .partition_point(|line| line.start <= edit_start)
+            .saturating_sub(1);
+        let checkpoint = self.lines[restart].clone();
+
+        let old_lines = &self.lines;
+        let relexed = relex(
+            &self.src,
+            self.config,
+            &self.interner,
+            checkpoint.start,
+            checkpoint.layout.clone(),
+            |start, layout| {
+                if start < new_edit_end {
+                    return None;
+                }
+                let old_start = start.checked_add_signed(-delta)?;
+                let idx = old_lines
+                    .binary_search_by_key(&old_start, |line| line.start)
+                    .ok()?;
+                (old_lines[idx].layout == *layout).then_some(idx)
+            },
+        );
+
+        let first_token = checkpoint.first_token;
+        let first_diagnostic = checkpoint.first_diagnostic;
+        let relexed_tokens = first_token..first_token + relexed.tokens.len();
+
+        // 新 lex 出来的行里的下标是相对的，需要加上前面保留部分的长度
+        let mut lines: Vec<_> = self.lines.drain(..restart).collect();
+        lines.extend(relexed.lines.into_iter().map(|mut line| {
+            line.first_token += first_token;
+            line.first_diagnostic += first_diagnostic;
+            line
+        }));
+        let mut tokens: Vec<_> = self.tokens.drain(..first_token).collect();
+        tokens.extend(relexed.tokens);
+        let mut diagnostics: Vec<_> =
+            self.diagnostics.drain(..first_diagnostic).collect();
+        diagnostics.extend(relexed.diagnostics);
+
+        // 重新对齐之后，剩下的 token 只需要平移
+        if let Some(old_line) = relexed.rejoined_at {
+            // self 里前面的部分已经被 drain 掉了，旧的下标要减去被拿走的长度
+            let old = &self.lines[old_line - restart];
+            let old_first_token = old.first_token - first_token;
+            let old_first_diagnostic = old.first_diagnostic - first_diagnostic;
+            let token_offset = tokens.len() as isize - old.first_token as isize;
+            let diagnostic_offset =
+                diagnostics.len() as isize - old.first_diagnostic as isize;
+
+            lines.extend(self.lines.drain(old_line - restart..).map(|mut line| {
+                line.start = line.start.checked_add_signed(delta).unwrap();
+                line.first_token =
+                    line.first_token.checked_add_signed(token_offset).unwrap();
+                line.first_diagnostic = line
+                    .first_diagnostic
+                    .checked_add_signed(diagnostic_offset)
+                    .unwrap();
+                line
+            }));
+            tokens.extend(self.tokens.drain(old_first_token..).map(|mut token| {
+                token.context = shift(token.context, delta);
+                token
+            }));
+            diagnostics.extend(self.diagnostics.drain(old_first_diagnostic..).map(
+                |mut diagnostic| {
+                    shift_diagnostic(&mut diagnostic, delta);
+                    diagnostic
+                },
+            ));
+        }
+
+        self.lines = lines;
+        self.tokens = tokens;
+        self.diagnostics = diagnostics;
+        relexed_tokens
+    }
+}
+
+/// lexes `src` line by line from `start` until the end of input, or until `rejoin` finds an old
+/// line with the same start and layout state as the next line
+fn relex(
+    src: &str,
+    config: LexerConfig,
+    interner: &Rc<RefCell<Interner>>,
+    start: usize,
+    layout: LayoutState,
+    rejoin: impl Fn(usize, &LayoutState) -> Option<usize>,
+) -> Relexed {
+    let mut lexer = Lexer::with_config(src, config, interner.clone());
+    lexer.pos = start;
+    lexer.indent_stack = layout.indent_stack;
+    lexer.open_brackets = layout.open_brackets;
+
+    let mut tokens = Vec::new();
+    let mut lines = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    loop {
+        // 编辑所在的那一行总是要重新 lex 的
+        let layout = LayoutState {
+            indent_stack: lexer.indent_stack.clone(),
+            open_brackets: lexer.open_brackets.clone(),
+        };
+        if !lines.is_empty()
+            && let Some(old_line) = rejoin(lexer.pos, &layout)
+        {
+            return Relexed {
+                tokens,
+                lines,
+                diagnostics,
+                rejoined_at: Some(old_line),
+            };
+        }
+
+        lines.push(LineCheckpoint {
+            start: lexer.pos,
+            layout,
+            first_token: tokens.len(),
+            first_diagnostic: diagnostics.len(),
+        });
+        let has_newline = lexer.lex_line();
+        if !has_newline {
+            lexer.finish();
+        }
+        tokens.extend(lexer.output_buffer.drain(..));
+        diagnostics.append(&mut lexer.diagnostics);
+
+        if !has_newline {
+            return Relexed {
+                tokens,
+                lines,
+                diagnostics,
+                rejoined_at: None,
+            };
+        }
+    }
+}
+
+fn shift(span: Span, delta: isize) -> Span {
+    let move_by = |idx: SpanIndex| {
+        (idx as usize)
+            .checked_add_signed(delta)
+            .expect("spans after an edit stay in bounds") as SpanIndex
+    };
+    Span::new(move_by(span.start()), move_by(span.end()))
+        .expect("shifting keeps spans ordered")
+}
+
+fn shift_diagnostic(diagnostic: &mut SimpleDiagnostic, delta: isize) {
+    diagnostic.primary_span = shift(diagnostic.primary_span, delta);
+    if let Some(child) = diagnostic.children.as_deref_mut() {
+        shift_diagnostic(child, delta);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// the incremental result must be exactly what lexing the new source from scratch gives
+    fn assert_same_as_full_lex(lexer: &IncrementalLexer) {
+        let full = IncrementalLexer::new(lexer.source(), 4, lexer.interner.clone());
+        assert_eq!(full.tokens, lexer.tokens, "tokens of {:?}", lexer.source());
+        assert_eq!(full.lines, lexer.lines, "lines of {:?}", lexer.source());
+
+        let diagnostics = |lexer: &IncrementalLexer| {
+            lexer
+                .diagnostics
+                .iter()
+                .map(|d| (d.message.clone(), d.primary_span))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(diagnostics(&full), diagnostics(lexer));
+
+        let tokens: Vec<_> =
+            Lexer::new(lexer.source(), 4, lexer.interner.clone()).collect();
+        assert_eq!(tokens, lexer.tokens);
+    }
+
+    fn edit(
+        lexer: &mut IncrementalLexer,
+        start: u32,
+        end: u32,
+        text: &str,
+    ) -> Range<usize> {
+        let relexed =
+            lexer.apply_edit(&TextEdit::new(Span::new(start, end).unwrap(), text));
+        assert_same_as_full_lex(lexer);
+        relexed
+    }
+
+    fn new_lexer(src: &str) -> IncrementalLexer {
+        IncrementalLexer::new(src, 4, Rc::new(RefCell::new(Interner::new())))
+    }
+
+    #[test]
+    fn test_edit_within_a_line() {
+        let mut lexer = new_lexer("f x =\n    x + 1\ng = \"s\"\nh = 3\n");
+
+        // `1` -> `10`
+        let relexed = edit(&mut lexer, 14, 15, "10");
+        // 只有 `    x + 10` 这一行被重新 lex
+        assert_eq!(4..9, relexed);
+
+        edit(&mut lexer, 21, 21, "`q");
+        assert_eq!(1, lexer.diagnostics().len());
+        edit(&mut lexer, 0, 1, "ff");
+    }
+
+    #[test]
+    fn test_edit_changes_indentation() {
+        let mut lexer = new_lexer("f =\n    a\n    b\n    c\ng\n");
+
+        edit(&mut lexer, 10, 10, "  ");
+        edit(&mut lexer, 4, 8, "");
+        edit(&mut lexer, 0, 0, "  ");
+        edit(&mut lexer, 6, 6, "x\n\n");
+    }
+
+    #[test]
+    fn test_edit_block_comment() {
+        let mut lexer = new_lexer("a\nb\nc -}\nd\n");
+
+        // 打开一个块注释会一直影响到它闭合的地方
+        edit(&mut lexer, 2, 2, "{- ");
+        // a NEWLINE NEWLINE d NEWLINE EOF
+        assert_eq!(6, lexer.tokens().len());
+        edit(&mut lexer, 2, 5, "");
+        // 没有闭合的块注释吃掉文件剩下的部分
+        edit(&mut lexer, 0, 0, "{- {- ");
+        edit(&mut lexer, 0, 6, "");
+    }
+
+    #[test]
+    fn test_edit_at_end_of_file() {
+        let mut lexer = new_lexer("f =\n    a");
+
+        edit(&mut lexer, 9, 9, "\n");
+        edit(&mut lexer, 10, 10, "g");
+        edit(&mut lexer, 0, 11, "");
+        edit(&mut lexer, 0, 0, "x\r");
+        edit(&mut lexer, 2, 2, "\n");
+    }
+
+    #[test]
+    fn test_relex_stops_early() {
+        let src = "f x =\n    x + 1\n".repeat(1000);
+        let mut lexer = new_lexer(&src);
+        let len = lexer.tokens().len();
+
+        let middle = (src.len() / 2) as u32;
+        let relexed = edit(&mut lexer, middle, middle, "y ");
+        assert!(relexed.len() < 10, "relexed {relexed:?} of {len} tokens");
+    }
+
+    #[test]
+    fn test_random_edits() {
+        const FRAGMENTS: &[&str] = &[
+            "", "a", "let", "  ", "\t", "\n", "\n    ", "\r\n", "{-", "-}", "--",
+            "--|", "\"", "\"s\"", "'a'", "\\", "1.5", "256u8", "<+>", "(", "é",
+        ];
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = |bound: usize| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 33) as usize % bound
+        };
+
+        let src = include_str!("../../../../samples/data.an");
+        let mut lexer = new_lexer(src);
+        for _ in 0..300 {
+            let len = lexer.source().len();
+            let boundary = |idx: usize| {
+                (idx..=len)
+                    .find(|&i| lexer.source().is_char_boundary(i))
+                    .unwrap()
+            };
+            let start = boundary(next(len + 1));
+            let end = boundary(start + next(8).min(len - start));
+            let text = FRAGMENTS[next(FRAGMENTS.len())];
+            edit(&mut lexer, start as u32, end as u32, text);
+        }
+    }
+}
@@ -1,12 +1,24 @@
 pub mod ast_builder;
 pub mod delimiter;
+pub mod escape;
+pub mod fixity;
 pub mod keyword;
+pub mod layout;
 pub mod lexer;
 pub mod line_tokenizer;
+pub mod lossless;
+pub mod method;
+pub mod module;
+pub mod number;
 pub mod operator;
+pub mod pest_lexer;
+pub mod string_literal;
 pub mod token;
 pub mod token_stream;
 pub mod untyped_ast;
 
+pub use layout::Layout;
 pub use lexer::Lexer;
-pub use token_stream::TokenStream;
+pub use lossless::LosslessLexer;
+pub use pest_lexer::PestLexer;
+pub use token_stream::{SpannedTokenStream, TokenStream};
@@ -1,10 +1,25 @@
 use pest_derive::Parser;
-use std::{cell::RefCell, rc::Rc};
-
-use crate::{keyword::Keyword::*, operator::Operator};
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use crate::{
+    delimiter::Delimiter,
+    escape::unescape,
+    keyword::Keyword::*,
+    lexer::normalize_ident,
+    number::{parse_float, parse_integer},
+    operator::Operator,
+    string_literal::{parse_string, shift, string_tokens},
+};
 use anon_ast::literal::Literal;
-use anon_core::interner::Interner;
-use pest::iterators::{Pair, Pairs};
+use anon_core::{
+    diagnostic::{Severity, SimpleDiagnostic},
+    interner::Interner,
+    span::{Span, SpanIndex, Spanned},
+};
+use pest::{
+    Parser,
+    iterators::{Pair, Pairs},
+};
 
 use crate::token::Token;
 
@@ -12,6 +27,13 @@ use crate::token::Token;
 #[grammar = "anon.pest"]
 pub struct PestParser;
 
+/// converts the byte range of a pest pair into a [Span]
+pub fn span_of(pair: &Pair<'_, Rule>) -> Span {
+    let span = pair.as_span();
+    Span::new(span.start() as u32, span.end() as u32)
+        .expect("pest spans are always ordered")
+}
+
 /// 单行lexer
 #[derive(Debug, Clone)]
 pub struct LineTokenizer<'a> {
@@ -20,14 +42,18 @@ pub struct LineTokenizer<'a> {
     // String Interner
     interner: Rc<RefCell<Interner>>,
     buffer: Option<Pair<'a, Rule>>,
+    // 插值字符串产生的多个 token 中还没有输出的部分
+    pending: VecDeque<Spanned<Token>>,
+    // 本行产生的词法错误
+    diagnostics: Vec<SimpleDiagnostic>,
 }
 
 impl<'a> LineTokenizer<'a> {
     /// pair仅接受line层级rule LINE = { (SPACE | TAB)* ~ ATOM* ~ _LINE_COMMENT? ~ NEWLINE }
+    /// 或者没有换行符的 LAST_LINE
     pub fn new(pair: Pair<'a, Rule>, interner: Rc<RefCell<Interner>>) -> Self {
-        assert_eq!(
-            pair.as_rule(),
-            Rule::LINE,
+        assert!(
+            matches!(pair.as_rule(), Rule::LINE | Rule::LAST_LINE),
             "tokenizer should only receive line rule!"
         );
         // Inner pairs, including Space, TAB, ATOM, line comment and newline
@@ -37,6 +63,8 @@ impl<'a> LineTokenizer<'a> {
             pairs: inner_pairs,
             interner,
             buffer: None,
+            pending: VecDeque::new(),
+            diagnostics: Vec::new(),
         }
     }
     ///  你需要自己确保Pairs都是来自line
@@ -48,50 +76,142 @@ impl<'a> LineTokenizer<'a> {
             pairs,
             interner,
             buffer: None,
+            pending: VecDeque::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// the lexical errors found so far
+    pub fn diagnostics(&self) -> &[SimpleDiagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<SimpleDiagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// records a lexical error and returns the [Token::Error] that replaces the input
+    fn error(&mut self, span: Span, message: String) -> Token {
+        self.diagnostics.push(SimpleDiagnostic::new(
+            Severity::Error,
+            message,
+            span,
+            None,
+        ));
+        Token::Error
+    }
+
+    /// decodes the escapes of a literal, reporting every malformed one
+    fn unescape(&mut self, content: &str, offset: SpanIndex) -> Option<String> {
+        match unescape(content, offset) {
+            Ok(decoded) => Some(decoded),
+            Err(errors) => {
+                for err in errors {
+                    self.error(err.span, err.kind.to_string());
+                }
+                None
+            }
+        }
+    }
+
+    /// any kind of string literal, an interpolated one becomes several tokens
+    fn string_literal(&mut self, pair: &Pair<'a, Rule>) -> Vec<Spanned<Token>> {
+        let text = pair.as_str();
+        let offset = pair.as_span().start();
+        match parse_string(text, offset) {
+            Ok(literal) => {
+                let interner = self.interner.clone();
+                string_tokens(literal, &interner, |content| {
+                    let start = content.start() as usize;
+                    let end = content.end() as usize;
+                    self.lex_interpolation(&text[start - offset..end - offset], start)
+                })
+            }
+            Err(errors) => {
+                self.diagnostics.extend(errors);
+                vec![Spanned::new(span_of(pair), Token::Error)]
+            }
+        }
+    }
+
+    /// lexes the expression of an interpolation, starting at `offset`, as a line of its own
+    fn lex_interpolation(&mut self, src: &str, offset: usize) -> Vec<Spanned<Token>> {
+        let line = PestParser::parse(Rule::LAST_LINE, src)
+            .expect("a line without line breaks always parses")
+            .next()
+            .expect("LAST_LINE produces one pair");
+        let mut tokenizer = LineTokenizer::new(line, self.interner.clone());
+        let (_, mut tokens) = tokenizer.parse_line(0);
+
+        self.diagnostics
+            .extend(
+                tokenizer
+                    .take_diagnostics()
+                    .into_iter()
+                    .map(|mut diagnostic| {
+                        diagnostic.primary_span =
+                            shift(diagnostic.primary_span, offset);
+                        diagnostic
+                    }),
+            );
+        for token in &mut tokens {
+            token.context = shift(token.context, offset);
         }
+        tokens
     }
 
     /// parse the whole line, returns the indent count and the rest tokens,
-    pub fn parse_line(mut self, tab_width: u32) -> (usize, Vec<Token>) {
+    pub fn parse_line(&mut self, tab_width: u32) -> (usize, Vec<Spanned<Token>>) {
         let mut indent_count = 0usize;
 
-        loop {
-            match self.pairs.next() {
-                Some(pair) => match pair.as_rule() {
-                    Rule::INDENT => {
-                        let indent_text = pair.as_str();
-                        indent_count += indent_text.chars().fold(0usize, |acc, c| {
-                            if c == '\t' {
-                                acc + tab_width as usize
-                            } else {
-                                acc + 1
-                            }
-                        });
-                    }
-                    _ => {
-                        self.buffer = Some(pair);
-                        break;
-                    }
-                },
-                None => break,
+        for pair in self.pairs.by_ref() {
+            match pair.as_rule() {
+                Rule::INDENT => {
+                    let indent_text = pair.as_str();
+                    indent_count += indent_text.chars().fold(0usize, |acc, c| {
+                        if c == '\t' {
+                            acc + tab_width as usize
+                        } else {
+                            acc + 1
+                        }
+                    });
+                }
+                _ => {
+                    self.buffer = Some(pair);
+                    break;
+                }
             }
         }
 
         // collect remaining tokens from the same iterator we used above
-        (indent_count, self.collect())
+        (indent_count, self.by_ref().collect())
     }
 }
 
 impl<'a> Iterator for LineTokenizer<'a> {
-    type Item = Token;
+    type Item = Spanned<Token>;
 
     fn next(&mut self) -> Option<Self::Item> {
         // 优先从缓存里读取，然后从迭代器self.pairs里读取，最后再返回None
+        if let Some(token) = self.pending.pop_front() {
+            return Some(token);
+        }
         let line_pair = self.buffer.take().or_else(|| self.pairs.next())?;
+        let span = span_of(&line_pair);
         match line_pair.as_rule() {
-            Rule::NEWLINE => Some(Token::Newline),
+            Rule::NEWLINE => Some(Spanned::new(span, Token::Newline)),
             Rule::ATOM => {
                 let atom_pair = line_pair.into_inner().next().unwrap();
+                if matches!(
+                    atom_pair.as_rule(),
+                    Rule::STRING
+                        | Rule::MULTILINE_STRING
+                        | Rule::RAW_STRING
+                        | Rule::RAW_MULTILINE_STRING
+                ) {
+                    self.pending = self.string_literal(&atom_pair).into();
+                    return self.pending.pop_front();
+                }
                 let token = match atom_pair.as_rule() {
                     Rule::KW_CASE => Token::Keyword(Case),
                     Rule::KW_CLASS => Token::Keyword(Class),
@@ -106,29 +226,50 @@ impl<'a> Iterator for LineTokenizer<'a> {
                     Rule::KW_MATCH => Token::Keyword(Match),
                     Rule::KW_THEN => Token::Keyword(Then),
                     Rule::KW_TYPE => Token::Keyword(Type),
-                    Rule::FLOAT => Token::Literal(Literal::Float(
-                        atom_pair.as_str().parse().unwrap(),
-                    )),
-                    Rule::INTEGER => Token::Literal(Literal::Integer(
-                        atom_pair.as_str().parse().unwrap(),
-                    )),
+                    Rule::KW_WHERE => Token::Keyword(Where),
+                    Rule::KW_CAN => Token::Keyword(Can),
+                    Rule::KW_REFINE => Token::Keyword(Refine),
+                    Rule::KW_MODULE => Token::Keyword(Module),
+                    Rule::KW_INFIX => Token::Keyword(Infix),
+                    Rule::KW_INFIXL => Token::Keyword(InfixL),
+                    Rule::KW_INFIXR => Token::Keyword(InfixR),
+                    Rule::FLOAT => match parse_float(atom_pair.as_str()) {
+                        Ok(literal) => Token::Literal(literal),
+                        Err(err) => self.error(span, err.to_string()),
+                    },
+                    Rule::INTEGER => match parse_integer(atom_pair.as_str()) {
+                        Ok(literal) => Token::Literal(literal),
+                        Err(err) => self.error(span, err.to_string()),
+                    },
                     Rule::CHARACTER => {
                         let raw_char = atom_pair.as_str();
                         let content = &raw_char[1..raw_char.len() - 1];
-                        Token::Literal(Literal::Char(content.parse().unwrap()))
-                    }
-                    Rule::STRING => {
-                        // Remove surrounding quotes and intern the string value
-                        let raw_str = atom_pair.as_str();
-                        let content = raw_str[1..raw_str.len() - 1].to_string(); // naive unquote
-                        Token::Literal(Literal::String(
-                            self.interner.borrow_mut().intern_or_get(&content),
-                        ))
+                        match self.unescape(content, span.start() + 1) {
+                            Some(decoded) => {
+                                let mut chars = decoded.chars();
+                                match (chars.next(), chars.next()) {
+                                    (Some(c), None) => Token::Literal(Literal::Char(c)),
+                                    _ => self.error(
+                                        span,
+                                        format!(
+                                            "character literal `{raw_char}` must contain exactly one character"
+                                        ),
+                                    ),
+                                }
+                            }
+                            None => Token::Error,
+                        }
                     }
                     Rule::IDENT => {
-                        let ident = atom_pair.as_str();
+                        let ident = normalize_ident(atom_pair.as_str());
                         Token::Identifier(
-                            self.interner.borrow_mut().intern_or_get(ident),
+                            self.interner.borrow_mut().intern_or_get(&ident),
+                        )
+                    }
+                    Rule::SYMBOLIC_IDENT => {
+                        let op = atom_pair.as_str();
+                        Token::SymbolicIdentifier(
+                            self.interner.borrow_mut().intern_or_get(op),
                         )
                     }
                     Rule::NEWLINE => {
@@ -136,31 +277,78 @@ impl<'a> Iterator for LineTokenizer<'a> {
                     }
                     Rule::OP_ADD => Token::Operator(Operator::Add),
                     Rule::OP_NEG => Token::Operator(Operator::Negate),
-                    Rule::OP_EQ => Token::Operator(Operator::Eq),
                     Rule::OP_MUL => Token::Operator(Operator::Mul),
-                    Rule::DELIMITER_ANNOTATE => {
-                        Token::Delimiter(crate::delimiter::Delimiter::Annotate)
-                    }
-                    Rule::DELIMITER_COMMA => {
-                        Token::Delimiter(crate::delimiter::Delimiter::Comma)
+                    Rule::OP_DIV => Token::Operator(Operator::Div),
+                    Rule::OP_MOD => Token::Operator(Operator::Mod),
+                    Rule::OP_EQ => Token::Operator(Operator::Eq),
+                    Rule::OP_EQ_EQ => Token::Operator(Operator::EqEq),
+                    Rule::OP_NOT_EQ => Token::Operator(Operator::NotEq),
+                    Rule::OP_LT => Token::Operator(Operator::Lt),
+                    Rule::OP_LE => Token::Operator(Operator::Le),
+                    Rule::OP_GT => Token::Operator(Operator::Gt),
+                    Rule::OP_GE => Token::Operator(Operator::Ge),
+                    Rule::OP_AND => Token::Operator(Operator::And),
+                    Rule::OP_OR => Token::Operator(Operator::Or),
+                    Rule::OP_NOT => Token::Operator(Operator::Not),
+                    Rule::OP_ARROW => Token::Operator(Operator::Arrow),
+                    Rule::OP_FAT_ARROW => Token::Operator(Operator::FatArrow),
+                    Rule::OP_COMPOSE_RIGHT => Token::Operator(Operator::ComposeRight),
+                    Rule::OP_COMPOSE_LEFT => Token::Operator(Operator::ComposeLeft),
+                    Rule::OP_APPLY => Token::Operator(Operator::Apply),
+                    Rule::OP_RANGE => Token::Operator(Operator::Range),
+                    Rule::DELIMITER_ANNOTATE => Token::Delimiter(Delimiter::Annotate),
+                    Rule::DELIMITER_COMMA => Token::Delimiter(Delimiter::Comma),
+                    Rule::DELIMITER_LPAREN => Token::Delimiter(Delimiter::LParen),
+                    Rule::DELIMITER_RPAREN => Token::Delimiter(Delimiter::RParen),
+                    Rule::DELIMITER_LBRACKET => Token::Delimiter(Delimiter::LBracket),
+                    Rule::DELIMITER_RBRACKET => Token::Delimiter(Delimiter::RBracket),
+                    Rule::DELIMITER_LBRACE => Token::Delimiter(Delimiter::LBrace),
+                    Rule::DELIMITER_RBRACE => Token::Delimiter(Delimiter::RBrace),
+                    Rule::DELIMITER_UNDERSCORE => {
+                        Token::Delimiter(Delimiter::UnderScore)
                     }
-                    Rule::DELIMITER_LPAREN => {
-                        Token::Delimiter(crate::delimiter::Delimiter::LParen)
+                    Rule::DELIMITER_BAR => Token::Delimiter(Delimiter::Bar),
+                    Rule::DELIMITER_AT => Token::Delimiter(Delimiter::At),
+                    Rule::DELIMITER_DOT => Token::Delimiter(Delimiter::Dot),
+                    Rule::DELIMITER_BACKSLASH => Token::Delimiter(Delimiter::Backslash),
+                    Rule::DOC_COMMENT => {
+                        let text = atom_pair.as_str()["--|".len()..].trim();
+                        Token::DocComment(
+                            self.interner.borrow_mut().intern_or_get(text),
+                        )
                     }
-                    Rule::DELIMITER_RPAREN => {
-                        Token::Delimiter(crate::delimiter::Delimiter::RParen)
+                    Rule::UNTERMINATED_BLOCK_COMMENT => self.error(
+                        Span::new(span.start(), span.start() + 2)
+                            .expect("`{-` is two bytes long"),
+                        "unterminated block comment".to_string(),
+                    ),
+                    Rule::UNTERMINATED_MULTILINE_STRING => {
+                        let open = if atom_pair.as_str().starts_with('r') {
+                            4
+                        } else {
+                            3
+                        };
+                        self.error(
+                            Span::new(span.start(), span.start() + open)
+                                .expect("the opening quotes are in the literal"),
+                            "unterminated multi-line string literal".to_string(),
+                        )
                     }
-                    Rule::DELIMITER_UNDERSCORE => {
-                        Token::Delimiter(crate::delimiter::Delimiter::UnderScore)
+                    Rule::UNTERMINATED_STRING => {
+                        self.error(span, "unterminated string literal".to_string())
                     }
+                    Rule::UNKNOWN_CHAR => self.error(
+                        span,
+                        format!("unexpected character `{}`", atom_pair.as_str()),
+                    ),
 
                     y => {
                         unreachable!("Unreachable Atom rule: {:#?}", y)
                     }
                 };
-                Some(token)
+                Some(Spanned::new(span, token))
             }
-            Rule::SPACE => self.next(),
+            Rule::SPACE | Rule::TAB => self.next(),
 
             x => {
                 unreachable!("Unreachable Line rule: {:#?}", x)
@@ -178,6 +366,7 @@ mod test {
 
     use Literal::*;
     use Operator::*;
+    use anon_ast::literal::{FloatSuffix, IntSuffix};
 
     #[test]
     fn test_empty() {
@@ -190,7 +379,7 @@ mod test {
         let interner = Rc::new(RefCell::new(Interner::new()));
         let line_tokenizer = LineTokenizer::new(line, interner.clone());
 
-        let tokens: Vec<Token> = line_tokenizer.collect();
+        let tokens: Vec<Token> = line_tokenizer.map(|t| t.value).collect();
         let expected: Vec<Token> = vec![Token::Newline];
 
         assert_eq!(expected, tokens);
@@ -207,7 +396,7 @@ mod test {
         let interner = Rc::new(RefCell::new(Interner::new()));
         let line_tokenizer = LineTokenizer::new(line, interner.clone());
 
-        let tokens: Vec<_> = line_tokenizer.collect();
+        let tokens: Vec<_> = line_tokenizer.map(|t| t.value).collect();
         let expected: Vec<_> = vec![
             Token::Identifier(interner.borrow_mut().intern_or_get("foo")),
             Token::Operator(Operator::Eq),
@@ -229,7 +418,7 @@ mod test {
         let interner = Rc::new(RefCell::new(Interner::new()));
         let line_tokenizer = LineTokenizer::new(line, interner.clone());
 
-        let tokens: Vec<_> = line_tokenizer.collect();
+        let tokens: Vec<_> = line_tokenizer.map(|t| t.value).collect();
         let expected: Vec<_> = vec![
             Token::Identifier(interner.borrow_mut().intern_or_get("bar")),
             Token::Operator(Eq),
@@ -252,7 +441,7 @@ mod test {
         let interner = Rc::new(RefCell::new(Interner::new()));
         let line_tokenizer = LineTokenizer::new(line, interner.clone());
 
-        let tokens: Vec<_> = line_tokenizer.collect();
+        let tokens: Vec<_> = line_tokenizer.map(|t| t.value).collect();
         let expected: Vec<_> = vec![Token::Newline];
 
         assert_eq!(expected, tokens);
@@ -269,7 +458,7 @@ mod test {
         let interner = Rc::new(RefCell::new(Interner::new()));
         let line_tokenizer = LineTokenizer::new(line, interner.clone());
 
-        let tokens: Vec<_> = line_tokenizer.collect();
+        let tokens: Vec<_> = line_tokenizer.map(|t| t.value).collect();
         let expected: Vec<_> = vec![
             Token::Identifier(interner.borrow_mut().intern_or_get("c")),
             Token::Operator(Eq),
@@ -291,7 +480,7 @@ mod test {
         let interner = Rc::new(RefCell::new(Interner::new()));
         let line_tokenizer = LineTokenizer::new(line, interner.clone());
 
-        let tokens: Vec<Token> = line_tokenizer.collect();
+        let tokens: Vec<Token> = line_tokenizer.map(|t| t.value).collect();
         let s_sym = { interner.borrow_mut().intern_or_get("s") };
         let lit_sym = {
             interner
@@ -318,9 +507,10 @@ mod test {
             .unwrap();
 
         let interner = Rc::new(RefCell::new(Interner::new()));
-        let line_tokenizer = LineTokenizer::new(line, interner.clone());
+        let mut line_tokenizer = LineTokenizer::new(line, interner.clone());
 
         let (indent_count, tokens) = line_tokenizer.parse_line(4);
+        let tokens: Vec<_> = tokens.into_iter().map(|t| t.value).collect();
 
         assert_eq!(4, indent_count);
 
@@ -345,9 +535,10 @@ mod test {
             .unwrap();
 
         let interner = Rc::new(RefCell::new(Interner::new()));
-        let line_tokenizer = LineTokenizer::new(line, interner.clone());
+        let mut line_tokenizer = LineTokenizer::new(line, interner.clone());
 
         let (indent_count, tokens) = line_tokenizer.parse_line(4);
+        let tokens: Vec<_> = tokens.into_iter().map(|t| t.value).collect();
 
         assert_eq!(4, indent_count);
 
@@ -377,9 +568,10 @@ mod test {
             .unwrap();
 
         let interner = Rc::new(RefCell::new(Interner::new()));
-        let line_tokenizer = LineTokenizer::new(line, interner.clone());
+        let mut line_tokenizer = LineTokenizer::new(line, interner.clone());
 
         let (indent_count, tokens) = line_tokenizer.parse_line(4);
+        let tokens: Vec<_> = tokens.into_iter().map(|t| t.value).collect();
 
         assert_eq!(12, indent_count);
 
@@ -399,4 +591,358 @@ mod test {
 
         assert_eq!(expected, tokens);
     }
+
+    #[test]
+    fn test_token_spans() {
+        let test_str = "  foo = 'c' -- comment\n";
+        let line = PestParser::parse(super::Rule::LINE, test_str)
+            .expect("unsuccessful parse")
+            .next()
+            .unwrap();
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut line_tokenizer = LineTokenizer::new(line, interner.clone());
+
+        let (_, tokens) = line_tokenizer.parse_line(4);
+        let spans: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.context.start(), t.context.end()))
+            .collect();
+
+        assert_eq!(vec![(2, 5), (6, 7), (8, 11), (22, 23)], spans);
+    }
+
+    #[test]
+    fn test_escaped_literals() {
+        let test_str = "s = \"a\\nb\\u{e9}\" '\\n' '\\''\n";
+        let line = PestParser::parse(super::Rule::LINE, test_str)
+            .expect("unsuccessful parse")
+            .next()
+            .unwrap();
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let line_tokenizer = LineTokenizer::new(line, interner.clone());
+
+        let tokens: Vec<_> = line_tokenizer.map(|t| t.value).collect();
+        let s_sym = interner.borrow_mut().intern_or_get("s");
+        let lit_sym = interner.borrow_mut().intern_or_get("a\nbé");
+
+        let expected = vec![
+            Token::Identifier(s_sym),
+            Token::Operator(Eq),
+            Token::Literal(Literal::String(lit_sym)),
+            Token::Literal(Char('\n')),
+            Token::Literal(Char('\'')),
+            Token::Newline,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        let test_str = "\"\\q\" 'ab' '\\u{110000}'\n";
+        let line = PestParser::parse(super::Rule::LINE, test_str)
+            .expect("unsuccessful parse")
+            .next()
+            .unwrap();
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut line_tokenizer = LineTokenizer::new(line, interner.clone());
+
+        let (_, tokens) = line_tokenizer.parse_line(4);
+        let tokens: Vec<_> = tokens.into_iter().map(|t| t.value).collect();
+
+        assert_eq!(
+            vec![Token::Error, Token::Error, Token::Error, Token::Newline],
+            tokens
+        );
+
+        let spans: Vec<_> = line_tokenizer
+            .diagnostics()
+            .iter()
+            .map(|d| (d.primary_span.start(), d.primary_span.end()))
+            .collect();
+        assert_eq!(vec![(1, 3), (5, 9), (11, 21)], spans);
+    }
+
+    /// tokenizes a single line, dropping the spans
+    fn tokenize(test_str: &str) -> (Vec<Token>, Rc<RefCell<Interner>>) {
+        let line = PestParser::parse(super::Rule::LINE, test_str)
+            .expect("unsuccessful parse")
+            .next()
+            .unwrap();
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let line_tokenizer = LineTokenizer::new(line, interner.clone());
+
+        (line_tokenizer.map(|t| t.value).collect(), interner)
+    }
+
+    #[test]
+    fn test_operators() {
+        let (tokens, _) =
+            tokenize("+ - * / % = == != < <= > >= && || ! -> => >> << $ ..\n");
+
+        let expected: Vec<_> = [
+            Add,
+            Negate,
+            Mul,
+            Div,
+            Mod,
+            Eq,
+            EqEq,
+            NotEq,
+            Lt,
+            Le,
+            Gt,
+            Ge,
+            And,
+            Or,
+            Not,
+            Arrow,
+            FatArrow,
+            ComposeRight,
+            ComposeLeft,
+            Apply,
+            Range,
+        ]
+        .into_iter()
+        .map(Token::Operator)
+        .chain([Token::Newline])
+        .collect();
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_delimiters() {
+        use Delimiter::*;
+
+        let (tokens, _) = tokenize("( ) [ ] { } , :: _ | @ .\n");
+
+        let expected: Vec<_> = [
+            LParen, RParen, LBracket, RBracket, LBrace, RBrace, Comma, Annotate,
+            UnderScore, Bar, At, Dot,
+        ]
+        .into_iter()
+        .map(Token::Delimiter)
+        .chain([Token::Newline])
+        .collect();
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_operators_without_spaces() {
+        let (tokens, interner) = tokenize("f<<g>>h $x[1..]->y!=z\n");
+        let sym = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
+
+        let expected = vec![
+            sym("f"),
+            Token::Operator(ComposeLeft),
+            sym("g"),
+            Token::Operator(ComposeRight),
+            sym("h"),
+            Token::Operator(Apply),
+            sym("x"),
+            Token::Delimiter(Delimiter::LBracket),
+            Token::Literal(Integer(1)),
+            Token::Operator(Range),
+            Token::Delimiter(Delimiter::RBracket),
+            Token::Operator(Arrow),
+            sym("y"),
+            Token::Operator(NotEq),
+            sym("z"),
+            Token::Newline,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_readme_surface_syntax() {
+        let (tokens, interner) = tokenize("Just a | None -> x.unwrap (p, q)@r\n");
+        let sym = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
+
+        let expected = vec![
+            sym("Just"),
+            sym("a"),
+            Token::Delimiter(Delimiter::Bar),
+            sym("None"),
+            Token::Operator(Arrow),
+            sym("x"),
+            Token::Delimiter(Delimiter::Dot),
+            sym("unwrap"),
+            Token::Delimiter(Delimiter::LParen),
+            sym("p"),
+            Token::Delimiter(Delimiter::Comma),
+            sym("q"),
+            Token::Delimiter(Delimiter::RParen),
+            Token::Delimiter(Delimiter::At),
+            sym("r"),
+            Token::Newline,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_comment_marker_inside_literals() {
+        let (tokens, interner) = tokenize("\"a -- b\" '-' -- real comment\n");
+        let lit_sym = interner.borrow_mut().intern_or_get("a -- b");
+
+        let expected = vec![
+            Token::Literal(Literal::String(lit_sym)),
+            Token::Literal(Char('-')),
+            Token::Newline,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_symbolic_identifiers() {
+        let (tokens, interner) = tokenize("a <+> b >>= c >> d <=> e - f\n");
+        let sym = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
+        let op =
+            |s: &str| Token::SymbolicIdentifier(interner.borrow_mut().intern_or_get(s));
+
+        let expected = vec![
+            sym("a"),
+            op("<+>"),
+            sym("b"),
+            op(">>="),
+            sym("c"),
+            Token::Operator(ComposeRight),
+            sym("d"),
+            op("<=>"),
+            sym("e"),
+            Token::Operator(Negate),
+            sym("f"),
+            Token::Newline,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_keywords_need_word_boundary() {
+        let (tokens, interner) = tokenize("infixl infix input iffy in\n");
+        let sym = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
+
+        let expected = vec![
+            Token::Keyword(InfixL),
+            Token::Keyword(Infix),
+            sym("input"),
+            sym("iffy"),
+            Token::Keyword(In),
+            Token::Newline,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_number_literals() {
+        let (tokens, _) = tokenize("0xFFu8 1_000 2.5e3f32 0b101 1e-2 x[1..]\n");
+
+        assert_eq!(
+            &[
+                Token::Literal(SuffixedInteger(255, IntSuffix::U8)),
+                Token::Literal(Integer(1000)),
+                Token::Literal(SuffixedFloat(2500.0, FloatSuffix::F32)),
+                Token::Literal(Integer(5)),
+                Token::Literal(Float(0.01)),
+            ],
+            &tokens[..5]
+        );
+        assert_eq!(Token::Operator(Range), tokens[8]);
+    }
+
+    #[test]
+    fn test_number_literal_errors() {
+        let test_str = "300u8 99999999999999999999 1.5abc\n";
+        let line = PestParser::parse(super::Rule::LINE, test_str)
+            .expect("unsuccessful parse")
+            .next()
+            .unwrap();
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut line_tokenizer = LineTokenizer::new(line, interner.clone());
+
+        let (_, tokens) = line_tokenizer.parse_line(4);
+        let tokens: Vec<_> = tokens.into_iter().map(|t| t.value).collect();
+        assert_eq!(
+            vec![Token::Error, Token::Error, Token::Error, Token::Newline],
+            tokens
+        );
+
+        let messages: Vec<_> = line_tokenizer
+            .diagnostics()
+            .iter()
+            .map(|d| {
+                (
+                    d.message.as_str(),
+                    d.primary_span.start(),
+                    d.primary_span.end(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("literal out of range for `u8`", 0, 5),
+                ("literal out of range for `Int`", 6, 26),
+                ("invalid suffix `abc` for number literal", 27, 33),
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let (tokens, interner) = tokenize("名前 = x' + f'' _tmp _ größe\n");
+        let ident = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
+
+        assert_eq!(
+            vec![
+                ident("名前"),
+                Token::Operator(Eq),
+                ident("x'"),
+                Token::Operator(Add),
+                ident("f''"),
+                ident("_tmp"),
+                Token::Delimiter(Delimiter::UnderScore),
+                ident("größe"),
+                Token::Newline,
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_bang_identifiers() {
+        let (tokens, interner) = tokenize("panic! x!=y Panic!\n");
+        let ident = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
+
+        assert_eq!(
+            vec![
+                ident("panic!"),
+                ident("x"),
+                Token::Operator(NotEq),
+                ident("y"),
+                ident("Panic!"),
+                Token::Newline,
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn test_identifiers_are_nfc_normalised() {
+        // `é` precomposed and as `e` followed by a combining acute accent
+        let (tokens, _) = tokenize("caf\u{e9} cafe\u{301}\n");
+        assert_eq!(tokens[0], tokens[1]);
+        assert!(matches!(tokens[0], Token::Identifier(_)));
+    }
 }
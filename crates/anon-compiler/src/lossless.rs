@@ -0,0 +1,303 @@
+//! A lexer mode that keeps whitespace, comments and blank lines as trivia,
+//! for the formatter and IDE features.
+//!
+//! Concatenating the text of every token and its trivia reproduces the input byte for byte.
+
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use anon_core::{
+    diagnostic::SimpleDiagnostic,
+    interner::Interner,
+    span::{Span, SpanIndex, Spanned},
+};
+
+use crate::{
+    lexer::{Lexer, block_comment_len},
+    token::Token,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// spaces and tabs
+    Whitespace,
+    /// a line break that is not a [Token::Newline], e.g. the end of a blank line
+    Newline,
+    /// `-- ...`, without the line break
+    LineComment,
+    /// `{- ... -}`, possibly nested and spanning several lines
+    BlockComment,
+    /// input the lexer produced no token for, only seen when the file could not be lexed at all
+    Skipped,
+}
+
+pub type Trivia = Spanned<TriviaKind>;
+
+/// a token together with the trivia around it
+///
+/// trivia on the same line after a token is its trailing trivia,
+/// everything else before a token is the leading trivia of that token
+#[derive(Debug, Clone, PartialEq)]
+pub struct LosslessToken {
+    pub leading: Vec<Trivia>,
+    pub token: Spanned<Token>,
+    pub trailing: Vec<Trivia>,
+}
+
+impl LosslessToken {
+    fn new(token: Spanned<Token>) -> Self {
+        Self {
+            leading: Vec::new(),
+            token,
+            trailing: Vec::new(),
+        }
+    }
+
+    /// `INDENT`, `DEDENT` and `EOF` have no text of their own,
+    /// the indentation is kept as the leading trivia of the first token of the line
+    pub fn is_virtual(&self) -> bool {
+        matches!(self.token.value, Token::Indent | Token::Dedent | Token::EOF)
+    }
+
+    /// the text of the token, without trivia
+    pub fn text<'s>(&self, src: &'s str) -> &'s str {
+        if self.is_virtual() {
+            ""
+        } else {
+            slice(src, self.token.context)
+        }
+    }
+
+    /// appends the leading trivia, the token and the trailing trivia to `out`
+    pub fn write_full_text(&self, src: &str, out: &mut String) {
+        for trivia in &self.leading {
+            out.push_str(slice(src, trivia.context));
+        }
+        out.push_str(self.text(src));
+        for trivia in &self.trailing {
+            out.push_str(slice(src, trivia.context));
+        }
+    }
+}
+
+fn slice(src: &str, span: Span) -> &str {
+    &src[span.start() as usize..span.end() as usize]
+}
+
+/// lexes a whole file into [LosslessToken]s, the significant tokens are the same as [Lexer]'s,
+/// except that the `NEWLINE`s of blank lines become trivia
+#[derive(Debug, Clone)]
+pub struct LosslessLexer {
+    tokens: VecDeque<LosslessToken>,
+    diagnostics: Vec<SimpleDiagnostic>,
+}
+
+impl LosslessLexer {
+    pub fn new(src: &str, tab_width: u32, interner: Rc<RefCell<Interner>>) -> Self {
+        let mut lexer = Lexer::new(src, tab_width, interner);
+        let mut tokens: Vec<LosslessToken> = Vec::new();
+        // 上一个有文本的 token，以及它结束的位置
+        let mut last_text_token: Option<usize> = None;
+        let mut cursor: SpanIndex = 0;
+
+        for token in lexer.by_ref() {
+            if matches!(token.value, Token::Indent | Token::Dedent) {
+                tokens.push(LosslessToken::new(token));
+                continue;
+            }
+
+            let after_newline = last_text_token
+                .is_none_or(|idx| tokens[idx].token.value == Token::Newline);
+            // 空行的 NEWLINE 只是 trivia
+            if token.value == Token::Newline && after_newline {
+                continue;
+            }
+
+            debug_assert!(token.context.start() >= cursor, "tokens are ordered");
+            // 同一行里第一个换行之前的 trivia 属于上一个 token
+            let mut trailing = split_trivia(src, cursor, token.context.start());
+            let split_at = if after_newline {
+                0
+            } else {
+                trailing
+                    .iter()
+                    .position(|trivia| trivia.value == TriviaKind::Newline)
+                    .unwrap_or(trailing.len())
+            };
+            let leading = trailing.split_off(split_at);
+            if let Some(prev) = last_text_token {
+                tokens[prev].trailing.extend(trailing);
+            }
+
+            let mut lossless = LosslessToken::new(token);
+            lossless.leading = leading;
+            cursor = if lossless.is_virtual() {
+                token.context.start()
+            } else {
+                token.context.end()
+            };
+            last_text_token = Some(tokens.len());
+            tokens.push(lossless);
+        }
+
+        Self {
+            tokens: tokens.into(),
+            diagnostics: lexer.take_diagnostics(),
+        }
+    }
+
+    /// the lexical errors, the same as the ones reported by [Lexer]
+    pub fn diagnostics(&self) -> &[SimpleDiagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<SimpleDiagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+}
+
+impl Iterator for LosslessLexer {
+    type Item = LosslessToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tokens.pop_front()
+    }
+}
+
+/// splits `src[start..end]`, which contains no significant token, into trivia
+fn split_trivia(src: &str, start: SpanIndex, end: SpanIndex) -> Vec<Trivia> {
+    let text = &src[start as usize..end as usize];
+    let mut trivia = Vec::new();
+    let mut pos = 0;
+
+    while pos < text.len() {
+        let rest = &text[pos..];
+        let (kind, len) = if rest.starts_with([' ', '\t']) {
+            let len = rest.find(|c| c != ' ' && c != '\t').unwrap_or(rest.len());
+            (TriviaKind::Whitespace, len)
+        } else if rest.starts_with("\r\n") {
+            (TriviaKind::Newline, 2)
+        } else if rest.starts_with('\n') {
+            (TriviaKind::Newline, 1)
+        } else if rest.starts_with("--") {
+            let len = rest.find(['\r', '\n']).unwrap_or(rest.len());
+            (TriviaKind::LineComment, len)
+        } else if rest.starts_with("{-") {
+            let len = block_comment_len(rest).unwrap_or(rest.len());
+            (TriviaKind::BlockComment, len)
+        } else {
+            let len = rest.chars().next().map_or(1, char::len_utf8);
+            (TriviaKind::Skipped, len)
+        };
+
+        let span =
+            Span::new(start + pos as SpanIndex, start + (pos + len) as SpanIndex)
+                .expect("trivia never ends before it starts");
+        trivia.push(Spanned::new(span, kind));
+        pos += len;
+    }
+
+    trivia
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lex(src: &str) -> (Vec<LosslessToken>, Rc<RefCell<Interner>>) {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let tokens = LosslessLexer::new(src, 4, interner.clone()).collect();
+        (tokens, interner)
+    }
+
+    fn round_trip(src: &str) -> String {
+        let mut out = String::new();
+        for token in lex(src).0 {
+            token.write_full_text(src, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_round_trip_samples() {
+        let samples_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../../samples");
+        let mut count = 0;
+        for entry in std::fs::read_dir(samples_dir).expect("samples directory exists") {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "an") {
+                let src = std::fs::read_to_string(&path).unwrap();
+                assert_eq!(src, round_trip(&src), "round trip of {}", path.display());
+                count += 1;
+            }
+        }
+        assert!(count > 0, "no sample programs found");
+    }
+
+    #[test]
+    fn test_round_trip_edge_cases() {
+        for src in [
+            "",
+            "\n\n",
+            "   \n",
+            "a\r\nb\r\n\r\n",
+            "x = \"unterminated\n  {- never closed",
+            "a\n    b\n  c\n",
+            "f `x`\n",
+            "s = \"a {x -- c} b\"\n",
+            "s =\n    \"\"\"\n    {x}\n    \"\"\"\n",
+        ] {
+            assert_eq!(src, round_trip(src));
+        }
+    }
+
+    #[test]
+    fn test_trivia_attachment() {
+        let src = "a -- c\n\n  {- b -}\nb  \n";
+        let (tokens, interner) = lex(src);
+        let sym = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
+
+        let found: Vec<_> = tokens
+            .iter()
+            .map(|t| {
+                let kinds = |trivia: &[Trivia]| {
+                    trivia.iter().map(|t| t.value).collect::<Vec<_>>()
+                };
+                (kinds(&t.leading), t.token.value, kinds(&t.trailing))
+            })
+            .collect();
+
+        use TriviaKind::*;
+        let expected = vec![
+            (vec![], sym("a"), vec![Whitespace, LineComment]),
+            (vec![], Token::Newline, vec![]),
+            // 空行和只有注释的行都是下一个 token 的 leading trivia
+            (
+                vec![Newline, Whitespace, BlockComment, Newline],
+                sym("b"),
+                vec![Whitespace],
+            ),
+            (vec![], Token::Newline, vec![]),
+            (vec![], Token::EOF, vec![]),
+        ];
+        assert_eq!(expected, found);
+    }
+
+    #[test]
+    fn test_indentation_is_trivia() {
+        let src = "f =\n    x\n";
+        let (tokens, _) = lex(src);
+
+        let indent = tokens
+            .iter()
+            .position(|t| t.token.value == Token::Indent)
+            .unwrap();
+        assert_eq!("", tokens[indent].text(src));
+        assert!(tokens[indent].leading.is_empty());
+        assert_eq!(
+            vec![Spanned::new(
+                Span::new(4u32, 8u32).unwrap(),
+                TriviaKind::Whitespace
+            )],
+            tokens[indent + 1].leading
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Resolving the method calls in a `can` block, `x.unwrap` calls the method `unwrap` of
+//! the type of `x`.
+//!
+//! Without types, only the receivers known to be `Self` are resolved: the parameters of a
+//! method at the `Self` positions of its signature, e.g. `x` in `unwrap :: Self -> a` and
+//! `unwrap x = ...`, or annotated with `(x :: Self)`. A name bound again by a pattern, a
+//! lambda, `let` or `where` hides them. The other calls are left to the type checker.
+
+use std::collections::HashSet;
+
+use anon_ast::{
+    expr::{Expr, ExprKind},
+    func_decl::FuncDecl,
+    pattern::{Pattern, PatternKind},
+    sig::Type,
+    type_decl::{SelfCall, TypeDecl},
+};
+use anon_core::{
+    diagnostic::{Severity, SimpleDiagnostic},
+    interner::{Interner, Symbol},
+    span::Span,
+};
+
+/// resolves the calls on `Self` in the methods of `decl` into [TypeDecl::self_calls],
+/// a call of a method the `can` block does not define is an error
+pub fn resolve_self_calls(
+    decl: &mut TypeDecl<Span>,
+    interner: &Interner,
+) -> Vec<SimpleDiagnostic> {
+    // 没有出现过 `Self` 的文件里没有 `Self` 类型的参数
+    let Some(self_type) = interner.get("Self") else {
+        return Vec::new();
+    };
+    let mut resolver = Resolver {
+        decl,
+        self_type,
+        interner,
+        calls: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    for method in &decl.methods {
+        resolver.function(method, &HashSet::new());
+    }
+    let Resolver {
+        calls, diagnostics, ..
+    } = resolver;
+    decl.self_calls = calls;
+    diagnostics
+}
+
+struct Resolver<'a> {
+    decl: &'a TypeDecl<Span>,
+    self_type: Symbol,
+    interner: &'a Interner,
+    calls: Vec<SelfCall>,
+    diagnostics: Vec<SimpleDiagnostic>,
+}
+
+impl Resolver<'_> {
+    /// `selves` are the names of `Self` values visible in the function
+    fn function(&mut self, func: &FuncDecl<Span>, selves: &HashSet<Symbol>) {
+        let mut param_types = Vec::new();
+        if let Some(sig) = &func.func_sig {
+            let mut ty = &sig.ty;
+            while let Type::Arrow { param, result } = ty {
+                param_types.push(&**param);
+                ty = result;
+            }
+        }
+        for clause in &func.clauses {
+            let mut scope = selves.clone();
+            for (i, param) in clause.params.iter().enumerate() {
+                for name in bound_names(param) {
+                    scope.remove(&name);
+                }
+                let is_self = param_types.get(i) == Some(&&Type::Con(self.self_type));
+                self.self_params(param, is_self, &mut scope);
+            }
+            // `where` 里的定义在函数体里可见
+            for binding in &clause.where_bindings {
+                scope.remove(&binding.func_name);
+            }
+            self.expr(&clause.body, &scope);
+            for binding in &clause.where_bindings {
+                self.function(binding, &scope);
+            }
+        }
+    }
+
+    /// adds the names that `pattern` binds to a `Self` value
+    fn self_params(
+        &self,
+        pattern: &Pattern,
+        is_self: bool,
+        selves: &mut HashSet<Symbol>,
+    ) {
+        match &pattern.kind {
+            PatternKind::Variable(name) if is_self => {
+                selves.insert(*name);
+            }
+            PatternKind::Alias { name, pattern } => {
+                if is_self {
+                    selves.insert(*name);
+                }
+                self.self_params(pattern, is_self, selves);
+            }
+            PatternKind::Annotated { pattern, ty } => {
+                let is_self = is_self || *ty == Type::Con(self.self_type);
+                self.self_params(pattern, is_self, selves);
+            }
+            _ => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr<Span>, selves: &HashSet<Symbol>) {
+        match &expr.kind {
+            ExprKind::MethodCall { receiver, method } => {
+                if let ExprKind::Object(name) = receiver.kind
+                    && selves.contains(&name)
+                {
+                    self.call(*method, expr.meta);
+                }
+                self.expr(receiver, selves);
+            }
+            ExprKind::Application { func, args } => {
+                self.expr(func, selves);
+                for arg in args {
+                    self.expr(arg, selves);
+                }
+            }
+            ExprKind::Binary { lhs, rhs, .. } => {
+                self.expr(lhs, selves);
+                self.expr(rhs, selves);
+            }
+            ExprKind::Negate(operand) => self.expr(operand, selves),
+            ExprKind::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expr(condition, selves);
+                self.expr(consequence, selves);
+                self.expr(alternative, selves);
+            }
+            ExprKind::Match { value, arms } => {
+                self.expr(value, selves);
+                for arm in arms {
+                    let scope = shadowed(selves, bound_names(&arm.pattern));
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard, &scope);
+                    }
+                    self.expr(&arm.body, &scope);
+                }
+            }
+            ExprKind::Let { bindings, body } => {
+                let scope = shadowed(selves, bindings.iter().map(|b| b.func_name));
+                for binding in bindings {
+                    self.function(binding, &scope);
+                }
+                self.expr(body, &scope);
+            }
+            ExprKind::Lambda { params, body } => {
+                let scope = shadowed(selves, params.iter().flat_map(bound_names));
+                self.expr(body, &scope);
+            }
+            ExprKind::LeftSection { lhs: operand, .. }
+            | ExprKind::RightSection { rhs: operand, .. } => self.expr(operand, selves),
+            ExprKind::List(elements) => {
+                for element in elements {
+                    self.expr(element, selves);
+                }
+            }
+            ExprKind::Index { value, index } => {
+                self.expr(value, selves);
+                self.expr(index, selves);
+            }
+            ExprKind::Slice { value, start, end } => {
+                self.expr(value, selves);
+                for bound in [start, end].into_iter().flatten() {
+                    self.expr(bound, selves);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// `x.method` at `span` with `x` of type `Self`
+    fn call(&mut self, method: Symbol, span: Span) {
+        if self.decl.method(method).is_some() {
+            self.calls.push(SelfCall { method, span });
+            return;
+        }
+        let resolve = |sym| self.interner.resolve(sym).unwrap_or_default();
+        self.diagnostics.push(SimpleDiagnostic::new(
+            Severity::Error,
+            format!(
+                "no method `{}` in the `can` block of `{}`",
+                resolve(method),
+                resolve(self.decl.name)
+            ),
+            span,
+            None,
+        ));
+    }
+}
+
+/// the names a pattern binds
+fn bound_names(pattern: &Pattern) -> Vec<Symbol> {
+    let mut names = Vec::new();
+    let mut stack = vec![pattern];
+    while let Some(pattern) = stack.pop() {
+        match &pattern.kind {
+            PatternKind::Variable(name) => names.push(*name),
+            PatternKind::Constructor { args, .. } => stack.extend(args),
+            PatternKind::Alias { name, pattern } => {
+                names.push(*name);
+                stack.push(pattern);
+            }
+            PatternKind::Annotated { pattern, .. } => stack.push(pattern),
+            _ => {}
+        }
+    }
+    names
+}
+
+/// `selves` without the names bound in an inner scope
+fn shadowed(
+    selves: &HashSet<Symbol>,
+    bound: impl IntoIterator<Item = Symbol>,
+) -> HashSet<Symbol> {
+    let mut scope = selves.clone();
+    for name in bound {
+        scope.remove(&name);
+    }
+    scope
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use anon_ast::definition::Definition;
+
+    use super::*;
+    use crate::{Lexer, ast_builder::Parser};
+
+    /// a method name or a message, with the source text of its span
+    type WithText = (String, String);
+
+    /// the resolved calls of the only type declaration in `src` and the diagnostics
+    fn resolve(src: &str) -> (Vec<WithText>, Vec<WithText>) {
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = Lexer::new(src, 4, interner.clone());
+        let mut parser = Parser::new(lexer, interner.clone());
+        let definitions: Vec<_> = parser.by_ref().collect();
+        let text =
+            |span: Span| src[span.start() as usize..span.end() as usize].to_string();
+        let interner = interner.borrow();
+
+        let [Definition::TypeDecl(decl)] = &definitions[..] else {
+            panic!("expected a type declaration, found {definitions:?}");
+        };
+        let calls = decl
+            .self_calls
+            .iter()
+            .map(|call| {
+                let method = interner.resolve(call.method).unwrap().to_string();
+                (method, text(call.span))
+            })
+            .collect();
+        let diagnostics = parser
+            .diagnostics()
+            .iter()
+            .map(|err| (err.message.clone(), text(err.primary_span)))
+            .collect();
+        (calls, diagnostics)
+    }
+
+    #[test]
+    fn test_self_calls() {
+        let (calls, diagnostics) = resolve(
+            "type Maybe a = data a { Just a | None } can {
+  isNone :: Self -> Bool
+  isNone None = True
+  isNone _ = False
+
+  isJust :: Self -> Bool
+  isJust m = m.isNone.not
+
+  unwrapOr :: Self -> a -> a
+  unwrapOr m d = match m.isNone
+      True -> d
+      False -> go
+    where go = m.unwrap
+
+  unwrap :: Self -> a
+  unwrap ((Just _) @ m) = m.first
+  unwrap None = panic! \"Unwrapping None!\"
+  first (xs :: Self) = xs.unwrap
+}
+",
+        );
+        assert_eq!(Vec::<WithText>::new(), diagnostics);
+        let call = |method: &str, text: &str| (method.to_string(), text.to_string());
+        assert_eq!(
+            vec![
+                call("isNone", "m.isNone"),
+                call("isNone", "m.isNone"),
+                call("unwrap", "m.unwrap"),
+                call("first", "m.first"),
+                call("unwrap", "xs.unwrap"),
+            ],
+            calls
+        );
+    }
+
+    #[test]
+    fn test_shadowed_and_unknown_receivers() {
+        let (calls, diagnostics) = resolve(
+            "type T = data { T } can {
+  f :: Self -> Self -> Int
+  f a b = match a
+    T -> b.missing
+  g :: Self -> Int
+  g x = let x = 1 in x.f
+  h :: Int -> Self -> Int
+  h n x = (\\x -> x.f) n
+  i x = x.missing
+  j :: Self -> Int
+  j (x :: Self) = x.missing.f
+}
+",
+        );
+        assert!(calls.is_empty(), "{calls:?}");
+        let missing = "no method `missing` in the `can` block of `T`".to_string();
+        assert_eq!(
+            vec![
+                (missing.clone(), "b.missing".to_string()),
+                (missing, "x.missing".to_string()),
+            ],
+            diagnostics
+        );
+    }
+}
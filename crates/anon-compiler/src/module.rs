@@ -0,0 +1,322 @@
+//! Finding the files of modules, `import Foo.Bar` refers to the file `Foo/Bar.an`
+//! under the source root, which must be registered in the [SourceMap].
+
+use std::{cell::RefCell, path::Path, rc::Rc};
+
+use anon_ast::{
+    definition::Definition,
+    module::{ListedName, ModuleDecl, ModulePath},
+};
+use anon_core::{
+    diagnostic::{Severity, SimpleDiagnostic},
+    interner::Interner,
+    source::{SourceId, SourceMap},
+};
+
+use crate::{
+    Lexer, ast_builder::Parser, fixity::FixityTable, lexer::LexerConfig,
+    untyped_ast::UntypedAST,
+};
+
+/// parses the module `id`, the fixities exported by its imports apply to the definitions
+/// after the `import`
+///
+/// the diagnostics are the parser's and the ones of checking the header and the imports,
+/// the errors of an imported module are reported at its `import`
+pub fn parse_module(
+    id: SourceId,
+    root: &Path,
+    source_map: &SourceMap,
+    interner: Rc<RefCell<Interner>>,
+) -> (Vec<UntypedAST>, Vec<SimpleDiagnostic>) {
+    let source = source_map.get(id);
+    let lexer =
+        Lexer::with_config(&source.content, LexerConfig::default(), interner.clone());
+    let mut parser = Parser::new(lexer, interner.clone());
+    let mut definitions = Vec::new();
+    let mut diagnostics = Vec::new();
+    // 导入的运算符只对之后的定义生效，所以一个一个地解析
+    while let Some(definition) = parser.next() {
+        match &definition {
+            Definition::Module(decl) => {
+                let checked = check_module_header(
+                    decl,
+                    &source.file_name,
+                    root,
+                    &interner.borrow(),
+                );
+                diagnostics.extend(checked.err());
+            }
+            Definition::Import(import) => {
+                let resolved = resolve_module(
+                    &import.module,
+                    root,
+                    source_map,
+                    &interner.borrow(),
+                );
+                match resolved {
+                    Ok(imported) => {
+                        let (fixities, errors) =
+                            exported_fixities(imported, source_map, interner.clone());
+                        // 它们的位置在被导入的文件里
+                        let name = import.module.name(&interner.borrow());
+                        diagnostics.extend(errors.into_iter().map(|err| {
+                            SimpleDiagnostic::new(
+                                err.severity,
+                                format!("in the module `{name}`: {}", err.message),
+                                import.module.span,
+                                None,
+                            )
+                        }));
+                        parser.import_fixities(&fixities, import);
+                    }
+                    Err(err) => diagnostics.push(err),
+                }
+            }
+            _ => {}
+        }
+        definitions.push(definition);
+    }
+    diagnostics.extend(parser.diagnostics().iter().cloned());
+    (definitions, diagnostics)
+}
+
+/// the fixities exported by the module `id` and the diagnostics of parsing it, which are
+/// located in its file
+///
+/// the module is parsed without its own imports, only the operators named by its `export`
+/// list are exported if it has one
+pub fn exported_fixities(
+    id: SourceId,
+    source_map: &SourceMap,
+    interner: Rc<RefCell<Interner>>,
+) -> (FixityTable, Vec<SimpleDiagnostic>) {
+    let content = source_map.get_content(id);
+    let lexer = Lexer::with_config(content, LexerConfig::default(), interner.clone());
+    let mut parser = Parser::new(lexer, interner);
+    let mut export: Option<Vec<ListedName>> = None;
+    for definition in parser.by_ref() {
+        if let Definition::Export(decl) = definition {
+            export.get_or_insert_default().extend(decl.names);
+        }
+    }
+    let fixities = parser.fixities().exported(export.as_deref());
+    (fixities, parser.diagnostics().to_vec())
+}
+
+/// the source of the module `path`, the file names in `source_map` include `root`
+pub fn resolve_module(
+    path: &ModulePath,
+    root: &Path,
+    source_map: &SourceMap,
+    interner: &Interner,
+) -> Result<SourceId, SimpleDiagnostic> {
+    let file_name = root.join(path.file_name(interner));
+    source_map.find(&file_name).ok_or_else(|| {
+        SimpleDiagnostic::new(
+            Severity::Error,
+            format!(
+                "module `{}` not found, expected the file `{}`",
+                path.name(interner),
+                file_name.display()
+            ),
+            path.span,
+            None,
+        )
+    })
+}
+
+/// the module header of the file `file_name` must name the file, e.g. `module Foo.Bar`
+/// for `Foo/Bar.an` under `root`
+pub fn check_module_header(
+    decl: &ModuleDecl,
+    file_name: &Path,
+    root: &Path,
+    interner: &Interner,
+) -> Result<(), SimpleDiagnostic> {
+    let expected = root.join(decl.path.file_name(interner));
+    if expected == file_name {
+        return Ok(());
+    }
+    Err(SimpleDiagnostic::new(
+        Severity::Error,
+        format!(
+            "the module `{}` must be in the file `{}`, found it in `{}`",
+            decl.path.name(interner),
+            expected.display(),
+            file_name.display()
+        ),
+        decl.path.span,
+        None,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+    use anon_ast::expr::ExprKind;
+    use anon_core::source::Source;
+
+    use super::*;
+
+    fn source(file_name: &str, content: &str) -> Source {
+        Source {
+            file_name: PathBuf::from(file_name),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_imports() {
+        let mut source_map = SourceMap::new();
+        source_map.add_or_get(source("src/Main.an", ""));
+        let vec = source_map.add_or_get(source("src/Data/Vec.an", "module Data.Vec\n"));
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let src = "module Main\nimport Data.Vec as V\nimport Data.Map\n";
+        let lexer = Lexer::new(src, 4, interner.clone());
+        let definitions: Vec<_> = Parser::new(lexer, interner.clone()).collect();
+
+        let interner = interner.borrow();
+        let root = Path::new("src");
+        let Definition::Module(header) = &definitions[0] else {
+            panic!("expected a module header, found {:?}", definitions[0]);
+        };
+        assert!(
+            check_module_header(header, Path::new("src/Main.an"), root, &interner)
+                .is_ok()
+        );
+        let err = check_module_header(header, Path::new("src/App.an"), root, &interner)
+            .unwrap_err();
+        assert_eq!(
+            "the module `Main` must be in the file `src/Main.an`, found it in `src/App.an`",
+            err.message
+        );
+
+        let imports: Vec<_> = definitions[1..]
+            .iter()
+            .map(|definition| match definition {
+                Definition::Import(import) => {
+                    resolve_module(&import.module, root, &source_map, &interner)
+                        .map_err(|err| err.message)
+                }
+                other => panic!("expected an import, found {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                Ok(vec),
+                Err(
+                    "module `Data.Map` not found, expected the file `src/Data/Map.an`"
+                        .to_string()
+                )
+            ],
+            imports
+        );
+    }
+
+    /// parses `src/Main.an` among `modules`, returns the outermost operator of every
+    /// function and the diagnostics
+    fn parse_main(modules: &[(&str, &str)], main: &str) -> (Vec<String>, Vec<String>) {
+        let mut source_map = SourceMap::new();
+        for (file_name, content) in modules {
+            source_map.add_or_get(source(file_name, content));
+        }
+        let main = source_map.add_or_get(source("src/Main.an", main));
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let (definitions, diagnostics) =
+            parse_module(main, Path::new("src"), &source_map, interner.clone());
+        let interner = interner.borrow();
+        let top_operators = definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::FuncDecl(decl) => match &decl.clauses[0].body.kind {
+                    ExprKind::Binary { op, .. } => {
+                        Some(interner.resolve(*op).unwrap().to_string())
+                    }
+                    other => panic!("expected an operator, found {other:?}"),
+                },
+                _ => None,
+            })
+            .collect();
+        let messages = diagnostics.into_iter().map(|err| err.message).collect();
+        (top_operators, messages)
+    }
+
+    #[test]
+    fn test_imported_fixities() {
+        let (top_operators, messages) = parse_main(
+            &[("src/Ops.an", "module Ops\ninfixr 0 <$>\n")],
+            "module Main\nx = a <$> b + c\nimport Ops\ny = a <$> b + c\nimport Data.Map\n",
+        );
+        assert_eq!(
+            vec!["module `Data.Map` not found, expected the file `src/Data/Map.an`"],
+            messages
+        );
+        // 导入之前 `<$>` 是默认的 infixl 9
+        assert_eq!(vec!["+", "<$>"], top_operators);
+    }
+
+    #[test]
+    fn test_fixities_follow_export_and_import_lists() {
+        let ops = ("src/Ops.an", "module Ops\ninfixr 0 <$>\ninfixr 0 <+>\n");
+        let uses = "x = a <$> b + c\ny = a <+> b + c\n";
+
+        let (top_operators, messages) =
+            parse_main(&[ops], &format!("module Main\nimport Ops ((<$>))\n{uses}"));
+        assert!(messages.is_empty(), "{messages:?}");
+        assert_eq!(vec!["<$>", "+"], top_operators);
+
+        let (top_operators, messages) = parse_main(
+            &[ops],
+            &format!("module Main\nimport Ops hiding ((<$>))\n{uses}"),
+        );
+        assert!(messages.is_empty(), "{messages:?}");
+        assert_eq!(vec!["+", "<+>"], top_operators);
+
+        // 只导出 `export` 列出的运算符
+        let ops = (
+            "src/Ops.an",
+            "module Ops\nexport ((<+>))\ninfixr 0 <$>\ninfixr 0 <+>\n",
+        );
+        let (top_operators, messages) =
+            parse_main(&[ops], &format!("module Main\nimport Ops\n{uses}"));
+        assert!(messages.is_empty(), "{messages:?}");
+        assert_eq!(vec!["+", "<+>"], top_operators);
+    }
+
+    #[test]
+    fn test_conflicting_imported_fixities() {
+        let modules = [
+            ("src/A.an", "module A\ninfixr 0 <$>\n"),
+            ("src/B.an", "module B\ninfixl 4 <$>\ninfixl 10 <|>\n"),
+            ("src/C.an", "module C\ninfixr 0 <$>\n"),
+        ];
+        let (top_operators, messages) = parse_main(
+            &modules,
+            "module Main\nimport A\nimport C\nimport B\nx = a <$> b + c\n",
+        );
+        // 第一个导入的生效
+        assert_eq!(vec!["<$>"], top_operators);
+        assert_eq!(
+            vec![
+                "in the module `B`: precedence must be between 0 and 9, found 10",
+                "`<$>` is imported with two different fixities",
+            ],
+            messages
+        );
+
+        // 本地的声明优先，不算冲突
+        let (top_operators, messages) = parse_main(
+            &modules[..2],
+            "module Main\nimport A\nimport B\ninfixl 6 <$>\nx = a <$> b + c\n",
+        );
+        assert_eq!(vec!["+"], top_operators);
+        assert_eq!(
+            vec!["in the module `B`: precedence must be between 0 and 9, found 10"],
+            messages
+        );
+    }
+}
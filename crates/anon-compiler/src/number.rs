@@ -0,0 +1,287 @@
+//! Parsing of numeric literals: radix prefixes, `_` separators, exponents and type suffixes
+
+use anon_ast::literal::{FloatSuffix, IntSuffix, Literal};
+
+/// the type of an integer literal without suffix
+const DEFAULT_INT_TYPE: &str = "Int";
+/// the type of a float literal without suffix
+const DEFAULT_FLOAT_TYPE: &str = "Float";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NumberErrorKind {
+    /// `10abc`, `1.5u8`
+    InvalidSuffix(String),
+    /// `0x`, `0b_`
+    NoDigits,
+    /// `0b102`
+    InvalidDigit { digit: char, radix: u32 },
+    /// the value does not fit the type named here
+    OutOfRange(&'static str),
+}
+
+impl std::fmt::Display for NumberErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSuffix(suffix) => {
+                write!(f, "invalid suffix `{suffix}` for number literal")
+            }
+            Self::NoDigits => write!(f, "number literal has no digits"),
+            Self::InvalidDigit { digit, radix } => {
+                write!(f, "invalid digit `{digit}` in base {radix} literal")
+            }
+            Self::OutOfRange(ty) => write!(f, "literal out of range for `{ty}`"),
+        }
+    }
+}
+
+/// parses an integer literal such as `42`, `1_000`, `0xFF`, `0o17`, `0b1010` or `10u8`.
+///
+/// a decimal integer with a float suffix, e.g. `10f32`, is a float
+pub fn parse_integer(text: &str) -> Result<Literal, NumberErrorKind> {
+    let (radix, body) = match text.get(..2) {
+        Some("0x" | "0X") => (16, &text[2..]),
+        Some("0o" | "0O") => (8, &text[2..]),
+        Some("0b" | "0B") => (2, &text[2..]),
+        _ => (10, text),
+    };
+
+    // binary and octal digits are taken as decimal digits first,
+    // so that `0b102` reports the digit instead of a suffix `2`
+    let digits_end = body
+        .find(|c: char| {
+            !(c == '_' || c.is_ascii_digit() || (radix == 16 && c.is_ascii_hexdigit()))
+        })
+        .unwrap_or(body.len());
+    let (digits, suffix) = body.split_at(digits_end);
+    let digits: String = digits.chars().filter(|&c| c != '_').collect();
+
+    if digits.is_empty() {
+        return Err(NumberErrorKind::NoDigits);
+    }
+    if let Some(digit) = digits.chars().find(|c| !c.is_digit(radix)) {
+        return Err(NumberErrorKind::InvalidDigit { digit, radix });
+    }
+
+    // 有符号类型的最小值是 `-` 加上最大值加一，取负之后由 parser 检查
+    if suffix.is_empty() {
+        return u64::from_str_radix(&digits, radix)
+            .ok()
+            .filter(|&value| value <= i64::MIN.unsigned_abs())
+            .map(Literal::Integer)
+            .ok_or(NumberErrorKind::OutOfRange(DEFAULT_INT_TYPE));
+    }
+
+    if let Some(int_suffix) = IntSuffix::from_suffix(suffix) {
+        return u64::from_str_radix(&digits, radix)
+            .ok()
+            .filter(|&value| value <= int_suffix.max_magnitude())
+            .map(|value| Literal::SuffixedInteger(value, int_suffix))
+            .ok_or(NumberErrorKind::OutOfRange(int_suffix.as_str()));
+    }
+
+    match FloatSuffix::from_suffix(suffix) {
+        Some(float_suffix) if radix == 10 => suffixed_float(&digits, float_suffix),
+        _ => Err(NumberErrorKind::InvalidSuffix(suffix.to_string())),
+    }
+}
+
+/// the type of an integer literal that only fits it as the operand of `-`, e.g. `i8`
+/// for `128i8`, the parser reports these literals anywhere else
+pub fn negation_only(literal: Literal) -> Option<&'static str> {
+    match literal {
+        Literal::Integer(value) if value > i64::MAX as u64 => Some(DEFAULT_INT_TYPE),
+        Literal::SuffixedInteger(value, suffix) if value > suffix.max_value() => {
+            Some(suffix.as_str())
+        }
+        _ => None,
+    }
+}
+
+/// parses a float literal such as `1.5`, `1_000.0`, `2.5e-3`, `1e10` or `1.5f32`
+pub fn parse_float(text: &str) -> Result<Literal, NumberErrorKind> {
+    let mantissa_end = text
+        .char_indices()
+        .find(|&(idx, c)| {
+            let is_exponent_sign = (c == '+' || c == '-')
+                && matches!(text[..idx].chars().last(), Some('e' | 'E'));
+            !(c.is_ascii_digit()
+                || c == '_'
+                || c == '.'
+                || c == 'e'
+                || c == 'E'
+                || is_exponent_sign)
+        })
+        .map_or(text.len(), |(idx, _)| idx);
+    let (mantissa, suffix) = text.split_at(mantissa_end);
+    let mantissa: String = mantissa.chars().filter(|&c| c != '_').collect();
+
+    if suffix.is_empty() {
+        let value: f64 = mantissa
+            .parse()
+            .map_err(|_| NumberErrorKind::InvalidSuffix(suffix.to_string()))?;
+        return if value.is_finite() {
+            Ok(Literal::Float(value))
+        } else {
+            Err(NumberErrorKind::OutOfRange(DEFAULT_FLOAT_TYPE))
+        };
+    }
+
+    match FloatSuffix::from_suffix(suffix) {
+        Some(float_suffix) => suffixed_float(&mantissa, float_suffix),
+        None => Err(NumberErrorKind::InvalidSuffix(suffix.to_string())),
+    }
+}
+
+fn suffixed_float(
+    mantissa: &str,
+    suffix: FloatSuffix,
+) -> Result<Literal, NumberErrorKind> {
+    let value: f64 = mantissa
+        .parse()
+        .map_err(|_| NumberErrorKind::InvalidSuffix(suffix.as_str().to_string()))?;
+    let fits = match suffix {
+        FloatSuffix::F32 => (value as f32).is_finite(),
+        FloatSuffix::F64 => value.is_finite(),
+    };
+    if fits {
+        Ok(Literal::SuffixedFloat(value, suffix))
+    } else {
+        Err(NumberErrorKind::OutOfRange(suffix.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(Ok(Literal::Integer(42)), parse_integer("42"));
+        assert_eq!(Ok(Literal::Integer(1_000_000)), parse_integer("1_000_000"));
+        assert_eq!(Ok(Literal::Integer(255)), parse_integer("0xff"));
+        assert_eq!(
+            Ok(Literal::Integer(0xdead_beef)),
+            parse_integer("0xDEAD_BEEF")
+        );
+        assert_eq!(Ok(Literal::Integer(15)), parse_integer("0o17"));
+        assert_eq!(Ok(Literal::Integer(10)), parse_integer("0b1010"));
+        assert_eq!(
+            Ok(Literal::Integer(i64::MAX as u64)),
+            parse_integer("9223372036854775807")
+        );
+    }
+
+    #[test]
+    fn test_integer_suffixes() {
+        assert_eq!(
+            Ok(Literal::SuffixedInteger(10, IntSuffix::U8)),
+            parse_integer("10u8")
+        );
+        assert_eq!(
+            Ok(Literal::SuffixedInteger(u64::MAX, IntSuffix::U64)),
+            parse_integer("0xffff_ffff_ffff_ffffu64")
+        );
+        assert_eq!(
+            Ok(Literal::SuffixedInteger(127, IntSuffix::I8)),
+            parse_integer("0b111_1111i8")
+        );
+        assert_eq!(
+            Ok(Literal::SuffixedFloat(10.0, FloatSuffix::F32)),
+            parse_integer("10f32")
+        );
+    }
+
+    #[test]
+    fn test_integer_out_of_range() {
+        assert_eq!(
+            Err(NumberErrorKind::OutOfRange("u8")),
+            parse_integer("256u8")
+        );
+        assert_eq!(
+            Err(NumberErrorKind::OutOfRange("i8")),
+            parse_integer("129i8")
+        );
+        assert_eq!(
+            Err(NumberErrorKind::OutOfRange("Int")),
+            parse_integer("9223372036854775809")
+        );
+        assert_eq!(
+            Err(NumberErrorKind::OutOfRange("u64")),
+            parse_integer("99999999999999999999u64")
+        );
+    }
+
+    #[test]
+    fn test_minimum_of_signed_types() {
+        // `-128i8` 是 `-` 加上 `128i8`
+        let min_i8 = parse_integer("128i8");
+        assert_eq!(Ok(Literal::SuffixedInteger(128, IntSuffix::I8)), min_i8);
+        assert_eq!(Some("i8"), negation_only(min_i8.unwrap()));
+        assert_eq!(None, negation_only(parse_integer("127i8").unwrap()));
+
+        let min_int = parse_integer("9223372036854775808");
+        assert_eq!(Ok(Literal::Integer(1 << 63)), min_int);
+        assert_eq!(Some("Int"), negation_only(min_int.unwrap()));
+        assert_eq!(
+            Some("i64"),
+            negation_only(parse_integer("0x8000_0000_0000_0000i64").unwrap())
+        );
+
+        // 无符号类型没有负数
+        assert_eq!(
+            Err(NumberErrorKind::OutOfRange("u8")),
+            parse_integer("256u8")
+        );
+        assert_eq!(None, negation_only(parse_integer("255u8").unwrap()));
+    }
+
+    #[test]
+    fn test_malformed_integers() {
+        assert_eq!(Err(NumberErrorKind::NoDigits), parse_integer("0x"));
+        assert_eq!(Err(NumberErrorKind::NoDigits), parse_integer("0b_"));
+        assert_eq!(
+            Err(NumberErrorKind::InvalidDigit {
+                digit: '2',
+                radix: 2
+            }),
+            parse_integer("0b102")
+        );
+        assert_eq!(
+            Err(NumberErrorKind::InvalidSuffix("abc".to_string())),
+            parse_integer("10abc")
+        );
+        assert_eq!(
+            Err(NumberErrorKind::InvalidSuffix("f32".to_string())),
+            parse_integer("0b1f32")
+        );
+    }
+
+    #[test]
+    fn test_floats() {
+        assert_eq!(Ok(Literal::Float(1.5)), parse_float("1.5"));
+        assert_eq!(Ok(Literal::Float(1000.25)), parse_float("1_000.25"));
+        assert_eq!(Ok(Literal::Float(2.5e-3)), parse_float("2.5e-3"));
+        assert_eq!(Ok(Literal::Float(1e10)), parse_float("1e10"));
+        assert_eq!(Ok(Literal::Float(3e2)), parse_float("3E+2"));
+        assert_eq!(
+            Ok(Literal::SuffixedFloat(1.5, FloatSuffix::F32)),
+            parse_float("1.5f32")
+        );
+    }
+
+    #[test]
+    fn test_malformed_floats() {
+        assert_eq!(
+            Err(NumberErrorKind::OutOfRange("Float")),
+            parse_float("1e400")
+        );
+        assert_eq!(
+            Err(NumberErrorKind::OutOfRange("f32")),
+            parse_float("1e39f32")
+        );
+        assert_eq!(
+            Err(NumberErrorKind::InvalidSuffix("u8".to_string())),
+            parse_float("1.5u8")
+        );
+    }
+}
@@ -6,7 +6,98 @@ pub enum Operator {
     Negate,
     /// ->
     Arrow,
+    /// =>
+    FatArrow,
+    /// +
     Add,
+    /// /
     Div,
+    /// *
     Mul,
+    /// %
+    Mod,
+    /// ==
+    EqEq,
+    /// !=
+    NotEq,
+    /// <
+    Lt,
+    /// <=
+    Le,
+    /// >
+    Gt,
+    /// >=
+    Ge,
+    /// &&
+    And,
+    /// ||
+    Or,
+    /// !
+    Not,
+    /// >>, `f >> g` is `\x -> g (f x)`
+    ComposeRight,
+    /// <<, `f << g` is `\x -> f (g x)`
+    ComposeLeft,
+    /// $
+    Apply,
+    /// ..
+    Range,
+}
+
+impl Operator {
+    /// the built-in operator spelled `symbol`, if any
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        let op = match symbol {
+            "=" => Self::Eq,
+            "-" => Self::Negate,
+            "->" => Self::Arrow,
+            "=>" => Self::FatArrow,
+            "+" => Self::Add,
+            "/" => Self::Div,
+            "*" => Self::Mul,
+            "%" => Self::Mod,
+            "==" => Self::EqEq,
+            "!=" => Self::NotEq,
+            "<" => Self::Lt,
+            "<=" => Self::Le,
+            ">" => Self::Gt,
+            ">=" => Self::Ge,
+            "&&" => Self::And,
+            "||" => Self::Or,
+            "!" => Self::Not,
+            ">>" => Self::ComposeRight,
+            "<<" => Self::ComposeLeft,
+            "$" => Self::Apply,
+            ".." => Self::Range,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// how the operator is spelled
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Negate => "-",
+            Self::Arrow => "->",
+            Self::FatArrow => "=>",
+            Self::Add => "+",
+            Self::Div => "/",
+            Self::Mul => "*",
+            Self::Mod => "%",
+            Self::EqEq => "==",
+            Self::NotEq => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::And => "&&",
+            Self::Or => "||",
+            Self::Not => "!",
+            Self::ComposeRight => ">>",
+            Self::ComposeLeft => "<<",
+            Self::Apply => "$",
+            Self::Range => "..",
+        }
+    }
 }
@@ -0,0 +1,664 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use anon_core::{
+    diagnostic::{Severity, SimpleDiagnostic},
+    interner::Interner,
+    span::{Span, Spanned},
+};
+use pest::{
+    Parser as _,
+    iterators::{Pair, Pairs},
+};
+
+use crate::{
+    lexer::update_brackets,
+    line_tokenizer::{LineTokenizer, PestParser, Rule, span_of},
+    token::Token,
+    token_stream::SpannedTokenStream,
+};
+
+/// the lexer built on the pest grammar in `anon.pest`,
+/// kept as the reference implementation that [crate::Lexer] is tested against
+#[derive(Debug, Clone)]
+pub struct PestLexer<'a> {
+    // 外部迭代器：提供 Rule::LINE、Rule::LAST_LINE 和 Rule::EOI，解析失败时为 None
+    file_pairs: Option<Pairs<'a, Rule>>,
+    // 缩进状态
+    indent_stack: Vec<usize>,
+    // 输出缓冲区：用于存储 INDENT/DEDENT
+    output_buffer: VecDeque<Spanned<Token>>,
+    // Tab 宽度
+    tab_width: u32,
+    // 还没有闭合的括号，其中有 `true` 时换行不产生 layout token
+    open_brackets: Vec<bool>,
+    // 词法错误，lexer 遇到错误不会停止
+    diagnostics: Vec<SimpleDiagnostic>,
+    // ... 其他字段 ...
+    interner: Rc<RefCell<Interner>>,
+}
+
+impl<'a> PestLexer<'a> {
+    pub fn new(
+        test_str: &'a str,
+        tab_width: u32,
+        interner: Rc<RefCell<Interner>>,
+    ) -> Self {
+        match PestParser::parse(Rule::File, test_str) {
+            Ok(mut pairs) => {
+                let file = pairs.next().expect("File rule always produces a pair");
+                Self::new_from_pair(file, tab_width, interner)
+            }
+            // File 规则能接受任意输入，这里只是兜底
+            Err(err) => {
+                let at = match err.location {
+                    pest::error::InputLocation::Pos(pos) => pos,
+                    pest::error::InputLocation::Span((start, _)) => start,
+                } as u32;
+                let eof_span = Span::empty(test_str.len() as u32);
+                Self {
+                    file_pairs: None,
+                    indent_stack: vec![0],
+                    output_buffer: VecDeque::from([Spanned::new(eof_span, Token::EOF)]),
+                    tab_width,
+                    open_brackets: Vec::new(),
+                    diagnostics: vec![SimpleDiagnostic::new(
+                        Severity::Error,
+                        format!(
+                            "unable to tokenize the file: {}",
+                            err.variant.message()
+                        ),
+                        Span::empty(at),
+                        None,
+                    )],
+                    interner,
+                }
+            }
+        }
+    }
+
+    pub fn new_from_pair(
+        file_pair: Pair<'a, Rule>,
+        tab_width: u32,
+        interner: Rc<RefCell<Interner>>,
+    ) -> Self {
+        debug_assert_eq!(
+            file_pair.as_rule(),
+            Rule::File,
+            "PestLexer should only receive File rule"
+        );
+
+        Self {
+            file_pairs: Some(file_pair.into_inner()), // LINE, LINE, ..., EOI
+            // 根缩进 [0]
+            indent_stack: vec![0],
+            output_buffer: VecDeque::new(),
+            tab_width,
+            open_brackets: Vec::new(),
+            diagnostics: Vec::new(),
+            interner,
+        }
+    }
+
+    /// the lexical errors reported so far
+    pub fn diagnostics(&self) -> &[SimpleDiagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<SimpleDiagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn lex_line(&mut self, line_pair: Pair<'a, Rule>) {
+        let line_span = span_of(&line_pair);
+        let mut line_tokenizer = LineTokenizer::new_line_pairs(
+            line_pair.into_inner(),
+            self.interner.clone(),
+        );
+        let (indent_count, mut tokens) = line_tokenizer.parse_line(self.tab_width);
+        self.diagnostics.extend(line_tokenizer.take_diagnostics());
+
+        // 括号里的行接在上一行后面，没有 layout
+        let continued = self.open_brackets.contains(&true);
+        update_brackets(&mut self.open_brackets, &tokens);
+        let joined = self.open_brackets.contains(&true);
+
+        // 空行（或只有注释的行）不参与缩进计算
+        if tokens.iter().all(|tok| tok.value == Token::Newline) {
+            if !joined {
+                self.output_buffer.extend(tokens);
+            }
+            return;
+        }
+
+        if joined {
+            if tokens.last().is_some_and(|tok| tok.value == Token::Newline) {
+                tokens.pop();
+            }
+        } else if tokens.last().is_none_or(|tok| tok.value != Token::Newline) {
+            // 文件最后一行可能没有换行符，补一个空的 NEWLINE
+            tokens.push(Spanned::new(Span::empty(line_span.end()), Token::Newline));
+        }
+        if continued {
+            self.output_buffer.extend(tokens);
+            return;
+        }
+
+        // INDENT 覆盖行首的空白，DEDENT 是第一个 token 前的空 span
+        let first_start = tokens[0].context.start();
+        let indent_span = Span::new(line_span.start(), first_start)
+            .expect("tokens never start before their line");
+
+        if indent_count > *self.indent_stack.last().unwrap_or(&0) {
+            self.indent_stack.push(indent_count);
+            self.output_buffer
+                .push_back(Spanned::new(indent_span, Token::Indent));
+        }
+        // Check if we need to decrease indentation
+        else if indent_count < *self.indent_stack.last().unwrap_or(&0) {
+            while indent_count < *self.indent_stack.last().unwrap_or(&0) {
+                self.indent_stack.pop();
+                self.output_buffer
+                    .push_back(Spanned::new(Span::empty(first_start), Token::Dedent));
+            }
+            // After dedenting, the indentation should match exactly
+            // 否则报错，并把这一行当作新的缩进层级，保证 INDENT/DEDENT 成对出现
+            if indent_count != *self.indent_stack.last().unwrap_or(&0) {
+                self.diagnostics.push(SimpleDiagnostic::new(
+                    Severity::Error,
+                    format!(
+                        "inconsistent indentation: width {indent_count} does not match any outer indentation level"
+                    ),
+                    indent_span,
+                    None,
+                ));
+                self.output_buffer
+                    .push_back(Spanned::new(indent_span, Token::Error));
+                self.indent_stack.push(indent_count);
+                self.output_buffer
+                    .push_back(Spanned::new(indent_span, Token::Indent));
+            }
+        }
+        self.output_buffer.extend(tokens);
+    }
+}
+
+impl<'a> Iterator for PestLexer<'a> {
+    type Item = Spanned<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // 优先从缓存里读取
+            if let Some(buffered_token) = self.output_buffer.pop_front() {
+                return Some(buffered_token);
+            }
+
+            let file_pair = self.file_pairs.as_mut()?.next()?;
+
+            match file_pair.as_rule() {
+                Rule::LINE | Rule::LAST_LINE => self.lex_line(file_pair),
+                Rule::EOI => {
+                    let eof_span = Span::empty(span_of(&file_pair).start());
+                    while self.indent_stack.len() > 1 {
+                        self.indent_stack.pop();
+                        self.output_buffer
+                            .push_back(Spanned::new(eof_span, Token::Dedent));
+                    }
+
+                    self.output_buffer
+                        .push_back(Spanned::new(eof_span, Token::EOF));
+                }
+                x => {
+                    unreachable!("Unreachable file rule: {:#?}", x)
+                }
+            }
+        }
+    }
+}
+
+impl<'a> SpannedTokenStream<'a> for PestLexer<'a> {}
+
+#[cfg(test)]
+mod test {
+    use anon_core::line_map::LineMap;
+    use pest::Parser;
+
+    use crate::{keyword::Keyword, line_tokenizer::PestParser};
+
+    use super::*;
+
+    #[test]
+    fn test_single_line() {
+        let test_str = "x = 1 \n";
+        let file = PestParser::parse(Rule::File, test_str)
+            .expect("unsuccessful parse")
+            .next()
+            .unwrap();
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = PestLexer::new_from_pair(file, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.map(|t| t.value).collect();
+
+        let expected = vec![
+            Token::Identifier(interner.borrow_mut().intern_or_get("x")),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Literal(anon_ast::literal::Literal::Integer(1)),
+            Token::Newline,
+            Token::EOF,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_two_lines() {
+        let test_str = "x=1\ny=2\n";
+        let file = PestParser::parse(Rule::File, test_str)
+            .expect("unsuccessful parse")
+            .next()
+            .unwrap();
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = PestLexer::new_from_pair(file, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.map(|t| t.value).collect();
+
+        let x_sym = interner.borrow_mut().intern_or_get("x");
+        let y_sym = interner.borrow_mut().intern_or_get("y");
+
+        let expected = vec![
+            Token::Identifier(x_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Literal(anon_ast::literal::Literal::Integer(1)),
+            Token::Newline,
+            Token::Identifier(y_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Literal(anon_ast::literal::Literal::Integer(2)),
+            Token::Newline,
+            Token::EOF,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_two_line_indent() {
+        let test_str = "x=1\n    y=2\n";
+        let file = PestParser::parse(Rule::File, test_str)
+            .expect("unsuccessful parse")
+            .next()
+            .unwrap();
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = PestLexer::new_from_pair(file, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.map(|t| t.value).collect();
+
+        let x_sym = interner.borrow_mut().intern_or_get("x");
+        let y_sym = interner.borrow_mut().intern_or_get("y");
+
+        let expected = vec![
+            Token::Identifier(x_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Literal(anon_ast::literal::Literal::Integer(1)),
+            Token::Newline,
+            Token::Indent,
+            Token::Identifier(y_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Literal(anon_ast::literal::Literal::Integer(2)),
+            Token::Newline,
+            Token::Dedent,
+            Token::EOF,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_multiple_line_indent() {
+        let test_str = "x=1\n    y=2\n    z = 3 \nphi = 4 \n";
+        let file = PestParser::parse(Rule::File, test_str)
+            .expect("unsuccessful parse")
+            .next()
+            .unwrap();
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = PestLexer::new_from_pair(file, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.map(|t| t.value).collect();
+
+        let x_sym = interner.borrow_mut().intern_or_get("x");
+        let y_sym = interner.borrow_mut().intern_or_get("y");
+        let z_sym = interner.borrow_mut().intern_or_get("z");
+        let phi_sym = interner.borrow_mut().intern_or_get("phi");
+
+        let expected = vec![
+            Token::Identifier(x_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Literal(anon_ast::literal::Literal::Integer(1)),
+            Token::Newline,
+            Token::Indent,
+            Token::Identifier(y_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Literal(anon_ast::literal::Literal::Integer(2)),
+            Token::Newline,
+            Token::Identifier(z_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Literal(anon_ast::literal::Literal::Integer(3)),
+            Token::Newline,
+            Token::Dedent,
+            Token::Identifier(phi_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Literal(anon_ast::literal::Literal::Integer(4)),
+            Token::Newline,
+            Token::EOF,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_if() {
+        let test_str = "if x\n  then\n    y\n  else\n    z\n";
+        let file = PestParser::parse(Rule::File, test_str)
+            .expect("unsuccessful parse")
+            .next()
+            .unwrap();
+
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = PestLexer::new_from_pair(file, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.map(|t| t.value).collect();
+
+        let x_sym = interner.borrow_mut().intern_or_get("x");
+        let y_sym = interner.borrow_mut().intern_or_get("y");
+        let z_sym = interner.borrow_mut().intern_or_get("z");
+
+        let expected = vec![
+            Token::Keyword(Keyword::If),
+            Token::Identifier(x_sym),
+            Token::Newline,
+            Token::Indent,
+            Token::Keyword(Keyword::Then),
+            Token::Newline,
+            Token::Indent,
+            Token::Identifier(y_sym),
+            Token::Newline,
+            Token::Dedent,
+            Token::Keyword(Keyword::Else),
+            Token::Newline,
+            Token::Indent,
+            Token::Identifier(z_sym),
+            Token::Newline,
+            Token::Dedent,
+            Token::Dedent,
+            Token::EOF,
+        ];
+
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_spans() {
+        let test_str = "x = 1\n  yy\n";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = PestLexer::new(test_str, 4, interner.clone());
+
+        let spans: Vec<_> = lexer
+            .map(|t| (t.value, (t.context.start(), t.context.end())))
+            .collect();
+
+        let x_sym = interner.borrow_mut().intern_or_get("x");
+        let yy_sym = interner.borrow_mut().intern_or_get("yy");
+
+        let expected = vec![
+            (Token::Identifier(x_sym), (0, 1)),
+            (Token::Operator(crate::operator::Operator::Eq), (2, 3)),
+            (
+                Token::Literal(anon_ast::literal::Literal::Integer(1)),
+                (4, 5),
+            ),
+            (Token::Newline, (5, 6)),
+            (Token::Indent, (6, 8)),
+            (Token::Identifier(yy_sym), (8, 10)),
+            (Token::Newline, (10, 11)),
+            (Token::Dedent, (11, 11)),
+            (Token::EOF, (11, 11)),
+        ];
+
+        assert_eq!(expected, spans);
+    }
+
+    #[test]
+    fn test_spans_match_line_map() {
+        let test_str = "x=1\r\nif y\n    z\n";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let line_map = LineMap::new(test_str);
+        let lexer = PestLexer::new(test_str, 4, interner.clone());
+
+        let locations: Vec<_> = lexer
+            .filter(|t| !t.context.is_empty())
+            .map(|t| {
+                let loc = line_map.index_to_location(t.context.start()).unwrap();
+                (t.value, (loc.line(), loc.column()))
+            })
+            .collect();
+
+        let x_sym = interner.borrow_mut().intern_or_get("x");
+        let y_sym = interner.borrow_mut().intern_or_get("y");
+        let z_sym = interner.borrow_mut().intern_or_get("z");
+
+        let expected = vec![
+            (Token::Identifier(x_sym), (0, 0)),
+            (Token::Operator(crate::operator::Operator::Eq), (0, 1)),
+            (
+                Token::Literal(anon_ast::literal::Literal::Integer(1)),
+                (0, 2),
+            ),
+            (Token::Newline, (0, 3)),
+            (Token::Keyword(Keyword::If), (1, 0)),
+            (Token::Identifier(y_sym), (1, 3)),
+            (Token::Newline, (1, 4)),
+            (Token::Indent, (2, 0)),
+            (Token::Identifier(z_sym), (2, 4)),
+            (Token::Newline, (2, 5)),
+        ];
+
+        assert_eq!(expected, locations);
+    }
+
+    #[test]
+    fn test_errors_do_not_stop_lexing() {
+        let test_str = "x = 1 ` 2\ny = 99999999999999999999\nz = 'ab'\n";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut lexer = PestLexer::new(test_str, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.by_ref().map(|t| t.value).collect();
+
+        let x_sym = interner.borrow_mut().intern_or_get("x");
+        let y_sym = interner.borrow_mut().intern_or_get("y");
+        let z_sym = interner.borrow_mut().intern_or_get("z");
+
+        let expected = vec![
+            Token::Identifier(x_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Literal(anon_ast::literal::Literal::Integer(1)),
+            Token::Error,
+            Token::Literal(anon_ast::literal::Literal::Integer(2)),
+            Token::Newline,
+            Token::Identifier(y_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Error,
+            Token::Newline,
+            Token::Identifier(z_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Error,
+            Token::Newline,
+            Token::EOF,
+        ];
+        assert_eq!(expected, tokens);
+
+        let spans: Vec<_> = lexer
+            .diagnostics()
+            .iter()
+            .map(|d| (d.primary_span.start(), d.primary_span.end()))
+            .collect();
+        assert_eq!(vec![(6, 7), (14, 34), (39, 43)], spans);
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let test_str = "s = \"abc\nt\n";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut lexer = PestLexer::new(test_str, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.by_ref().map(|t| t.value).collect();
+        let s_sym = interner.borrow_mut().intern_or_get("s");
+        let t_sym = interner.borrow_mut().intern_or_get("t");
+
+        let expected = vec![
+            Token::Identifier(s_sym),
+            Token::Operator(crate::operator::Operator::Eq),
+            Token::Error,
+            Token::Newline,
+            Token::Identifier(t_sym),
+            Token::Newline,
+            Token::EOF,
+        ];
+        assert_eq!(expected, tokens);
+        assert_eq!(1, lexer.diagnostics().len());
+    }
+
+    #[test]
+    fn test_inconsistent_dedent() {
+        let test_str = "a\n    b\n  c\nd\n";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut lexer = PestLexer::new(test_str, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.by_ref().map(|t| t.value).collect();
+        let sym = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
+
+        let expected = vec![
+            sym("a"),
+            Token::Newline,
+            Token::Indent,
+            sym("b"),
+            Token::Newline,
+            Token::Dedent,
+            Token::Error,
+            Token::Indent,
+            sym("c"),
+            Token::Newline,
+            Token::Dedent,
+            sym("d"),
+            Token::Newline,
+            Token::EOF,
+        ];
+        assert_eq!(expected, tokens);
+
+        let diagnostics = lexer.diagnostics();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            (8, 10),
+            (
+                diagnostics[0].primary_span.start(),
+                diagnostics[0].primary_span.end()
+            )
+        );
+    }
+
+    #[test]
+    fn test_missing_trailing_newline() {
+        let test_str = "x\n  y -- comment";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = PestLexer::new(test_str, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer
+            .map(|t| (t.value, (t.context.start(), t.context.end())))
+            .collect();
+        let x_sym = interner.borrow_mut().intern_or_get("x");
+        let y_sym = interner.borrow_mut().intern_or_get("y");
+
+        let expected = vec![
+            (Token::Identifier(x_sym), (0, 1)),
+            (Token::Newline, (1, 2)),
+            (Token::Indent, (2, 4)),
+            (Token::Identifier(y_sym), (4, 5)),
+            (Token::Newline, (16, 16)),
+            (Token::Dedent, (16, 16)),
+            (Token::EOF, (16, 16)),
+        ];
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let test_str = "a {- one {- nested -} -} b\n{- spans\n   two lines -}\nc -- line {- comment\n";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut lexer = PestLexer::new(test_str, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.by_ref().map(|t| t.value).collect();
+        let sym = |s: &str| Token::Identifier(interner.borrow_mut().intern_or_get(s));
+
+        let expected = vec![
+            sym("a"),
+            sym("b"),
+            Token::Newline,
+            Token::Newline,
+            sym("c"),
+            Token::Newline,
+            Token::EOF,
+        ];
+        assert_eq!(expected, tokens);
+        assert!(lexer.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let test_str = "a\n{- {- -}\nb\n";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let mut lexer = PestLexer::new(test_str, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.by_ref().map(|t| t.value).collect();
+        let a_sym = interner.borrow_mut().intern_or_get("a");
+
+        let expected = vec![
+            Token::Identifier(a_sym),
+            Token::Newline,
+            Token::Error,
+            Token::Newline,
+            Token::EOF,
+        ];
+        assert_eq!(expected, tokens);
+
+        let diagnostics = lexer.diagnostics();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("unterminated block comment", diagnostics[0].message);
+        assert_eq!(
+            (2, 4),
+            (
+                diagnostics[0].primary_span.start(),
+                diagnostics[0].primary_span.end()
+            )
+        );
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let test_str = "--| adds one\n--|   to a number\ninc x = x + 1 -- not a doc\n";
+        let interner = Rc::new(RefCell::new(Interner::new()));
+        let lexer = PestLexer::new(test_str, 4, interner.clone());
+
+        let tokens: Vec<_> = lexer.map(|t| t.value).take(5).collect();
+        let sym = |s: &str| interner.borrow_mut().intern_or_get(s);
+
+        let expected = vec![
+            Token::DocComment(sym("adds one")),
+            Token::Newline,
+            Token::DocComment(sym("to a number")),
+            Token::Newline,
+            Token::Identifier(sym("inc")),
+        ];
+        assert_eq!(expected, tokens);
+    }
+}
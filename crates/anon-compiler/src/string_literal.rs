@@ -0,0 +1,385 @@
+//! The contents of string literals, shared by [crate::Lexer] and [crate::PestLexer]
+//!
+//! both lexers only find where a literal ends, this module strips the indentation of
+//! multi-line strings, decodes escapes and splits out the `{...}` interpolations.
+//! an interpolation can not contain braces, quotes or line breaks,
+//! `\{` and `\}` write literal braces
+
+use std::{cell::RefCell, ops::Range};
+
+use anon_ast::literal::Literal;
+use anon_core::{
+    diagnostic::{Severity, SimpleDiagnostic},
+    interner::Interner,
+    span::{Span, SpanIndex, Spanned},
+};
+
+use crate::{escape::unescape, token::Token};
+
+/// a piece of a string literal, spans are absolute source positions
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    /// decoded text, the span covers everything between the surrounding delimiters
+    Text(String, Span),
+    /// `{` expr `}`, `content` is the source between the braces
+    Interpolation {
+        open: Span,
+        content: Span,
+        close: Span,
+    },
+}
+
+/// a successfully decoded string literal
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringLiteral {
+    /// `"`, `"""`, `r"` or `r"""`
+    pub open: Span,
+    pub parts: Vec<StringPart>,
+    pub close: Span,
+}
+
+impl StringLiteral {
+    pub fn has_interpolation(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, StringPart::Interpolation { .. }))
+    }
+}
+
+fn span(start: usize, end: usize) -> Span {
+    Span::new(start as SpanIndex, end as SpanIndex).expect("string parts are ordered")
+}
+
+fn error(span: Span, message: String) -> SimpleDiagnostic {
+    SimpleDiagnostic::new(Severity::Error, message, span, None)
+}
+
+/// decodes the complete literal `text`, which starts at `offset` in the source
+///
+/// `text` must include its delimiters and the `r` prefix of raw strings
+pub fn parse_string(
+    text: &str,
+    offset: usize,
+) -> Result<StringLiteral, Vec<SimpleDiagnostic>> {
+    let raw = text.starts_with('r');
+    let prefix = raw as usize;
+    let multiline = text[prefix..].starts_with("\"\"\"");
+    let quote = if multiline { 3 } else { 1 };
+    let content = prefix + quote..text.len() - quote;
+
+    let lines = if multiline {
+        strip_indentation(text, content.clone())
+    } else {
+        vec![content.clone()]
+    };
+
+    let mut parts = Vec::new();
+    let mut errors = Vec::new();
+    let mut value = String::new();
+    // 当前这段文本在源码里开始的位置
+    let mut text_start = content.start;
+
+    for (idx, line) in lines.into_iter().enumerate() {
+        if idx > 0 {
+            value.push('\n');
+        }
+        if raw {
+            value.push_str(&text[line]);
+            continue;
+        }
+
+        let mut piece_start = line.start;
+        let mut pos = line.start;
+        while pos < line.end {
+            let rest = &text[pos..line.end];
+            if rest.starts_with("\\u{") {
+                // `\u{...}` 里的花括号不是插值
+                pos += rest.find('}').map_or(rest.len(), |idx| idx + 1);
+            } else if let Some(escaped) = rest.strip_prefix('\\') {
+                pos += 1 + escaped.chars().next().map_or(0, char::len_utf8);
+            } else if let Some(inner) = rest.strip_prefix('{') {
+                let close = inner.find(['{', '}']).map(|idx| pos + 1 + idx);
+                match close {
+                    Some(close) if text[close..].starts_with('}') => {
+                        decode(text, piece_start..pos, offset, &mut value, &mut errors);
+                        if !value.is_empty() {
+                            let value = std::mem::take(&mut value);
+                            parts.push(StringPart::Text(
+                                value,
+                                span(offset + text_start, offset + pos),
+                            ));
+                        }
+                        parts.push(StringPart::Interpolation {
+                            open: span(offset + pos, offset + pos + 1),
+                            content: span(offset + pos + 1, offset + close),
+                            close: span(offset + close, offset + close + 1),
+                        });
+                        pos = close + 1;
+                        piece_start = pos;
+                        text_start = pos;
+                    }
+                    _ => {
+                        errors.push(error(
+                            span(offset + pos, offset + pos + 1),
+                            "unterminated interpolation, expected `}`".to_string(),
+                        ));
+                        pos += 1;
+                    }
+                }
+            } else if rest.starts_with('}') {
+                errors.push(error(
+                    span(offset + pos, offset + pos + 1),
+                    "unmatched `}` in string literal, write `\\}` for a literal brace"
+                        .to_string(),
+                ));
+                pos += 1;
+            } else {
+                pos += rest.chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        decode(text, piece_start..line.end, offset, &mut value, &mut errors);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    if !value.is_empty() || parts.is_empty() {
+        parts.push(StringPart::Text(
+            value,
+            span(offset + text_start, offset + content.end),
+        ));
+    }
+    Ok(StringLiteral {
+        open: span(offset, offset + content.start),
+        parts,
+        close: span(offset + content.end, offset + text.len()),
+    })
+}
+
+/// appends the decoded `text[range]` to `value`
+fn decode(
+    text: &str,
+    range: Range<usize>,
+    offset: usize,
+    value: &mut String,
+    errors: &mut Vec<SimpleDiagnostic>,
+) {
+    let piece = &text[range.clone()];
+    if !piece.contains('\\') {
+        value.push_str(piece);
+        return;
+    }
+    match unescape(piece, (offset + range.start) as SpanIndex) {
+        Ok(decoded) => value.push_str(&decoded),
+        Err(escape_errors) => errors.extend(
+            escape_errors
+                .into_iter()
+                .map(|err| error(err.span, err.kind.to_string())),
+        ),
+    }
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+/// the lines of a multi-line string, without line breaks and common indentation
+///
+/// a line break right after the opening `"""` is dropped, so is the last line when it only
+/// holds the indentation of the closing `"""`. the common indentation is the smallest one of
+/// the non blank lines and the closing line, the first line is kept as is when it follows
+/// the opening `"""` directly
+fn strip_indentation(text: &str, content: Range<usize>) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = content.start;
+    for (idx, _) in text[content.clone()].match_indices('\n') {
+        let end = content.start + idx;
+        let end = if text[..end].ends_with('\r') {
+            end - 1
+        } else {
+            end
+        };
+        lines.push(start..end);
+        start = content.start + idx + 1;
+    }
+    lines.push(start..content.end);
+
+    let opens_with_newline = lines.len() > 1 && lines[0].is_empty();
+    if opens_with_newline {
+        lines.remove(0);
+    }
+    let first_stripped = usize::from(!opens_with_newline);
+
+    let mut closing_indent = None;
+    if lines.len() > first_stripped
+        && let Some(last) = lines.last()
+        && text[last.clone()]
+            .trim_start_matches([' ', '\t'])
+            .is_empty()
+    {
+        closing_indent = Some(last.len());
+        lines.pop();
+    }
+
+    let common = lines
+        .iter()
+        .skip(first_stripped)
+        .map(|line| &text[line.clone()])
+        .filter(|line| !line.trim_start_matches([' ', '\t']).is_empty())
+        .map(indentation)
+        .chain(closing_indent)
+        .min()
+        .unwrap_or(0);
+
+    for line in lines.iter_mut().skip(first_stripped) {
+        line.start += indentation(&text[line.clone()]).min(common);
+    }
+    lines
+}
+
+/// the tokens of a decoded literal, `lex_interpolation` lexes the source of an interpolation
+///
+/// a literal without interpolations is a single [Literal::String]
+pub fn string_tokens(
+    literal: StringLiteral,
+    interner: &RefCell<Interner>,
+    mut lex_interpolation: impl FnMut(Span) -> Vec<Spanned<Token>>,
+) -> Vec<Spanned<Token>> {
+    if !literal.has_interpolation() {
+        let value = match literal.parts.first() {
+            Some(StringPart::Text(value, _)) => value.as_str(),
+            _ => "",
+        };
+        let sym = interner.borrow_mut().intern_or_get(value);
+        return vec![Spanned::new(
+            literal.open.to(literal.close),
+            Token::Literal(Literal::String(sym)),
+        )];
+    }
+
+    let mut tokens = vec![Spanned::new(literal.open, Token::StringStart)];
+    for part in literal.parts {
+        match part {
+            StringPart::Text(value, span) => {
+                let sym = interner.borrow_mut().intern_or_get(&value);
+                tokens.push(Spanned::new(span, Token::StringFragment(sym)));
+            }
+            StringPart::Interpolation {
+                open,
+                content,
+                close,
+            } => {
+                tokens.push(Spanned::new(open, Token::InterpolationStart));
+                tokens.extend(lex_interpolation(content));
+                tokens.push(Spanned::new(close, Token::InterpolationEnd));
+            }
+        }
+    }
+    tokens.push(Spanned::new(literal.close, Token::StringEnd));
+    tokens
+}
+
+/// moves a span lexed on its own to its position in the source
+pub(crate) fn shift(span: Span, by: usize) -> Span {
+    Span::new(span.start() + by as SpanIndex, span.end() + by as SpanIndex)
+        .expect("shifting keeps spans ordered")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text_parts(text: &str) -> Vec<String> {
+        parse_string(text, 0)
+            .unwrap()
+            .parts
+            .into_iter()
+            .map(|part| match part {
+                StringPart::Text(value, _) => value,
+                StringPart::Interpolation { content, .. } => {
+                    format!(
+                        "{{{}}}",
+                        &text[content.start() as usize..content.end() as usize]
+                    )
+                }
+            })
+            .collect()
+    }
+
+    fn messages(text: &str) -> Vec<String> {
+        parse_string(text, 0)
+            .unwrap_err()
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_plain_and_raw() {
+        assert_eq!(vec!["a\tb"], text_parts(r#""a\tb""#));
+        assert_eq!(vec![""], text_parts(r#""""#));
+        assert_eq!(vec![r"a\t{b}"], text_parts(r#"r"a\t{b}""#));
+        assert_eq!(vec!["{x}"], text_parts(r#""\{x\}""#));
+        assert_eq!(vec!["A"], text_parts(r#""\u{41}""#));
+    }
+
+    #[test]
+    fn test_interpolation() {
+        assert_eq!(
+            vec!["hello ", "{name}", "!"],
+            text_parts(r#""hello {name}!""#)
+        );
+        assert_eq!(vec!["{a}", "{ b c }"], text_parts(r#""{a}{ b c }""#));
+
+        let literal = parse_string(r#""a{x}""#, 10).unwrap();
+        assert_eq!(Span::new(10u32, 11u32).unwrap(), literal.open);
+        assert_eq!(
+            StringPart::Interpolation {
+                open: Span::new(12u32, 13u32).unwrap(),
+                content: Span::new(13u32, 14u32).unwrap(),
+                close: Span::new(14u32, 15u32).unwrap(),
+            },
+            literal.parts[1]
+        );
+        assert_eq!(Span::new(15u32, 16u32).unwrap(), literal.close);
+    }
+
+    #[test]
+    fn test_interpolation_errors() {
+        assert_eq!(
+            vec!["unterminated interpolation, expected `}`"],
+            messages(r#""a {b {c}""#)
+        );
+        assert_eq!(
+            vec!["unmatched `}` in string literal, write `\\}` for a literal brace"],
+            messages(r#""a } b""#)
+        );
+        assert_eq!(
+            vec!["unknown escape sequence `\\q`"],
+            messages(r#""{x} \q""#)
+        );
+    }
+
+    #[test]
+    fn test_multiline_indentation() {
+        let text = "\"\"\"\n    first\n      second\n\n    third\n    \"\"\"";
+        assert_eq!(vec!["first\n  second\n\nthird"], text_parts(text));
+
+        // 关闭的 `"""` 缩进更少时，它决定去掉多少缩进
+        let text = "\"\"\"\n    a\n  \"\"\"";
+        assert_eq!(vec!["  a"], text_parts(text));
+
+        // 紧跟在 `"""` 后面的第一行保持原样
+        let text = "\"\"\"  a\n    b\n    c\"\"\"";
+        assert_eq!(vec!["  a\nb\nc"], text_parts(text));
+
+        let text = "r\"\"\"\r\n  \\n {x}\r\n  \"\"\"";
+        assert_eq!(vec!["\\n {x}"], text_parts(text));
+    }
+
+    #[test]
+    fn test_multiline_interpolation() {
+        let text = "\"\"\"\n  hi {name}\n    bye\n  \"\"\"";
+        assert_eq!(vec!["hi ", "{name}", "\n  bye"], text_parts(text));
+    }
+}
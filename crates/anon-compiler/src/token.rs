@@ -14,10 +14,38 @@ pub enum Token {
     EOF,
 
     Identifier(Sym),
+    /// user defined operator made of symbol characters, e.g. `<+>`, `>>=`
+    SymbolicIdentifier(Sym),
     Literal(Literal),
     Operator(Operator),
     Keyword(Keyword),
     Delimiter(Delimiter),
+    /// `--| text`, the text after `--|` with surrounding whitespace trimmed
+    DocComment(Sym),
+
+    // 插值字符串 `"a {x} b"` 被拆成
+    // StringStart, StringFragment, InterpolationStart, ..., InterpolationEnd, StringFragment, StringEnd
+    /// the opening quotes of a string with interpolations
+    StringStart,
+    /// text between interpolations, with escapes decoded
+    StringFragment(Sym),
+    /// `{` starting an interpolation, the tokens of the expression follow
+    InterpolationStart,
+    /// `}` ending an interpolation
+    InterpolationEnd,
+    /// the closing quotes of a string with interpolations
+    StringEnd,
+
+    // 由 layout pass 插入，没有对应的源码，span 为空
+    /// the start of a block opened by layout, e.g. after `let`
+    VirtualOpen,
+    /// separates the items of a block written on several lines
+    VirtualSemi,
+    /// the end of a block opened by layout
+    VirtualClose,
+
+    /// 无法识别的输入，对应的 diagnostic 已经被 lexer 记录
+    Error,
 
     // 从 pest Pair 中提取的实际 Tokens
     #[deprecated]
@@ -25,3 +53,32 @@ pub enum Token {
     // 占位符，用于处理所有我们不关心的 pest Tokens
     Other(Rule),
 }
+
+/// how a token is named in diagnostics, e.g. "expected `)`, found end of file"
+impl std::fmt::Display for Token {
+    #[allow(deprecated)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Indent | Token::Dedent => write!(f, "indentation"),
+            Token::Newline => write!(f, "line break"),
+            Token::EOF => write!(f, "end of file"),
+            Token::Identifier(_) => write!(f, "identifier"),
+            Token::SymbolicIdentifier(_) => write!(f, "operator"),
+            Token::Literal(_) => write!(f, "literal"),
+            Token::Operator(op) => write!(f, "`{}`", op.as_str()),
+            Token::Keyword(keyword) => write!(f, "`{}`", keyword.as_str()),
+            Token::Delimiter(delimiter) => write!(f, "`{}`", delimiter.as_str()),
+            Token::DocComment(_) => write!(f, "doc comment"),
+            Token::StringStart | Token::StringFragment(_) | Token::StringEnd => {
+                write!(f, "string literal")
+            }
+            Token::InterpolationStart => write!(f, "start of interpolation"),
+            Token::InterpolationEnd => write!(f, "end of interpolation"),
+            Token::VirtualOpen => write!(f, "start of block"),
+            Token::VirtualSemi => write!(f, "end of line"),
+            Token::VirtualClose => write!(f, "end of block"),
+            Token::Error => write!(f, "invalid token"),
+            Token::Statement(_) | Token::Other(_) => write!(f, "{self:?}"),
+        }
+    }
+}
@@ -1 +1 @@
-pub type UntypedAST = anon_ast::definition::Definition<()>;
+pub type UntypedAST = anon_ast::definition::Definition<anon_core::span::Span>;
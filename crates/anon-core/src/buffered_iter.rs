@@ -1,7 +1,6 @@
 //! This mod provides two "put-back-able" iterators: SingleBufferIter and MultiBufferIter
 
 /// A buffer iterator with single buffer, allows to put back one item.
-
 pub struct SingleBufferIter<T: Iterator> {
     inner: T,
     buffer: Option<T::Item>,
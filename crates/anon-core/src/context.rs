@@ -1,5 +1,20 @@
 /// puts Val in the context of Ctx
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Contextual<Ctx, Val> {
     pub context: Ctx,
     pub value: Val,
 }
+
+impl<Ctx, Val> Contextual<Ctx, Val> {
+    pub fn new(context: Ctx, value: Val) -> Self {
+        Self { context, value }
+    }
+
+    /// maps the value while keeping the context
+    pub fn map<U>(self, f: impl FnOnce(Val) -> U) -> Contextual<Ctx, U> {
+        Contextual {
+            context: self.context,
+            value: f(self.value),
+        }
+    }
+}
@@ -99,5 +99,6 @@ impl std::error::Error for SimpleDiagnostic {}
 
 #[derive(Debug)]
 pub struct DiagnosticCollector {
+    #[allow(dead_code)]
     bag: Vec<Box<dyn Diagnostic>>,
 }
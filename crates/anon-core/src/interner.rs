@@ -1,5 +1,4 @@
 use core::panic;
-use std::u32;
 
 use string_interner::{StringInterner, symbol::SymbolU32};
 
@@ -16,46 +16,41 @@ impl LineMap {
         let char_indices = source.char_indices();
         let mut buffered_iter = MultiBufferIter::new(char_indices);
 
-        loop {
-            if let Some((_, c)) = buffered_iter.next() {
-                // 找到换行符 LF
-                if c == '\n' {
-                    // 换行符之后还有字符，说明下一个字符是行首
+        while let Some((_, c)) = buffered_iter.next() {
+            // 找到换行符 LF
+            if c == '\n' {
+                // 换行符之后还有字符，说明下一个字符是行首
+                if let Some((next_idx, next_c)) = buffered_iter.next() {
+                    buffered_iter.put_back((next_idx, next_c));
+                    line_starts.push(next_idx);
+                }
+                // 如果换行符之后没有字符也不用管了，字符流就结束了
+                else {
+                    break;
+                }
+            }
+            // 可能是换行符 CRLF
+            else if c == '\r' {
+                //　之后是\n
+                if let Some((_, '\n')) = buffered_iter.next() {
+                    // CRLF之后还有字符，说明下一个字符是行首
+                    if let Some((next_next_idx, next_next_c)) = buffered_iter.next() {
+                        buffered_iter.put_back((next_next_idx, next_next_c));
+                        line_starts.push(next_next_idx);
+                    } else {
+                        break;
+                    }
+                }
+                // \r后面不是\n或者没有后续字符，\r单独作为换行符处理
+                else {
+                    // \r之后的字符是行首
                     if let Some((next_idx, next_c)) = buffered_iter.next() {
                         buffered_iter.put_back((next_idx, next_c));
                         line_starts.push(next_idx);
-                    }
-                    // 如果换行符之后没有字符也不用管了，字符流就结束了
-                    else {
+                    } else {
                         break;
                     }
                 }
-                // 可能是换行符 CRLF
-                else if c == '\r' {
-                    //　之后是\n
-                    if let Some((_, '\n')) = buffered_iter.next() {
-                        // CRLF之后还有字符，说明下一个字符是行首
-                        if let Some((next_next_idx, next_next_c)) = buffered_iter.next()
-                        {
-                            buffered_iter.put_back((next_next_idx, next_next_c));
-                            line_starts.push(next_next_idx);
-                        } else {
-                            break;
-                        }
-                    }
-                    // \r后面不是\n或者没有后续字符，\r单独作为换行符处理
-                    else {
-                        // \r之后的字符是行首
-                        if let Some((next_idx, next_c)) = buffered_iter.next() {
-                            buffered_iter.put_back((next_idx, next_c));
-                            line_starts.push(next_idx);
-                        } else {
-                            break;
-                        }
-                    }
-                }
-            } else {
-                break;
             }
         }
 
@@ -80,6 +75,10 @@ impl LineMap {
     pub fn len(&self) -> usize {
         self.len
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 }
 
 #[cfg(test)]
@@ -100,7 +99,7 @@ mod test {
             .map(|&x| line_map.index_to_location(x as u32))
             .collect();
 
-        let expected: Vec<_> = vec![
+        let expected: Vec<_> = [
             (0u32, 0u32),
             (1u32, 0u32),
             (2u32, 0u32),
@@ -130,7 +129,7 @@ mod test {
             .map(|&x| line_map.index_to_location(x as u32))
             .collect();
 
-        let expected: Vec<_> = vec![
+        let expected: Vec<_> = [
             (0u32, 0u32),
             (1u32, 0u32),
             (2u32, 0u32),
@@ -1,11 +1,11 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::context::Contextual;
 
 pub type SourceIdIndex = u32;
 
 /// Source ID to identify a source in the source map
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct SourceId(SourceIdIndex);
 
 pub type WithID<Val> = Contextual<SourceId, Val>;
@@ -54,4 +54,12 @@ impl SourceMap {
     pub fn get_content(&self, source_id: SourceId) -> &str {
         &self.get(source_id).content
     }
+
+    /// the source registered with the file name `file_name`
+    pub fn find(&self, file_name: &Path) -> Option<SourceId> {
+        self.sources
+            .iter()
+            .position(|source| source.file_name == file_name)
+            .map(|idx| SourceId(idx as u32))
+    }
 }
@@ -11,6 +11,9 @@ pub struct Span {
 }
 
 impl Span {
+    /// # Safety
+    ///
+    /// `start` must not be greater than `end`
     pub unsafe fn new_unchecked(
         start: impl Into<SpanIndex>,
         end: impl Into<SpanIndex>,
@@ -27,6 +30,28 @@ impl Span {
         (start <= end).then_some(Self { start, end })
     }
 
+    /// an empty span located at `at`, used by synthetic tokens
+    pub fn empty(at: impl Into<SpanIndex>) -> Self {
+        let at = at.into();
+        Self { start: at, end: at }
+    }
+
+    /// the smallest span covering both `self` and `other`
+    pub fn to(self, other: Self) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    pub fn len(self) -> SpanIndex {
+        self.end - self.start
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+
     pub fn start(self) -> SpanIndex {
         self.start
     }
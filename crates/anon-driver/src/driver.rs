@@ -3,10 +3,7 @@ use std::{
     rc::Rc,
 };
 
-use anon_core::{
-    interner::Interner,
-    source::{self, SourceMap},
-};
+use anon_core::{interner::Interner, source::SourceMap};
 
 pub trait Driver {
     fn interner(&self) -> Ref<'_, Interner>;
@@ -21,6 +18,12 @@ pub struct DebugDriver {
     source_map: SourceMap,
 }
 
+impl Default for DebugDriver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DebugDriver {
     pub fn new() -> Self {
         Self {
@@ -49,17 +52,18 @@ impl Driver for DebugDriver {
 
 #[cfg(test)]
 mod test {
-    use anon_compiler::{Lexer, line_tokenizer::PestParser};
+    use anon_compiler::{Lexer, token::Token};
 
-    use super::*;
     use crate::driver::DebugDriver;
 
     #[test]
     fn test_debug_driver() {
-        let mut debug_driver = DebugDriver::new();
+        let debug_driver = DebugDriver::new();
         let str = "";
 
-        let mut lexer = Lexer::new(str, 4, debug_driver.interner.clone());
+        let lexer = Lexer::new(str, 4, debug_driver.interner.clone());
+        let tokens: Vec<_> = lexer.map(|t| t.value).collect();
 
+        assert_eq!(vec![Token::EOF], tokens);
     }
 }